@@ -0,0 +1,27 @@
+//! `cargo bench` times adding, matching and migrating on synthetic databases of
+//! each size in [`bench::SIZES`], like `jumpjump bench --synthetic`.
+//!
+//! It reports the fastest and mean of a number of runs, without statistics or
+//! comparison with earlier runs.
+
+use jumpjump::bench;
+
+const RUNS: u32 = 10;
+
+fn main() -> Result<(), jumpjump::JumpError> {
+    // `cargo bench` passes --bench, `cargo test --all-targets` only checks that this runs.
+    if !std::env::args().any(|arg| arg == "--bench") {
+        return Ok(());
+    }
+    for &size in bench::SIZES.iter() {
+        for t in bench::synthetic(size, RUNS)? {
+            println!(
+                "{:<32}  min {:>10.3} ms  mean {:>10.3} ms",
+                t.name,
+                t.min.as_secs_f64() * 1000.0,
+                t.mean.as_secs_f64() * 1000.0
+            );
+        }
+    }
+    Ok(())
+}
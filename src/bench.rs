@@ -0,0 +1,140 @@
+//! Timings of database operations, so that performance issues can be reported with
+//! numbers from the user's own database and changes measured on synthetic ones.
+
+use crate::database::migrate;
+use crate::{Database, JumpError};
+use rusqlite::{Connection, NO_PARAMS};
+use std::time::{Duration, Instant};
+
+/// Entry counts of the synthetic databases.
+pub const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// Schema version that synthetic databases are migrated from, the first with access times.
+const LEGACY_VERSION: usize = 3;
+
+/// How long an operation took over several runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    pub name: String,
+    pub runs: u32,
+    /// The fastest run, the one least disturbed by other work.
+    pub min: Duration,
+    pub mean: Duration,
+}
+
+/// Times `runs` runs of `f`, which returns how long the measured part of a run took.
+fn measure<F>(name: String, runs: u32, mut f: F) -> Result<Timing, JumpError>
+where
+    F: FnMut(u32) -> Result<Duration, JumpError>,
+{
+    let runs = runs.max(1);
+    let mut min = Duration::MAX;
+    let mut total = Duration::ZERO;
+    for run in 0..runs {
+        let elapsed = f(run)?;
+        min = min.min(elapsed);
+        total += elapsed;
+    }
+    Ok(Timing {
+        name,
+        runs,
+        min,
+        mean: total / runs,
+    })
+}
+
+/// Times `runs` whole runs of `f`.
+fn time<F>(name: String, runs: u32, mut f: F) -> Result<Timing, JumpError>
+where
+    F: FnMut(u32) -> Result<(), JumpError>,
+{
+    measure(name, runs, |run| {
+        let start = Instant::now();
+        f(run)?;
+        Ok(start.elapsed())
+    })
+}
+
+/// Times reading `db` without changing it: listing every entry and matching each of
+/// `patterns`.
+pub fn queries(db: &Database, patterns: &[String], runs: u32) -> Result<Vec<Timing>, JumpError> {
+    let mut timings = vec![time("get_entries".to_string(), runs, |_| {
+        db.get_entries().map(drop)
+    })?];
+    for pattern in patterns {
+        let name = format!("get_matching_locations {}", pattern);
+        timings.push(time(name, runs, |_| {
+            db.get_matching_locations(pattern.split_whitespace())
+                .map(drop)
+        })?);
+    }
+    Ok(timings)
+}
+
+/// Times adding, matching and migrating on in-memory databases of `size` entries.
+pub fn synthetic(size: usize, runs: u32) -> Result<Vec<Timing>, JumpError> {
+    let mut db = Database::new(Connection::open_in_memory()?)?;
+    db.set_aging(None);
+    db.add_locations(locations(size))?;
+
+    let add = time(format!("add_location {}", size), runs, |run| {
+        db.add_location(format!("/home/me/new/{}", run))
+    })?;
+    let get = time(format!("get_matching_locations {}", size), runs, |_| {
+        db.get_matching_locations(&["src", "7"]).map(drop)
+    })?;
+    let migrations = measure(format!("migrations {}", size), runs, |_| {
+        let connection = Connection::open_in_memory()?;
+        seed_legacy(&connection, size)?;
+        let start = Instant::now();
+        Database::new(connection)?;
+        Ok(start.elapsed())
+    })?;
+    Ok(vec![add, get, migrations])
+}
+
+/// Locations spread over a few projects like a real database, all distinct.
+fn locations(size: usize) -> impl Iterator<Item = String> {
+    (0..size).map(|i| format!("/home/me/src/project{}/dir{}", i % 100, i))
+}
+
+/// Fills `connection` with `size` entries in the oldest schema that can be migrated.
+fn seed_legacy(connection: &Connection, size: usize) -> Result<(), JumpError> {
+    migrate(connection, LEGACY_VERSION)?;
+    connection.execute_batch("begin transaction")?;
+    {
+        let mut stmt = connection.prepare(
+            "insert into jump_location(location, rank, lastAccess) \
+             values(?, ?, strftime('%Y-%m-%d %H:%M:%f', 'now', 'localtime'))",
+        )?;
+        for (i, location) in locations(size).enumerate() {
+            stmt.execute(rusqlite::params![location, (i % 10 + 1) as i64])?;
+        }
+    }
+    connection.execute("commit", NO_PARAMS)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_synthetic_databases() {
+        let timings = synthetic(100, 2).unwrap();
+        let names = timings.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "add_location 100",
+                "get_matching_locations 100",
+                "migrations 100"
+            ]
+        );
+        assert!(timings.iter().all(|t| t.runs == 2 && t.min <= t.mean));
+
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let timings = queries(&db, &["src lib".to_string()], 1).unwrap();
+        assert_eq!(timings[1].name, "get_matching_locations src lib");
+    }
+}
@@ -0,0 +1,474 @@
+//! Loading of the optional `config.toml`, which understands a small subset of TOML.
+
+use crate::{Case, JumpError, Weights};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A TOML value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+const KEYS: &[&str] = &[
+    "db",
+    "format",
+    "exclude",
+    "case",
+    "resolve_symlinks",
+    "per_host",
+    "vcs_roots",
+    "aging.enabled",
+    "aging.factor",
+    "aging.interval",
+    "aging.max_rank",
+    "aging.max_entries",
+    "weights.hour",
+    "weights.day",
+    "weights.week",
+    "weights.older",
+    "weights.dwell",
+    "weights.match",
+    "hooks.get",
+    "history.enabled",
+    "history.max_entries",
+    "maintenance.probability",
+];
+
+/// Options read from the config file, `None` where the default applies.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub db: Option<PathBuf>,
+    /// Output format name, checked by the CLI.
+    pub format: Option<String>,
+    pub aging: bool,
+    pub aging_factor: Option<f64>,
+    pub aging_interval: Option<u32>,
+    /// Total rank at which ranks are aged right away.
+    pub aging_max_rank: Option<f64>,
+    /// Number of entries beyond which the lowest scoring are evicted.
+    pub aging_max_entries: Option<usize>,
+    /// Globs for locations that are never tracked.
+    pub exclude: Vec<String>,
+    pub case: Case,
+    /// Store physical paths with symlinks resolved rather than logical ones.
+    pub resolve_symlinks: bool,
+    /// Track locations separately for each host sharing the database.
+    pub per_host: bool,
+    /// Also record the repository root when adding a location inside one.
+    pub vcs_roots: bool,
+    pub weights: Weights,
+    /// Command run after `get` chooses a location, see the `hooks.get` key.
+    pub get_hook: Option<String>,
+    pub history: bool,
+    /// Number of jumps kept in the history.
+    pub history_max_entries: Option<usize>,
+    /// Chance that an add also drops a sample of deleted directories.
+    pub maintenance_probability: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db: None,
+            format: None,
+            aging: true,
+            aging_factor: None,
+            aging_interval: None,
+            aging_max_rank: None,
+            aging_max_entries: None,
+            exclude: Vec::new(),
+            case: Case::default(),
+            resolve_symlinks: false,
+            per_host: false,
+            vcs_roots: false,
+            weights: Weights::default(),
+            get_hook: None,
+            history: true,
+            history_max_entries: None,
+            maintenance_probability: None,
+        }
+    }
+}
+
+/// Returns `$JUMPJUMP_CONFIG` or `$XDG_CONFIG_HOME/jumpjump/config.toml`.
+pub fn get_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("JUMPJUMP_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+        _ => dirs::home_dir()?.join(".config"),
+    };
+    Some(config_dir.join("jumpjump").join("config.toml"))
+}
+
+/// Loads the config at `path`, a missing file gives the defaults.
+pub fn load(path: &Path) -> Result<Config, JumpError> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => return Err(JumpError::Io(err)),
+    };
+    from_values(&parse(&text)?).map_err(|err| parse_error!("{}: {}", path.display(), err))
+}
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Expands a leading `~` to the home directory and `$NAME` or `${NAME}` to the
+/// value of that environment variable, leaving unset variables and any other `$`,
+/// such as a regex anchor, as they are.
+pub fn expand(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    if rest == "~" || rest.starts_with("~/") || (cfg!(windows) && rest.starts_with("~\\")) {
+        if let Some(home) = dirs::home_dir() {
+            expanded.push_str(&home.to_string_lossy());
+            rest = &rest[1..];
+        }
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        let starts_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        match std::env::var(name) {
+            Ok(value) if starts_name => {
+                expanded.push_str(&value);
+                rest = &after[len..];
+            }
+            _ => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn from_values(values: &BTreeMap<String, Value>) -> Result<Config, JumpError> {
+    let mut config = Config::default();
+    for (key, value) in values {
+        match (key.as_str(), value) {
+            ("db", Value::String(path)) => config.db = Some(expand_home(path)),
+            ("format", Value::String(format)) => config.format = Some(format.clone()),
+            ("exclude", Value::Array(globs)) => {
+                config.exclude = globs
+                    .iter()
+                    .map(|glob| match glob {
+                        Value::String(glob) => Ok(expand_home(glob).to_string_lossy().into_owned()),
+                        _ => Err(parse_error!("Invalid value for exclude")),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            ("case", Value::String(name)) if Case::from_name(name).is_some() => {
+                config.case = Case::from_name(name).unwrap()
+            }
+            ("resolve_symlinks", Value::Bool(resolve)) => config.resolve_symlinks = *resolve,
+            ("per_host", Value::Bool(per_host)) => config.per_host = *per_host,
+            ("vcs_roots", Value::Bool(vcs_roots)) => config.vcs_roots = *vcs_roots,
+            ("aging.enabled", Value::Bool(enabled)) => config.aging = *enabled,
+            ("aging.factor", Value::Float(factor)) => config.aging_factor = Some(*factor),
+            ("aging.factor", Value::Integer(factor)) => config.aging_factor = Some(*factor as f64),
+            ("aging.interval", Value::Integer(interval)) if *interval >= 0 => {
+                config.aging_interval = Some(*interval as u32)
+            }
+            ("aging.max_rank", Value::Float(max)) => config.aging_max_rank = Some(*max),
+            ("aging.max_rank", Value::Integer(max)) => config.aging_max_rank = Some(*max as f64),
+            ("aging.max_entries", Value::Integer(max)) if *max >= 0 => {
+                config.aging_max_entries = Some(*max as usize)
+            }
+            ("hooks.get", Value::String(command)) => config.get_hook = Some(command.clone()),
+            ("history.enabled", Value::Bool(enabled)) => config.history = *enabled,
+            ("history.max_entries", Value::Integer(max)) if *max >= 0 => {
+                config.history_max_entries = Some(*max as usize)
+            }
+            ("maintenance.probability", Value::Float(p)) if (0.0..=1.0).contains(p) => {
+                config.maintenance_probability = Some(*p)
+            }
+            ("maintenance.probability", Value::Integer(p)) if (0..=1).contains(p) => {
+                config.maintenance_probability = Some(*p as f64)
+            }
+            (key, value) if key.starts_with("weights.") => {
+                let weight =
+                    weight(value).ok_or_else(|| parse_error!("Invalid value for {}", key))?;
+                match key {
+                    "weights.hour" => config.weights.hour = weight,
+                    "weights.day" => config.weights.day = weight,
+                    "weights.week" => config.weights.week = weight,
+                    "weights.older" => config.weights.older = weight,
+                    "weights.dwell" => config.weights.dwell = weight,
+                    "weights.match" => config.weights.matching = weight,
+                    _ => eprintln!("Ignoring unknown config option {}", key),
+                }
+            }
+            (key, _) if KEYS.contains(&key) => {
+                return Err(parse_error!("Invalid value for {}", key))
+            }
+            _ => eprintln!("Ignoring unknown config option {}", key),
+        }
+    }
+    Ok(config)
+}
+
+/// A recency weight, which must not be negative.
+fn weight(value: &Value) -> Option<f64> {
+    match value {
+        Value::Float(weight) if *weight >= 0.0 && weight.is_finite() => Some(*weight),
+        Value::Integer(weight) if *weight >= 0 => Some(*weight as f64),
+        _ => None,
+    }
+}
+
+/// Parses TOML into values keyed by their dotted `table.key` name.
+pub fn parse(text: &str) -> Result<BTreeMap<String, Value>, JumpError> {
+    let mut values = BTreeMap::new();
+    let mut table = String::new();
+    let mut lines = text.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(parse_error!("Invalid table header on line {}", n + 1));
+            }
+            table = line[1..line.len() - 1].trim().to_string();
+            continue;
+        }
+
+        let eq = line
+            .find('=')
+            .ok_or_else(|| parse_error!("Expected key = value on line {}", n + 1))?;
+        let key = line[..eq].trim().trim_matches('"');
+        let mut raw = line[eq + 1..].trim().to_string();
+        // Arrays may span lines until the closing bracket.
+        if raw.starts_with('[') {
+            while !raw.ends_with(']') {
+                match lines.next() {
+                    Some((_, next)) => {
+                        raw.push(' ');
+                        raw.push_str(strip_comment(next).trim());
+                    }
+                    None => return Err(parse_error!("Unterminated array on line {}", n + 1)),
+                }
+            }
+        }
+
+        let value = parse_value(&raw).map_err(|err| parse_error!("{} on line {}", err, n + 1))?;
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        };
+        values.insert(key, value);
+    }
+    Ok(values)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    line
+}
+
+fn parse_value(raw: &str) -> Result<Value, JumpError> {
+    if let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        return split_array(inner)
+            .into_iter()
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+    if raw.len() >= 2 && raw.starts_with('\'') && raw.ends_with('\'') {
+        return Ok(Value::String(raw[1..raw.len() - 1].to_string()));
+    }
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        return unescape(&raw[1..raw.len() - 1]).map(Value::String);
+    }
+    match raw {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => (),
+    }
+    let number = raw.replace('_', "");
+    if let Ok(i) = number.parse::<i64>() {
+        return Ok(Value::Integer(i));
+    }
+    if let Ok(f) = number.parse::<f64>() {
+        return Ok(Value::Float(f));
+    }
+    Err(parse_error!("Invalid value {}", raw))
+}
+
+fn split_array(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match (quote, c) {
+            (None, ',') => {
+                items.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => (),
+        }
+    }
+    items.push(inner[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn unescape(s: &str) -> Result<String, JumpError> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('"') => unescaped.push('"'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => return Err(parse_error!("Unsupported escape \\{}", c)),
+            None => return Err(parse_error!("Unterminated escape")),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_toml_subset() {
+        let text = r#"
+            # jumpjump config
+            db = "/data/jump.db" # trailing comment
+
+            [aging]
+            enabled = true
+            factor = 0.75
+            interval = 1_000
+            names = [
+                "a#b",
+                'c:\d',
+            ]
+        "#;
+
+        let values = parse(text).unwrap();
+
+        assert_eq!(values["db"], Value::String("/data/jump.db".to_string()));
+        assert_eq!(values["aging.enabled"], Value::Bool(true));
+        assert_eq!(values["aging.factor"], Value::Float(0.75));
+        assert_eq!(values["aging.interval"], Value::Integer(1000));
+        assert_eq!(
+            values["aging.names"],
+            Value::Array(vec![
+                Value::String("a#b".to_string()),
+                Value::String("c:\\d".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn expands_home_and_variables() {
+        std::env::set_var("JUMPJUMP_TEST_EXPAND", "/srv");
+        let home = dirs::home_dir().unwrap().to_string_lossy().into_owned();
+
+        assert_eq!(expand("~/src"), format!("{}/src", home));
+        assert_eq!(expand("~"), home);
+        assert_eq!(expand("~user/src"), "~user/src");
+        assert_eq!(expand("$JUMPJUMP_TEST_EXPAND/app"), "/srv/app");
+        assert_eq!(expand("${JUMPJUMP_TEST_EXPAND}app"), "/srvapp");
+        assert_eq!(expand("src$"), "src$");
+        assert_eq!(expand("$JUMPJUMP_TEST_UNSET/x"), "$JUMPJUMP_TEST_UNSET/x");
+        assert_eq!(expand("${oops"), "${oops");
+    }
+
+    #[test]
+    fn builds_config() {
+        let text = "db = '/x.db'\n[aging]\nfactor = 1\ninterval = 5\nmax_entries = 500\n";
+        let values = parse(text).unwrap();
+
+        let config = from_values(&values).unwrap();
+
+        assert_eq!(config.db, Some(PathBuf::from("/x.db")));
+        assert_eq!(config.aging_factor, Some(1.0));
+        assert_eq!(config.aging_interval, Some(5));
+        assert_eq!(config.aging_max_entries, Some(500));
+        assert_eq!(config.aging_max_rank, None);
+        assert!(from_values(&parse("db = 1").unwrap()).is_err());
+        assert_eq!(
+            from_values(&parse("exclude = ['/tmp', 'node_modules']").unwrap())
+                .unwrap()
+                .exclude,
+            vec!["/tmp", "node_modules"]
+        );
+        assert!(from_values(&parse("exclude = [1]").unwrap()).is_err());
+        assert_eq!(
+            from_values(&parse("case = 'sensitive'").unwrap())
+                .unwrap()
+                .case,
+            Case::Sensitive
+        );
+        assert!(from_values(&parse("case = 'upper'").unwrap()).is_err());
+        assert_eq!(
+            from_values(&parse("maintenance.probability = 0.05").unwrap())
+                .unwrap()
+                .maintenance_probability,
+            Some(0.05)
+        );
+        assert!(from_values(&parse("maintenance.probability = 2").unwrap()).is_err());
+        let weights = from_values(&parse("[weights]\nhour = 1\nolder = 0.5\nmatch = 2").unwrap())
+            .unwrap()
+            .weights;
+        assert_eq!(weights.hour, 1.0);
+        assert_eq!(weights.day, 2.0);
+        assert_eq!(weights.older, 0.5);
+        assert_eq!(weights.matching, 2.0);
+        assert!(from_values(&parse("[weights]\nweek = -1").unwrap()).is_err());
+        assert_eq!(
+            from_values(&parse("[hooks]\nget = 'tmux rename-window {}'").unwrap())
+                .unwrap()
+                .get_hook,
+            Some("tmux rename-window {}".to_string())
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! `jumpjump daemon` keeps the database open and answers simple `add` and `get`
+//! requests over a Unix domain socket, so that prompt hooks do not pay for opening
+//! the database on every prompt.
+//!
+//! A request is NUL separated fields, `add`, a path and optionally the shell's
+//! session or `get`, the client's working directory and patterns, ended by the
+//! client shutting down its write half. `get-skip-cwd` is `get` leaving out the
+//! working directory itself. The reply is `ok`, `none` or `error`, a NUL, then the
+//! path or message.
+//!
+//! With `--watch` it also polls for deleted directories and drops their entries.
+//! Subscribing to filesystem events instead would need a watch per entry. The cost
+//! is a `stat` of every entry each interval, and a deletion goes unnoticed until
+//! the next sweep.
+
+use anyhow::{anyhow, Error};
+use jumpjump::{canonicalize_path, Case, Database, Query};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The socket used when none is given, next to the database.
+pub fn default_socket_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("sock")
+}
+
+/// Every `interval`, checks every entry and drops those whose directories were
+/// deleted, on a database of its own from `open` as connections cannot be shared
+/// between threads.
+pub fn watch<F>(interval: Duration, open: F)
+where
+    F: FnOnce() -> Result<Database, Error> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        let db = match open() {
+            Ok(db) => db,
+            Err(err) => return eprintln!("Could not watch for deleted directories: {}", err),
+        };
+        loop {
+            std::thread::sleep(interval);
+            match drop_deleted(&db) {
+                Ok(0) => (),
+                Ok(dropped) => eprintln!("Dropped {} deleted locations", dropped),
+                Err(err) => eprintln!("Could not drop deleted locations: {}", err),
+            }
+        }
+    });
+}
+
+/// Removes the entries whose directories are gone. One whose parent is gone too is
+/// kept, as it may be on a drive that is not mounted.
+fn drop_deleted(db: &Database) -> Result<usize, Error> {
+    let deleted = db
+        .get_entries()?
+        .into_iter()
+        .filter(|e| {
+            let path = e.path();
+            !path.exists() && path.parent().is_some_and(Path::exists)
+        })
+        .map(|e| e.location)
+        .collect::<Vec<_>>();
+    if deleted.is_empty() {
+        return Ok(0);
+    }
+    Ok(db.remove_locations(&deleted)?)
+}
+
+/// A reply from the daemon, `request` returns `None` when no daemon is listening.
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Ok(OsString),
+    NoMatch,
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::io::{Read, Write};
+    use std::net::Shutdown;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    pub fn serve(db: &Database, socket: &Path, case: Case) -> Result<(), Error> {
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                return Err(anyhow!(
+                    "A daemon is already listening on {}",
+                    socket.display()
+                ));
+            }
+            std::fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+        eprintln!("Listening on {}", socket.display());
+
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(Error::from)
+                .and_then(|mut stream| handle(db, case, &mut stream));
+            if let Err(err) = result {
+                eprintln!("Request failed: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(db: &Database, case: Case, stream: &mut UnixStream) -> Result<(), Error> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut request = Vec::new();
+        stream.read_to_end(&mut request)?;
+        let reply = match answer(db, case, &request) {
+            Ok(Reply::Ok(path)) => [b"ok\0", path.as_bytes()].concat(),
+            Ok(Reply::NoMatch) => b"none\0".to_vec(),
+            Err(err) => format!("error\0{}", err).into_bytes(),
+        };
+        stream.write_all(&reply)?;
+        Ok(())
+    }
+
+    pub(super) fn answer(db: &Database, case: Case, request: &[u8]) -> Result<Reply, Error> {
+        let mut fields = request.split(|b| *b == 0).map(OsStr::from_bytes);
+        match fields.next().and_then(OsStr::to_str) {
+            Some("add") => {
+                let path = fields.next().ok_or_else(|| anyhow!("Missing path"))?;
+                db.add_path(path)?;
+                if let Some(session) = fields.next() {
+                    db.record_visit(session.to_string_lossy(), canonicalize_path(path)?)?;
+                }
+                Ok(Reply::Ok(path.to_os_string()))
+            }
+            Some(verb @ "get") | Some(verb @ "get-skip-cwd") => {
+                let cwd = fields.next().ok_or_else(|| anyhow!("Missing directory"))?;
+                let patterns = fields.map(|p| p.to_string_lossy()).collect::<Vec<_>>();
+                let typed = itertools::join(&patterns, " ");
+                let query = Query::new(patterns)
+                    .with_case(case)
+                    .with_cwd(Some(cwd.to_string_lossy()))
+                    .with_skip_cwd(verb == "get-skip-cwd")
+                    .with_require_exists(true);
+                match crate::matching_entries(db, &query)?.into_iter().next() {
+                    Some(entry) => {
+                        if let Err(err) = db.record_jump(typed, &entry.location) {
+                            eprintln!("Could not record jump: {}", err);
+                        }
+                        Ok(Reply::Ok(entry.path().into_os_string()))
+                    }
+                    None => Ok(Reply::NoMatch),
+                }
+            }
+            _ => Err(anyhow!("Unknown request")),
+        }
+    }
+
+    pub fn request(socket: &Path, fields: &[&OsStr]) -> Result<Option<Reply>, Error> {
+        let mut stream = match UnixStream::connect(socket) {
+            Ok(stream) => stream,
+            Err(_) => return Ok(None),
+        };
+        let request = fields
+            .iter()
+            .map(|f| f.as_bytes())
+            .collect::<Vec<_>>()
+            .join(&0);
+        stream.write_all(&request)?;
+        stream.shutdown(Shutdown::Write)?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        let mut parts = reply.splitn(2, |b| *b == 0);
+        match (parts.next(), parts.next()) {
+            (Some(b"ok"), Some(path)) => Ok(Some(Reply::Ok(OsString::from_vec(path.to_vec())))),
+            (Some(b"none"), _) => Ok(Some(Reply::NoMatch)),
+            (Some(b"error"), Some(message)) => Err(anyhow!("{}", String::from_utf8_lossy(message))),
+            _ => Err(anyhow!("Invalid reply from daemon")),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{request, serve};
+
+#[cfg(not(unix))]
+pub fn serve(_db: &Database, _socket: &Path, _case: Case) -> Result<(), Error> {
+    Err(anyhow!("The daemon needs Unix domain sockets"))
+}
+
+#[cfg(not(unix))]
+pub fn request(_socket: &Path, _fields: &[&std::ffi::OsStr]) -> Result<Option<Reply>, Error> {
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn drops_deleted_directories() {
+        let dir = std::env::temp_dir().join(format!("jumpjump-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_path(&dir).unwrap();
+        db.add_location("/no/such/mount/dir").unwrap();
+        db.add_path(std::env::temp_dir()).unwrap();
+
+        let kept = drop_deleted(&db).unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+        let dropped = drop_deleted(&db).unwrap();
+
+        assert_eq!((kept, dropped), (0, 1));
+        assert_eq!(db.get_entries().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn answers_requests() {
+        let socket = std::env::temp_dir().join(format!("jumpjump-{}.sock", std::process::id()));
+        let server_socket = socket.clone();
+        std::thread::spawn(move || {
+            let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+            serve(&db, &server_socket, Case::Smart).unwrap();
+        });
+        while request(&socket, &[OsStr::new("get"), OsStr::new("/")])
+            .unwrap()
+            .is_none()
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let tmp = std::env::temp_dir();
+
+        let added = request(&socket, &[OsStr::new("add"), tmp.as_os_str()]);
+        let found = request(
+            &socket,
+            &[OsStr::new("get"), OsStr::new("/"), tmp.as_os_str()],
+        );
+        let missing = request(
+            &socket,
+            &[OsStr::new("get"), OsStr::new("/"), OsStr::new("zzz")],
+        );
+        let skipped = request(
+            &socket,
+            &[OsStr::new("get-skip-cwd"), tmp.as_os_str(), tmp.as_os_str()],
+        );
+        let invalid = request(&socket, &[OsStr::new("remove")]);
+        std::fs::remove_file(&socket).unwrap();
+
+        assert_eq!(
+            added.unwrap(),
+            Some(Reply::Ok(tmp.clone().into_os_string()))
+        );
+        assert_eq!(found.unwrap(), Some(Reply::Ok(tmp.into_os_string())));
+        assert_eq!(missing.unwrap(), Some(Reply::NoMatch));
+        assert_eq!(skipped.unwrap(), Some(Reply::NoMatch));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn records_jumps() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let tmp = std::env::temp_dir();
+        db.add_path(&tmp).unwrap();
+        let request = [b"get\0/\0".to_vec(), tmp.as_os_str().as_bytes().to_vec()].concat();
+
+        let reply = unix::answer(&db, Case::Smart, &request).unwrap();
+
+        assert_eq!(reply, Reply::Ok(tmp.clone().into_os_string()));
+        let history = db.get_history(None).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].query, tmp.to_string_lossy());
+    }
+}
@@ -0,0 +1,3371 @@
+use crate::import::ImportedLocation;
+use crate::log::{self, Level};
+use crate::query::{self, Query, Strategy};
+use crate::{location, JumpError};
+use regex::{Regex, RegexSet};
+use rusqlite::{Connection, OptionalExtension, ToSql, NO_PARAMS};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MIGRATIONS: [&str; 20] = [
+    "
+        begin transaction;
+
+        create table if not exists jump_location (id INTEGER PRIMARY KEY ASC, location STRING UNIQUE, rank INTEGER);
+        create index if not exists location_index on jump_location(location);
+
+        insert into migration_version(version) values (1);
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        drop table if exists temp_jump_location;
+        alter table jump_location rename to temp_jump_location;
+
+        create table jump_location (id INTEGER PRIMARY KEY ASC, location STRING UNIQUE COLLATE NOCASE, rank INTEGER);
+        create index if not exists location_index on jump_location(location);
+
+        insert or ignore into jump_location
+            select id, location, rank from temp_jump_location;
+
+        update migration_version set version = 2 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        alter table jump_location add column lastAccess TEXT;
+
+        update jump_location set lastAccess = strftime('%Y-%m-%d %H:%M:%f', 'now', 'localtime');
+
+        update migration_version set version = 3 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        drop table if exists temp_jump_location;
+        alter table jump_location rename to temp_jump_location;
+
+        create table jump_location (id INTEGER PRIMARY KEY ASC, location STRING UNIQUE COLLATE NOCASE, rank REAL, lastAccess TEXT);
+
+        insert or ignore into jump_location
+            select id, location, rank, lastAccess from temp_jump_location;
+
+        drop table temp_jump_location;
+        create index if not exists location_index on jump_location(location);
+
+        create table if not exists jump_counter (name STRING PRIMARY KEY, value INTEGER);
+        insert or ignore into jump_counter(name, value) values ('adds', 0);
+
+        update migration_version set version = 4 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        alter table jump_location add column pinned INTEGER NOT NULL DEFAULT 0;
+
+        update migration_version set version = 5 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_alias (name STRING PRIMARY KEY, location STRING);
+
+        update migration_version set version = 6 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_tag (location STRING COLLATE NOCASE, tag STRING, PRIMARY KEY (location, tag));
+        create index if not exists tag_index on jump_tag(tag);
+
+        create trigger if not exists jump_location_tags_delete after delete on jump_location
+        begin
+            delete from jump_tag where location = old.location;
+        end;
+
+        update migration_version set version = 7 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        alter table jump_location add column raw BLOB;
+
+        update migration_version set version = 8 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        alter table jump_location add column dwell REAL NOT NULL DEFAULT 0;
+        alter table jump_location add column lastLeave TEXT;
+
+        update migration_version set version = 9 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_history (id INTEGER PRIMARY KEY ASC, query TEXT, location TEXT, time TEXT);
+
+        update migration_version set version = 10 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_operation (id INTEGER PRIMARY KEY ASC, description TEXT, time TEXT, open INTEGER NOT NULL DEFAULT 0);
+        create table if not exists jump_undo (id INTEGER PRIMARY KEY ASC, operation INTEGER, statement TEXT);
+        create index if not exists undo_index on jump_undo(operation);
+
+        create trigger if not exists jump_location_undo_insert after insert on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'delete from jump_location where id = ' || new.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_update after update on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'update jump_location set location = ' || quote(old.location)
+                    || ', rank = ' || quote(old.rank) || ', lastAccess = ' || quote(old.lastAccess)
+                    || ', pinned = ' || quote(old.pinned) || ', raw = ' || quote(old.raw)
+                    || ', dwell = ' || quote(old.dwell) || ', lastLeave = ' || quote(old.lastLeave)
+                    || ' where id = ' || old.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_delete after delete on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave) values('
+                    || old.id || ', ' || quote(old.location) || ', ' || quote(old.rank)
+                    || ', ' || quote(old.lastAccess) || ', ' || quote(old.pinned) || ', ' || quote(old.raw)
+                    || ', ' || quote(old.dwell) || ', ' || quote(old.lastLeave) || ')'
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_tag_undo_delete after delete on jump_tag
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'insert or ignore into jump_tag(location, tag) values('
+                    || quote(old.location) || ', ' || quote(old.tag) || ')'
+                from jump_operation where open;
+        end;
+
+        update migration_version set version = 11 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        drop table if exists temp_jump_location;
+        alter table jump_location rename to temp_jump_location;
+
+        create table jump_location (id INTEGER PRIMARY KEY ASC, location STRING COLLATE NOCASE, rank REAL, lastAccess TEXT,
+            pinned INTEGER NOT NULL DEFAULT 0, raw BLOB, dwell REAL NOT NULL DEFAULT 0, lastLeave TEXT,
+            host TEXT NOT NULL DEFAULT '', UNIQUE (location, host));
+
+        insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave)
+            select id, location, rank, lastAccess, pinned, raw, dwell, lastLeave from temp_jump_location;
+
+        drop table temp_jump_location;
+        create index if not exists location_index on jump_location(location);
+
+        create trigger if not exists jump_location_tags_delete after delete on jump_location
+        begin
+            delete from jump_tag where location = old.location
+                and not exists (select 1 from jump_location where location = old.location);
+        end;
+
+        create trigger if not exists jump_location_undo_insert after insert on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'delete from jump_location where id = ' || new.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_update after update on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'update jump_location set location = ' || quote(old.location)
+                    || ', rank = ' || quote(old.rank) || ', lastAccess = ' || quote(old.lastAccess)
+                    || ', pinned = ' || quote(old.pinned) || ', raw = ' || quote(old.raw)
+                    || ', dwell = ' || quote(old.dwell) || ', lastLeave = ' || quote(old.lastLeave)
+                    || ', host = ' || quote(old.host) || ' where id = ' || old.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_delete after delete on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave, host) values('
+                    || old.id || ', ' || quote(old.location) || ', ' || quote(old.rank)
+                    || ', ' || quote(old.lastAccess) || ', ' || quote(old.pinned) || ', ' || quote(old.raw)
+                    || ', ' || quote(old.dwell) || ', ' || quote(old.lastLeave) || ', ' || quote(old.host) || ')'
+                from jump_operation where open;
+        end;
+
+        update migration_version set version = 12 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        update or ignore jump_tag set location = jump_nfc(location) where location != jump_nfc(location);
+        delete from jump_tag where location != jump_nfc(location);
+        update jump_alias set location = jump_nfc(location) where location != jump_nfc(location);
+        update jump_history set location = jump_nfc(location) where location != jump_nfc(location);
+
+        create temp table jump_decomposed as
+            select id, jump_nfc(location) as location, rank, lastAccess, pinned, raw, dwell, lastLeave, host,
+                cast(location as blob) as bytes
+            from jump_location where location != jump_nfc(location);
+
+        update jump_location set
+            rank = rank + (select sum(d.rank) from jump_decomposed d
+                where d.location = jump_location.location and d.host = jump_location.host),
+            dwell = dwell + (select sum(d.dwell) from jump_decomposed d
+                where d.location = jump_location.location and d.host = jump_location.host),
+            lastAccess = max(lastAccess, (select max(d.lastAccess) from jump_decomposed d
+                where d.location = jump_location.location and d.host = jump_location.host)),
+            pinned = max(pinned, (select max(d.pinned) from jump_decomposed d
+                where d.location = jump_location.location and d.host = jump_location.host))
+        where exists (select 1 from jump_decomposed d
+            where d.location = jump_location.location and d.host = jump_location.host);
+
+        delete from jump_location where id in (select id from jump_decomposed);
+        insert or ignore into jump_location(location, rank, lastAccess, pinned, raw, dwell, lastLeave, host)
+            select location, sum(rank), max(lastAccess), max(pinned), coalesce(max(raw), max(bytes)),
+                sum(dwell), max(lastLeave), host
+            from jump_decomposed group by location, host;
+
+        drop table jump_decomposed;
+
+        update migration_version set version = 13 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        alter table jump_location add column kind TEXT NOT NULL DEFAULT 'dir';
+
+        drop trigger if exists jump_location_undo_update;
+        create trigger jump_location_undo_update after update on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'update jump_location set location = ' || quote(old.location)
+                    || ', rank = ' || quote(old.rank) || ', lastAccess = ' || quote(old.lastAccess)
+                    || ', pinned = ' || quote(old.pinned) || ', raw = ' || quote(old.raw)
+                    || ', dwell = ' || quote(old.dwell) || ', lastLeave = ' || quote(old.lastLeave)
+                    || ', host = ' || quote(old.host) || ', kind = ' || quote(old.kind)
+                    || ' where id = ' || old.id
+                from jump_operation where open;
+        end;
+
+        drop trigger if exists jump_location_undo_delete;
+        create trigger jump_location_undo_delete after delete on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave, host, kind) values('
+                    || old.id || ', ' || quote(old.location) || ', ' || quote(old.rank)
+                    || ', ' || quote(old.lastAccess) || ', ' || quote(old.pinned) || ', ' || quote(old.raw)
+                    || ', ' || quote(old.dwell) || ', ' || quote(old.lastLeave) || ', ' || quote(old.host)
+                    || ', ' || quote(old.kind) || ')'
+                from jump_operation where open;
+        end;
+
+        update migration_version set version = 14 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        drop table if exists temp_jump_location;
+        alter table jump_location rename to temp_jump_location;
+
+        create table jump_location (id INTEGER PRIMARY KEY ASC, location STRING COLLATE NOCASE, rank REAL NOT NULL DEFAULT 0,
+            lastAccess INTEGER, pinned INTEGER NOT NULL DEFAULT 0, raw BLOB, dwell REAL NOT NULL DEFAULT 0, lastLeave INTEGER,
+            host TEXT NOT NULL DEFAULT '', kind TEXT NOT NULL DEFAULT 'dir', UNIQUE (location, host));
+
+        insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave, host, kind)
+            select id, location, cast(coalesce(rank, 0) as real),
+                cast(strftime('%s', lastAccess, 'utc') as integer), pinned, raw, dwell,
+                cast(strftime('%s', lastLeave, 'utc') as integer), host, kind
+            from temp_jump_location;
+
+        drop table temp_jump_location;
+        create index if not exists location_index on jump_location(location);
+
+        create trigger if not exists jump_location_tags_delete after delete on jump_location
+        begin
+            delete from jump_tag where location = old.location
+                and not exists (select 1 from jump_location where location = old.location);
+        end;
+
+        create trigger if not exists jump_location_undo_insert after insert on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'delete from jump_location where id = ' || new.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_update after update on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'update jump_location set location = ' || quote(old.location)
+                    || ', rank = ' || quote(old.rank) || ', lastAccess = ' || quote(old.lastAccess)
+                    || ', pinned = ' || quote(old.pinned) || ', raw = ' || quote(old.raw)
+                    || ', dwell = ' || quote(old.dwell) || ', lastLeave = ' || quote(old.lastLeave)
+                    || ', host = ' || quote(old.host) || ', kind = ' || quote(old.kind)
+                    || ' where id = ' || old.id
+                from jump_operation where open;
+        end;
+
+        create trigger if not exists jump_location_undo_delete after delete on jump_location
+        when exists (select 1 from jump_operation where open)
+        begin
+            insert into jump_undo(operation, statement)
+                select max(id), 'insert into jump_location(id, location, rank, lastAccess, pinned, raw, dwell, lastLeave, host, kind) values('
+                    || old.id || ', ' || quote(old.location) || ', ' || quote(old.rank)
+                    || ', ' || quote(old.lastAccess) || ', ' || quote(old.pinned) || ', ' || quote(old.raw)
+                    || ', ' || quote(old.dwell) || ', ' || quote(old.lastLeave) || ', ' || quote(old.host)
+                    || ', ' || quote(old.kind) || ')'
+                from jump_operation where open;
+        end;
+
+        update migration_version set version = 15 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        drop table if exists temp_jump_history;
+        alter table jump_history rename to temp_jump_history;
+
+        create table jump_history (id INTEGER PRIMARY KEY ASC, query TEXT, location TEXT, time INTEGER);
+
+        insert into jump_history(id, query, location, time)
+            select id, query, location, cast(strftime('%s', time, 'utc') as integer) from temp_jump_history;
+
+        drop table temp_jump_history;
+
+        update jump_operation set time = datetime(time, 'utc');
+
+        update migration_version set version = 16 where id = 1;
+
+        commit;
+    ",
+    // Once created rank_index, which no query can use because they all order by a
+    // score that depends on the time of the query. Kept so that version numbers
+    // stay the same for databases that ran it.
+    "
+        update migration_version set version = 17 where id = 1;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_session (id INTEGER PRIMARY KEY ASC, session TEXT NOT NULL, location TEXT NOT NULL, time INTEGER);
+        create index if not exists session_index on jump_session(session, id);
+
+        update migration_version set version = 18 where id = 1;
+
+        commit;
+    ",
+    "
+        begin transaction;
+
+        create table if not exists jump_stack (id INTEGER PRIMARY KEY ASC, session TEXT NOT NULL, location TEXT NOT NULL);
+        create index if not exists stack_index on jump_stack(session, id);
+
+        update migration_version set version = 19 where id = 1;
+
+        commit;
+    ",
+    // Drops the rank_index of databases that ran migration 17 before it was emptied.
+    "
+        begin transaction;
+
+        drop index if exists rank_index;
+
+        update migration_version set version = 20 where id = 1;
+
+        commit;
+    ",
+];
+
+/// How much recency counts towards a location's score, as multipliers of its rank
+/// by the time since it was last accessed. The defaults favour recent locations like
+/// z; setting every weight to 1 ranks by visit count alone like autojump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weights {
+    /// Accessed within the last hour.
+    pub hour: f64,
+    /// Accessed within the last day.
+    pub day: f64,
+    /// Accessed within the last week.
+    pub week: f64,
+    /// Accessed longer ago.
+    pub older: f64,
+    /// Extra multiplier for locations where visits last ten minutes or longer on
+    /// average, as recorded by [`Database::leave_location`]. 0 ignores how long
+    /// visits last.
+    pub dwell: f64,
+    /// How much the quality of the text match counts, as the power its score of 1
+    /// to 3 is raised to before multiplying the frecency score, so that a close match
+    /// on a less visited location can beat a poor one. 0 orders by frecency alone,
+    /// except that fuzzy matches always count it at least once.
+    pub matching: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            hour: 4.0,
+            day: 2.0,
+            week: 0.5,
+            older: 0.25,
+            dwell: 1.0,
+            matching: 0.0,
+        }
+    }
+}
+
+impl Weights {
+    /// The SQL expression for an entry's frecency score.
+    fn frecency(&self) -> String {
+        format!(
+            "rank * case \
+             when strftime('%s', 'now') - lastAccess < 3600 then {:?} \
+             when strftime('%s', 'now') - lastAccess < 86400 then {:?} \
+             when strftime('%s', 'now') - lastAccess < 604800 then {:?} \
+             else {:?} end \
+             * (1.0 + {:?} * min(case when rank > 0 then dwell / rank else 0 end / {:?}, 1.0))",
+            self.hour, self.day, self.week, self.older, self.dwell, DWELL_FULL
+        )
+    }
+}
+
+/// Average seconds per visit that earn the whole dwell weight.
+const DWELL_FULL: f64 = 600.0;
+
+/// Longest time counted for one visit, so that a shell left open overnight does not
+/// dominate.
+const DWELL_MAX: f64 = 3600.0;
+
+const AGING_MIN_RANK: f64 = 0.1;
+
+/// Operations that can be undone, older ones are forgotten.
+const UNDO_LIMIT: i64 = 20;
+
+/// Entries checked for deleted directories by each maintenance run.
+const MAINTENANCE_SAMPLE: i64 = 20;
+
+/// Jumps kept in the history by default.
+const HISTORY_LIMIT: usize = 1000;
+
+/// Visits kept for each shell session, see [`Database::record_visit`].
+const SESSION_LIMIT: i64 = 100;
+
+/// Days after which the visits of a shell session that has gone quiet are dropped.
+const SESSION_DAYS: i64 = 30;
+
+const ADD_LOCATION: &str =
+    "insert into jump_location(location, rank, lastAccess, raw, host, kind) \
+     values(?, 1, strftime('%s', 'now'), ?, jump_host(), jump_kind()) \
+     on conflict(location, host) do update set rank = rank + 1, \
+     lastAccess = strftime('%s', 'now'), raw = coalesce(excluded.raw, raw), \
+     kind = excluded.kind";
+
+/// How long a statement waits for another process to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Attempts at recording a visit while the database stays busy.
+const BUSY_RETRIES: u32 = 3;
+
+/// Score multiplier when the last pattern matches the final path component.
+const LAST_COMPONENT_BOOST: f64 = 2.0;
+
+/// Score multiplier for descendants of the query's working directory.
+const CWD_BOOST: f64 = 2.0;
+
+/// Periodic decay of ranks so that locations which are no longer visited fall away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aging {
+    /// Age ranks once every `interval` adds, 0 never ages.
+    pub interval: u32,
+    /// Multiplier applied to every rank when aging.
+    pub factor: f64,
+    /// Ages ranks as soon as their unpinned total exceeds this, like z's cap.
+    pub max_rank: Option<f64>,
+    /// Evicts the lowest scoring unpinned entries beyond this many.
+    pub max_entries: Option<usize>,
+}
+
+impl Default for Aging {
+    fn default() -> Self {
+        Aging {
+            interval: 100,
+            factor: 0.9,
+            max_rank: None,
+            max_entries: None,
+        }
+    }
+}
+
+/// Problems found by [`Database::diagnose`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnosis {
+    /// Messages from SQLite's integrity check, empty when it passes.
+    pub integrity: Vec<String>,
+    /// The schema version recorded in the database.
+    pub version: usize,
+    /// The schema version this build expects.
+    pub expected_version: usize,
+    /// Locations that are stored differently but normalize to the same path, keyed by
+    /// that path.
+    pub duplicates: Vec<(String, Vec<String>)>,
+}
+
+impl Diagnosis {
+    /// Whether nothing needs attention.
+    pub fn is_healthy(&self) -> bool {
+        self.integrity.is_empty()
+            && self.version == self.expected_version
+            && self.duplicates.is_empty()
+    }
+}
+
+/// How imported ranks combine with an existing entry for the same location.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Conflict {
+    /// Add the ranks together and keep the newest access time.
+    Sum,
+    /// Keep the larger rank and the newest access time.
+    Max,
+    /// Leave the existing entry untouched.
+    KeepExisting,
+}
+
+impl Conflict {
+    fn update_clause(self) -> &'static str {
+        match self {
+            Conflict::Sum => {
+                "rank = rank + excluded.rank, lastAccess = max(lastAccess, excluded.lastAccess)"
+            }
+            Conflict::Max => {
+                "rank = max(rank, excluded.rank), lastAccess = max(lastAccess, excluded.lastAccess)"
+            }
+            Conflict::KeepExisting => "rank = rank",
+        }
+    }
+}
+
+/// A stored location along with its ranking data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub location: String,
+    /// Visit count, reduced over time by aging.
+    pub rank: f64,
+    /// Unix time of the last visit, in seconds. Formatted only for output, so that
+    /// ordering by it holds across time zones.
+    pub last_access: i64,
+    /// Frecency score used to order results.
+    pub score: f64,
+    /// Pinned entries outrank all others and never age.
+    pub pinned: bool,
+    /// Original bytes of a location that is not valid UTF-8, see [`Entry::path`].
+    pub raw: Option<Vec<u8>>,
+}
+
+impl Entry {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Entry> {
+        Ok(Entry {
+            location: row.get(0)?,
+            rank: row.get(1)?,
+            last_access: row.get(2)?,
+            score: row.get(3)?,
+            pinned: row.get(4)?,
+            raw: row.get(5)?,
+        })
+    }
+
+    /// Returns the location as a path, intact even when it is not valid UTF-8.
+    pub fn path(&self) -> PathBuf {
+        location::path(&self.location, self.raw.as_deref())
+    }
+}
+
+/// Summary figures for a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub entries: usize,
+    /// Sum of all ranks, roughly the number of recorded visits.
+    pub total_rank: f64,
+    /// Unix time of the least recent access, `None` without entries.
+    pub oldest_access: Option<i64>,
+    /// Unix time of the most recent access, `None` without entries.
+    pub newest_access: Option<i64>,
+}
+
+/// What an entry's location is, see [`Database::set_kind`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Kind {
+    #[default]
+    Directory,
+    /// A file, for recent file pickers in editors.
+    File,
+}
+
+impl Kind {
+    /// The name stored in the `kind` column.
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Directory => "dir",
+            Kind::File => "file",
+        }
+    }
+}
+
+/// A location chosen by `get`, see [`Database::record_jump`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jump {
+    /// The patterns as typed.
+    pub query: String,
+    pub location: String,
+    /// Unix time of the jump, in seconds.
+    pub time: i64,
+}
+
+/// A jumpjump database of visited locations.
+pub struct Database {
+    connection: Connection,
+    aging: Option<Aging>,
+    weights: Weights,
+    history_limit: Option<usize>,
+    maintenance: f64,
+    kind: Kind,
+    exclude: Option<RegexSet>,
+    dry_run: bool,
+    changes: RefCell<Vec<Change>>,
+}
+
+/// A change to an entry recorded instead of being committed in dry run mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Insert {
+        location: String,
+        rank: f64,
+    },
+    Update {
+        location: String,
+        from: f64,
+        to: f64,
+    },
+    Delete {
+        location: String,
+        rank: f64,
+    },
+}
+
+fn ranks(dbc: &Connection) -> Result<BTreeMap<String, f64>, JumpError> {
+    let mut stmt = dbc.prepare("select location, rank from jump_entry")?;
+    let ranks = stmt
+        .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    Ok(ranks)
+}
+
+fn diff_ranks(before: &BTreeMap<String, f64>, after: &BTreeMap<String, f64>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (location, &rank) in before {
+        match after.get(location) {
+            None => changes.push(Change::Delete {
+                location: location.clone(),
+                rank,
+            }),
+            Some(&to) if to != rank => changes.push(Change::Update {
+                location: location.clone(),
+                from: rank,
+                to,
+            }),
+            Some(_) => (),
+        }
+    }
+    for (location, &rank) in after {
+        if !before.contains_key(location) {
+            changes.push(Change::Insert {
+                location: location.clone(),
+                rank,
+            });
+        }
+    }
+    changes
+}
+
+fn ensure_tables(dbc: &Connection) -> Result<(), JumpError> {
+    add_nfc_function(dbc)?;
+    migrate(dbc, MIGRATIONS.len())?;
+    add_regexp_function(dbc)
+}
+
+pub(crate) fn migrate(dbc: &Connection, desired_version: usize) -> Result<(), JumpError> {
+    dbc.execute("create table if not exists migration_version (id INTEGER PRIMARY KEY ASC, version INTEGER);", NO_PARAMS)?;
+
+    loop {
+        let migration_version: usize = {
+            let mut stmt =
+                dbc.prepare("select version from migration_version where id = 1 limit 1")?;
+            let mut results_iter = stmt.query_map(NO_PARAMS, |row| row.get::<_, u32>(0))?;
+            match results_iter.next() {
+                None => 0,
+                Some(Ok(version)) => version as usize,
+                Some(Err(err)) => {
+                    return Err(JumpError::Migration(format!(
+                        "Failed to get database version: {}",
+                        err
+                    )))
+                }
+            }
+        };
+
+        if migration_version == desired_version {
+            return Ok(());
+        }
+
+        if migration_version > MIGRATIONS.len() {
+            return Err(JumpError::Migration(format!(
+                "Unrecognized database version {}",
+                migration_version
+            )));
+        }
+
+        dbc.execute_batch(MIGRATIONS[migration_version as usize])?;
+    }
+}
+
+/// Uses a write-ahead log so that prompt hooks in several shells can write
+/// without blocking readers, and waits rather than failing when busy.
+fn configure_connection(dbc: &Connection) -> Result<(), JumpError> {
+    dbc.busy_timeout(BUSY_TIMEOUT)?;
+    dbc.query_row("pragma journal_mode = wal", NO_PARAMS, |row| {
+        row.get::<_, String>(0)
+    })?;
+    dbc.execute_batch("pragma synchronous = normal")?;
+    Ok(())
+}
+
+fn is_busy(err: &JumpError) -> bool {
+    match err {
+        JumpError::Database(rusqlite::Error::SqliteFailure(err, _)) => matches!(
+            err.code,
+            rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+        ),
+        _ => false,
+    }
+}
+
+fn trace_sql(sql: &str) {
+    trace!("sql: {}", sql);
+}
+
+fn age(dbc: &Connection, factor: f64) -> Result<usize, JumpError> {
+    dbc.execute(
+        "update jump_location set rank = rank * ?1, dwell = dwell * ?1 where not pinned",
+        &[&factor],
+    )?;
+    let removed = dbc.execute(
+        "delete from jump_location where rank < ? and not pinned",
+        &[&AGING_MIN_RANK],
+    )?;
+    debug!("Aged ranks by {:.3}, dropping {} entries", factor, removed);
+    Ok(removed)
+}
+
+/// Keeps the database within the caps of `aging`, returning the number of entries dropped.
+fn evict(dbc: &Connection, aging: &Aging, frecency: &str) -> Result<usize, JumpError> {
+    let mut removed = 0;
+    if let Some(max_rank) = aging.max_rank {
+        let total: f64 = dbc.query_row(
+            "select coalesce(sum(rank), 0) from jump_location where not pinned",
+            NO_PARAMS,
+            |row| row.get(0),
+        )?;
+        if total > max_rank {
+            // Scale down to the aged cap so that the next few adds do not age again.
+            removed += age(dbc, max_rank * aging.factor / total)?;
+        }
+    }
+    if let Some(max_entries) = aging.max_entries {
+        let evicted = dbc.execute(
+            &format!(
+                "delete from jump_location where id in (\
+                 select id from jump_location where not pinned \
+                 order by {} desc, lastAccess desc limit -1 offset ?)",
+                frecency
+            ),
+            &[&(max_entries as i64)],
+        )?;
+        if evicted > 0 {
+            debug!("Evicted {} entries beyond {}", evicted, max_entries);
+        }
+        removed += evicted;
+    }
+    Ok(removed)
+}
+
+/// Makes `jump_kind()` return the name of `kind`, the kind of entries read and added.
+fn add_kind_function(db: &Connection, kind: Kind) -> Result<(), JumpError> {
+    db.create_scalar_function("jump_kind", 0, true, move |_| Ok(kind.name()))?;
+    Ok(())
+}
+
+/// Makes `jump_host()` return `host`, the host new entries belong to.
+fn add_host_function(db: &Connection, host: String) -> Result<(), JumpError> {
+    db.create_scalar_function("jump_host", 0, true, move |_| Ok(host.clone()))?;
+    Ok(())
+}
+
+/// Creates the `jump_entry` view that queries read from, holding the entries of the
+/// current host or, with `all_hosts`, the entries of every host merged by location.
+fn create_entry_view(db: &Connection, all_hosts: bool) -> Result<(), JumpError> {
+    let entries = if all_hosts {
+        "select min(id) as id, location, sum(rank) as rank, max(lastAccess) as lastAccess, \
+         max(pinned) as pinned, max(raw) as raw, sum(dwell) as dwell, max(lastLeave) as lastLeave, \
+         jump_host() as host, kind from jump_location where kind = jump_kind() group by location"
+    } else {
+        "select * from jump_location where host = jump_host() and kind = jump_kind()"
+    };
+    db.execute_batch(&format!(
+        "drop view if exists temp.jump_entry; create temp view jump_entry as {};",
+        entries
+    ))?;
+    Ok(())
+}
+
+/// Makes `jump_nfc(text)` return `text` in Unicode NFC, for migrating locations.
+fn add_nfc_function(db: &Connection) -> Result<(), JumpError> {
+    db.create_scalar_function("jump_nfc", 1, true, |ctx| {
+        Ok(location::nfc(&ctx.get::<String>(0)?))
+    })?;
+    Ok(())
+}
+
+fn add_regexp_function(db: &Connection) -> Result<(), JumpError> {
+    let mut cached_regexes = HashMap::new();
+    db.create_scalar_function("regexp", 2, true, move |ctx| {
+        let regex_s = ctx.get::<String>(0)?;
+        let entry = cached_regexes.entry(regex_s.clone());
+        let regex = {
+            use std::collections::hash_map::Entry::{Occupied, Vacant};
+            match entry {
+                Occupied(occ) => occ.into_mut(),
+                Vacant(vac) => match Regex::new(&regex_s) {
+                    Ok(r) => vac.insert(r),
+                    Err(err) => return Err(rusqlite::Error::UserFunctionError(Box::new(err))),
+                },
+            }
+        };
+
+        let text = ctx.get::<String>(1)?;
+        Ok(regex.is_match(&text))
+    })?;
+
+    Ok(())
+}
+
+impl Database {
+    /// Wraps an open connection, creating or migrating the schema as needed.
+    pub fn new(mut connection: Connection) -> Result<Database, JumpError> {
+        if log::enabled(Level::Trace) {
+            connection.trace(Some(trace_sql));
+        }
+        configure_connection(&connection)?;
+        ensure_tables(&connection)?;
+        add_regexp_function(&connection)?;
+        add_host_function(&connection, String::new())?;
+        add_kind_function(&connection, Kind::Directory)?;
+        create_entry_view(&connection, false)?;
+        Ok(Database {
+            connection,
+            aging: Some(Aging::default()),
+            weights: Weights::default(),
+            history_limit: Some(HISTORY_LIMIT),
+            maintenance: 0.0,
+            kind: Kind::Directory,
+            exclude: None,
+            dry_run: false,
+            changes: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// In dry run mode changes are rolled back and recorded for [`Database::take_changes`]
+    /// instead of being committed.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns the changes recorded in dry run mode so far.
+    pub fn take_changes(&self) -> Vec<Change> {
+        self.changes.replace(Vec::new())
+    }
+
+    /// Scopes entries to `host`, so that machines sharing a database track their
+    /// locations separately. `None`, the default, uses entries shared by every host.
+    pub fn set_host(&mut self, host: Option<&str>) -> Result<(), JumpError> {
+        add_host_function(&self.connection, host.unwrap_or_default().to_string())
+    }
+
+    /// Makes queries return the entries of every host, merged by location. Changes
+    /// still apply to the current host's entries.
+    pub fn set_all_hosts(&mut self, all_hosts: bool) -> Result<(), JumpError> {
+        create_entry_view(&self.connection, all_hosts)
+    }
+
+    /// Sets the kind of entries that queries return and adds record, directories by
+    /// default. Other changes apply to entries of either kind.
+    pub fn set_kind(&mut self, kind: Kind) -> Result<(), JumpError> {
+        self.kind = kind;
+        add_kind_function(&self.connection, kind)
+    }
+
+    /// Sets the chance, from 0 to 1, that an add also runs maintenance like z does,
+    /// dropping a random sample of entries whose directories were deleted. None by
+    /// default.
+    pub fn set_maintenance(&mut self, probability: f64) {
+        self.maintenance = probability;
+    }
+
+    /// Sets how recency weighs into scores.
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+    }
+
+    /// Sets how many jumps the history keeps, `Some(0)` records none and `None`
+    /// keeps every jump.
+    pub fn set_history_limit(&mut self, limit: Option<usize>) {
+        self.history_limit = limit;
+    }
+
+    /// Sets how ranks age as locations are added, `None` disables aging.
+    pub fn set_aging(&mut self, aging: Option<Aging>) {
+        self.aging = aging;
+    }
+
+    /// Sets globs for locations that are never added nor returned by the `get`
+    /// methods. A glob excludes descendants too, and one without a `/`, such as
+    /// `node_modules`, matches a path component anywhere.
+    pub fn set_exclude<I>(&mut self, globs: I) -> Result<(), JumpError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let patterns = globs
+            .into_iter()
+            .map(|glob| crate::glob::to_regex(glob.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.exclude = if patterns.is_empty() {
+            None
+        } else {
+            Some(RegexSet::new(patterns)?)
+        };
+        Ok(())
+    }
+
+    fn is_excluded(&self, location: &str) -> bool {
+        self.exclude
+            .as_ref()
+            .is_some_and(|exclude| exclude.is_match(location))
+    }
+
+    fn in_transaction<T, F>(&self, f: F) -> Result<T, JumpError>
+    where
+        F: FnOnce(&Connection) -> Result<T, JumpError>,
+    {
+        self.connection.execute_batch("begin transaction")?;
+        let result = if self.dry_run {
+            self.record_changes(f)
+        } else {
+            f(&self.connection)
+        };
+        match result {
+            Ok(result) if self.dry_run => {
+                self.connection.execute_batch("rollback")?;
+                Ok(result)
+            }
+            Ok(result) => {
+                self.connection.execute_batch("commit")?;
+                Ok(result)
+            }
+            Err(err) => {
+                self.connection.execute_batch("rollback")?;
+                Err(err)
+            }
+        }
+    }
+
+    fn record_changes<T, F>(&self, f: F) -> Result<T, JumpError>
+    where
+        F: FnOnce(&Connection) -> Result<T, JumpError>,
+    {
+        let before = ranks(&self.connection)?;
+        let result = f(&self.connection)?;
+        let after = ranks(&self.connection)?;
+        self.changes
+            .borrow_mut()
+            .extend(diff_ranks(&before, &after));
+        Ok(result)
+    }
+
+    /// Runs `f` as an operation that [`Database::undo`] can revert. Triggers journal
+    /// the statements reverting each change to locations while the operation is open.
+    fn journaled<T, F>(&self, description: &str, f: F) -> Result<T, JumpError>
+    where
+        F: FnOnce(&Connection) -> Result<T, JumpError>,
+    {
+        self.in_transaction(|dbc| {
+            dbc.prepare_cached(
+                "insert into jump_operation(description, time, open) \
+                 values(?, datetime('now'), 1)",
+            )?
+            .execute(&[description])?;
+            let id = dbc.last_insert_rowid();
+            let result = f(dbc)?;
+            let journaled = dbc
+                .prepare_cached("select exists (select 1 from jump_undo where operation = ?)")?
+                .query_row(&[&id], |row| row.get::<_, bool>(0))?;
+            if journaled {
+                dbc.prepare_cached("update jump_operation set open = 0 where id = ?")?
+                    .execute(&[&id])?;
+                dbc.prepare_cached("delete from jump_operation where id <= ?")?
+                    .execute(&[&(id - UNDO_LIMIT)])?;
+                dbc.prepare_cached("delete from jump_undo where operation <= ?")?
+                    .execute(&[&(id - UNDO_LIMIT)])?;
+            } else {
+                dbc.prepare_cached("delete from jump_operation where id = ?")?
+                    .execute(&[&id])?;
+            }
+            Ok(result)
+        })
+    }
+
+    /// Reverts the most recent add or removal, returning its description or
+    /// `None` when there is nothing left to undo.
+    pub fn undo(&self) -> Result<Option<String>, JumpError> {
+        self.in_transaction(|dbc| {
+            let operation = dbc
+                .query_row(
+                    "select id, description from jump_operation order by id desc limit 1",
+                    NO_PARAMS,
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            let (id, description) = match operation {
+                Some(operation) => operation,
+                None => return Ok(None),
+            };
+            let statements = dbc
+                .prepare("select statement from jump_undo where operation = ? order by id desc")?
+                .query_map(&[&id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for statement in statements {
+                dbc.execute_batch(&statement)?;
+            }
+            dbc.execute("delete from jump_undo where operation = ?", &[&id])?;
+            dbc.execute("delete from jump_operation where id = ?", &[&id])?;
+            Ok(Some(description))
+        })
+    }
+
+    /// Records a visit to `location`, which should already be canonical.
+    /// Excluded locations are silently ignored.
+    pub fn add_location<S: AsRef<str>>(&self, location: S) -> Result<(), JumpError> {
+        self.add(location.as_ref(), None)
+    }
+
+    /// Like [`Database::add_location`] but keeps paths that are not valid UTF-8
+    /// intact, see [`Entry::path`].
+    pub fn add_path<P: AsRef<Path>>(&self, path: P) -> Result<(), JumpError> {
+        let path = path.as_ref();
+        self.add(&location::key(path), location::raw(path))
+    }
+
+    fn add(&self, location: &str, raw: Option<Vec<u8>>) -> Result<(), JumpError> {
+        let location = &location::normalize(location);
+        if self.is_excluded(location) {
+            debug!("Not adding excluded {}", location);
+            return Ok(());
+        }
+        let mut attempt = 1;
+        loop {
+            match self.try_add(location, &raw) {
+                Err(ref err) if is_busy(err) && attempt < BUSY_RETRIES => {
+                    debug!("Database busy adding {}, retrying", location);
+                    attempt += 1
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn try_add(&self, location: &str, raw: &Option<Vec<u8>>) -> Result<(), JumpError> {
+        self.journaled(&format!("add {}", location), |dbc| {
+            dbc.prepare_cached(ADD_LOCATION)?
+                .execute(rusqlite::params![location, raw])?;
+            self.count_adds(dbc, 1)?;
+            if self.maintenance > 0.0 {
+                let roll: f64 = dbc.query_row(
+                    "select (random() & 4294967295) / 4294967296.0",
+                    NO_PARAMS,
+                    |row| row.get(0),
+                )?;
+                if roll < self.maintenance {
+                    self.maintain(dbc)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Drops deleted entries among a random sample, returning the number dropped.
+    /// An entry is only deleted when its parent still exists, so that entries on an
+    /// unmounted drive are kept.
+    fn maintain(&self, dbc: &Connection) -> Result<usize, JumpError> {
+        let mut stmt = dbc
+            .prepare_cached("select id, location, raw from jump_entry order by random() limit ?")?;
+        let sample = stmt
+            .query_map([MAINTENANCE_SAMPLE], |row| {
+                let location: String = row.get(1)?;
+                let raw: Option<Vec<u8>> = row.get(2)?;
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    location::path(&location, raw.as_deref()),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut dropped = 0;
+        for (id, path) in sample {
+            if !path.exists() && path.parent().is_some_and(Path::exists) {
+                dropped += dbc.execute("delete from jump_location where id = ?", [id])?;
+            }
+        }
+        debug!("Maintenance dropped {} deleted entries", dropped);
+        Ok(dropped)
+    }
+
+    /// Records a visit to each of `locations` in one transaction, returning the
+    /// number recorded. Excluded locations are skipped.
+    pub fn add_locations<I>(&self, locations: I) -> Result<usize, JumpError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.in_transaction(|dbc| {
+            let mut stmt = dbc.prepare_cached(ADD_LOCATION)?;
+            let mut added = 0;
+            for location in locations {
+                let location = &location::normalize(location.as_ref());
+                if !self.is_excluded(location) {
+                    stmt.execute(rusqlite::params![location, None::<Vec<u8>>])?;
+                    added += 1;
+                }
+            }
+            self.count_adds(dbc, added)?;
+            Ok(added)
+        })
+    }
+
+    /// Counts `added` visits towards aging, aging ranks when an interval passes.
+    fn count_adds(&self, dbc: &Connection, added: usize) -> Result<(), JumpError> {
+        let aging = match self.aging {
+            Some(aging) if added > 0 => aging,
+            _ => return Ok(()),
+        };
+        dbc.execute(
+            "update jump_counter set value = value + ? where name = 'adds'",
+            &[&(added as i64)],
+        )?;
+        let adds: u32 = dbc.query_row(
+            "select value from jump_counter where name = 'adds'",
+            NO_PARAMS,
+            |row| row.get(0),
+        )?;
+        let before = adds.saturating_sub(added as u32);
+        if aging.interval > 0 && adds / aging.interval > before / aging.interval {
+            age(dbc, aging.factor)?;
+        }
+        evict(dbc, &aging, &self.weights.frecency())?;
+        Ok(())
+    }
+
+    /// Multiplies every unpinned rank by `factor` and drops entries whose rank falls too low,
+    /// returning the number dropped.
+    pub fn age_locations(&self, factor: f64) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| age(dbc, factor))
+    }
+
+    /// Adds locations from another source in one transaction.
+    pub fn import_locations(
+        &self,
+        locations: &[ImportedLocation],
+        conflict: Conflict,
+    ) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| {
+            let mut stmt = dbc.prepare_cached(&format!(
+                "insert into jump_location(location, rank, lastAccess, host, kind) \
+                 values(?1, ?2, coalesce(?3, strftime('%s', 'now')), jump_host(), jump_kind()) \
+                 on conflict(location, host) do update set {}",
+                conflict.update_clause()
+            ))?;
+            for l in locations {
+                stmt.execute(rusqlite::params![l.location, l.rank, l.last_access])?;
+            }
+            Ok(locations.len())
+        })
+    }
+
+    /// Sets the rank of each location in one transaction, adding those that are new.
+    pub fn set_ranks(&self, ranks: &[(String, f64)]) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| {
+            let mut stmt = dbc.prepare_cached(
+                "insert into jump_location(location, rank, lastAccess, host, kind) \
+                 values(?, ?, strftime('%s', 'now'), jump_host(), jump_kind()) \
+                 on conflict(location, host) do update set rank = excluded.rank",
+            )?;
+            let mut changed = 0;
+            for (location, rank) in ranks {
+                changed += stmt.execute(rusqlite::params![location, rank])?;
+            }
+            Ok(changed)
+        })
+    }
+
+    /// Records that `query` chose `location`, pruning the oldest jumps beyond the
+    /// history limit.
+    pub fn record_jump<S: AsRef<str>, T: AsRef<str>>(
+        &self,
+        query: S,
+        location: T,
+    ) -> Result<(), JumpError> {
+        if self.history_limit == Some(0) {
+            return Ok(());
+        }
+        self.in_transaction(|dbc| {
+            dbc.execute(
+                "insert into jump_history(query, location, time) \
+                 values(?, ?, strftime('%s', 'now'))",
+                &[query.as_ref(), location.as_ref()],
+            )?;
+            if let Some(limit) = self.history_limit {
+                dbc.execute(
+                    "delete from jump_history where id <= (select max(id) from jump_history) - ?",
+                    &[&(limit as i64)],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Records that the shell `session` moved to `location`, so that
+    /// [`Database::get_session_locations`] can retrace its steps. Each session keeps
+    /// its latest visits and sessions unused for a month are forgotten.
+    pub fn record_visit<S: AsRef<str>, T: AsRef<str>>(
+        &self,
+        session: S,
+        location: T,
+    ) -> Result<(), JumpError> {
+        let (session, location) = (session.as_ref(), location.as_ref());
+        self.in_transaction(|dbc| {
+            let last = dbc
+                .query_row(
+                    "select location from jump_session where session = ? order by id desc limit 1",
+                    &[session],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
+            if last.as_deref() == Some(location) {
+                return Ok(());
+            }
+            dbc.execute(
+                "insert into jump_session(session, location, time) \
+                 values(?, ?, strftime('%s', 'now'))",
+                &[session, location],
+            )?;
+            dbc.execute(
+                "delete from jump_session where session = ?1 and id <= \
+                 (select max(id) from jump_session where session = ?1) - ?2",
+                rusqlite::params![session, SESSION_LIMIT],
+            )?;
+            dbc.execute(
+                "delete from jump_session where session in (select session from jump_session \
+                 group by session having max(time) < strftime('%s', 'now') - ? * 86400)",
+                &[&SESSION_DAYS],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Returns the locations visited by the shell `session`, newest first.
+    pub fn get_session_locations<S: AsRef<str>>(
+        &self,
+        session: S,
+    ) -> Result<Vec<String>, JumpError> {
+        let mut stmt = self
+            .connection
+            .prepare("select location from jump_session where session = ? order by id desc")?;
+        let locations = stmt
+            .query_map(&[session.as_ref()], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(locations)
+    }
+
+    /// Pushes `location` onto the directory stack of `session`. Unlike the visits
+    /// of a session, stacks are kept until they are popped.
+    pub fn push_location<S: AsRef<str>, T: AsRef<str>>(
+        &self,
+        session: S,
+        location: T,
+    ) -> Result<(), JumpError> {
+        self.in_transaction(|dbc| {
+            dbc.execute(
+                "insert into jump_stack(session, location) values(?, ?)",
+                &[session.as_ref(), location.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Removes and returns the top of the directory stack of `session`.
+    pub fn pop_location<S: AsRef<str>>(&self, session: S) -> Result<Option<String>, JumpError> {
+        self.in_transaction(|dbc| {
+            let top = dbc
+                .query_row(
+                    "select id, location from jump_stack where session = ? order by id desc limit 1",
+                    &[session.as_ref()],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            match top {
+                Some((id, location)) => {
+                    dbc.execute("delete from jump_stack where id = ?", [id])?;
+                    Ok(Some(location))
+                }
+                None => Ok(None),
+            }
+        })
+    }
+
+    /// Returns the directory stack of `session`, top first.
+    pub fn get_stack<S: AsRef<str>>(&self, session: S) -> Result<Vec<String>, JumpError> {
+        let mut stmt = self
+            .connection
+            .prepare("select location from jump_stack where session = ? order by id desc")?;
+        let locations = stmt
+            .query_map(&[session.as_ref()], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(locations)
+    }
+
+    /// Returns recorded jumps, newest first.
+    pub fn get_history(&self, limit: Option<usize>) -> Result<Vec<Jump>, JumpError> {
+        let mut stmt = self
+            .connection
+            .prepare("select query, location, time from jump_history order by id desc limit ?")?;
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let jumps = stmt
+            .query_map(&[&limit], |row| {
+                Ok(Jump {
+                    query: row.get(0)?,
+                    location: row.get(1)?,
+                    time: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(jumps)
+    }
+
+    /// Forgets every recorded jump, returning how many there were.
+    pub fn clear_history(&self) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| Ok(dbc.execute("delete from jump_history", NO_PARAMS)?))
+    }
+
+    /// Records leaving `location`, adding the time since it was last entered to how
+    /// long it is visited for. Returns whether a visit was open.
+    pub fn leave_location<S: AsRef<str>>(&self, location: S) -> Result<bool, JumpError> {
+        self.in_transaction(|dbc| {
+            let left = dbc.execute(
+                "update jump_location set \
+                 dwell = dwell + max(min(strftime('%s', 'now') - lastAccess, ?2), 0), \
+                 lastLeave = strftime('%s', 'now') \
+                 where location = ?1 and host = jump_host() \
+                 and (lastLeave is null or lastLeave < lastAccess)",
+                rusqlite::params![location.as_ref(), DWELL_MAX],
+            )?;
+            Ok(left > 0)
+        })
+    }
+
+    /// Adds `amount`, which may be negative, to the rank of `location` without going
+    /// below zero. Returns the new rank, or `None` when there is no such entry.
+    pub fn adjust_rank<S: AsRef<str>>(
+        &self,
+        location: S,
+        amount: f64,
+    ) -> Result<Option<f64>, JumpError> {
+        let location = location.as_ref();
+        self.in_transaction(|dbc| {
+            let changed = dbc.execute(
+                "update jump_location set rank = max(rank + ?2, 0) \
+                 where location = ?1 and host = jump_host()",
+                rusqlite::params![location, amount],
+            )?;
+            if changed == 0 {
+                return Ok(None);
+            }
+            let rank = dbc.query_row(
+                "select rank from jump_location where location = ? and host = jump_host()",
+                &[location],
+                |row| row.get(0),
+            )?;
+            Ok(Some(rank))
+        })
+    }
+
+    /// Checks the database file, its schema version and its stored locations.
+    pub fn diagnose(&self) -> Result<Diagnosis, JumpError> {
+        let mut stmt = self.connection.prepare("pragma integrity_check")?;
+        let integrity = stmt
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(0))?
+            .filter(|message| message.as_ref().map_or(true, |m| m != "ok"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let version = self.connection.query_row(
+            "select version from migration_version where id = 1",
+            NO_PARAMS,
+            |row| row.get::<_, i64>(0),
+        )?;
+
+        Ok(Diagnosis {
+            integrity,
+            version: version as usize,
+            expected_version: MIGRATIONS.len(),
+            duplicates: self.get_duplicates()?,
+        })
+    }
+
+    /// Returns groups of locations that are stored differently but normalize to the
+    /// same path, see [`Diagnosis::duplicates`]. On Windows locations differing only
+    /// in case are duplicates too, merged into the spelling with the highest rank.
+    pub fn get_duplicates(&self) -> Result<Vec<(String, Vec<String>)>, JumpError> {
+        let mut groups: BTreeMap<String, Vec<(String, f64)>> = BTreeMap::new();
+        let mut stmt = self
+            .connection
+            .prepare("select location, sum(rank) from jump_location group by location")?;
+        let rows = stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (location, rank): (String, f64) = row?;
+            let normalized = location::normalize(&location);
+            let key = if cfg!(windows) {
+                normalized.to_lowercase()
+            } else {
+                normalized
+            };
+            groups.entry(key).or_default().push((location, rank));
+        }
+        let duplicates = groups
+            .into_values()
+            .filter(|locations| locations.len() > 1)
+            .map(|mut locations| {
+                let best = locations
+                    .iter()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(location, _)| location::normalize(location))
+                    .unwrap_or_default();
+                locations.sort_by(|a, b| a.0.cmp(&b.0));
+                (best, locations.into_iter().map(|(l, _)| l).collect())
+            })
+            .collect();
+        Ok(duplicates)
+    }
+
+    /// Merges each group of duplicates into its normalized location, summing ranks,
+    /// keeping the latest access and keeping tags and aliases. Returns the number of
+    /// entries merged away.
+    pub fn merge_duplicates(
+        &self,
+        duplicates: &[(String, Vec<String>)],
+    ) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| {
+            let mut merged = 0;
+            for (normalized, locations) in duplicates {
+                for location in locations.iter().filter(|l| *l != normalized) {
+                    let params = [normalized, location];
+                    dbc.execute(
+                        "insert into jump_location(location, rank, lastAccess, pinned, raw, dwell, lastLeave, host, kind) \
+                         select ?1, rank, lastAccess, pinned, raw, dwell, lastLeave, host, kind from jump_location \
+                         where location = ?2 \
+                         on conflict(location, host) do update set rank = rank + excluded.rank, \
+                         lastAccess = max(lastAccess, excluded.lastAccess), \
+                         pinned = max(pinned, excluded.pinned), raw = coalesce(raw, excluded.raw), \
+                         dwell = dwell + excluded.dwell, lastLeave = max(lastLeave, excluded.lastLeave)",
+                        &params,
+                    )?;
+                    dbc.execute(
+                        "update or ignore jump_tag set location = ?1 where location = ?2",
+                        &params,
+                    )?;
+                    dbc.execute("update jump_alias set location = ?1 where location = ?2", &params)?;
+                    merged += dbc.execute("delete from jump_location where location = ?2", &params)?;
+                }
+            }
+            Ok(merged)
+        })
+    }
+
+    /// Merges all entries from the jumpjump database at `path`, summing ranks.
+    pub fn merge_database<P: AsRef<Path>>(&self, path: P) -> Result<usize, JumpError> {
+        let path = path.as_ref().to_string_lossy();
+        self.connection
+            .execute("attach database ? as other", &[&path.as_ref()])?;
+        let merged = self.merge_attached();
+        self.connection
+            .execute("detach database other", NO_PARAMS)?;
+        merged
+    }
+
+    fn merge_attached(&self) -> Result<usize, JumpError> {
+        let version: u32 = self
+            .connection
+            .query_row(
+                "select version from other.migration_version where id = 1",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .map_err(|err| JumpError::Migration(format!("Not a jumpjump database: {}", err)))?;
+        if version < 3 {
+            return Err(JumpError::Migration(format!(
+                "Database version {} is too old to merge, open it with jumpjump first",
+                version
+            )));
+        }
+        // Entries were all directories before version 14.
+        let kind = if version < 14 { "'dir'" } else { "kind" };
+        self.in_transaction(|dbc| {
+            let merged = dbc.execute(
+                &format!(
+                    "insert into main.jump_location(location, rank, lastAccess, host, kind) \
+                     select location, rank, \
+                     case when typeof(lastAccess) = 'text' \
+                     then cast(strftime('%s', lastAccess, 'utc') as integer) else lastAccess end, \
+                     jump_host(), {} from other.jump_location where true \
+                     on conflict(location, host) do update set {}",
+                    kind,
+                    Conflict::Sum.update_clause()
+                ),
+                NO_PARAMS,
+            )?;
+            Ok(merged)
+        })
+    }
+
+    /// Returns every entry in a form suitable for [`crate::import`] writers.
+    pub fn export_locations(&self) -> Result<Vec<ImportedLocation>, JumpError> {
+        let mut stmt = self.connection.prepare(&format!(
+            "select location, rank, lastAccess \
+             from jump_entry order by {} desc, lastAccess desc",
+            self.weights.frecency()
+        ))?;
+        let locations = stmt
+            .query_map(NO_PARAMS, |row| {
+                Ok(ImportedLocation {
+                    location: row.get(0)?,
+                    rank: row.get(1)?,
+                    last_access: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(locations)
+    }
+
+    /// Returns every location, best first.
+    pub fn get_locations(&self) -> Result<Vec<String>, JumpError> {
+        let mut stmt = self.connection.prepare(&format!(
+            "select location from jump_entry order by pinned desc, {} desc, lastAccess desc",
+            self.weights.frecency()
+        ))?;
+        let mut locations = stmt
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        locations.retain(|l| !self.is_excluded(l));
+
+        Ok(locations)
+    }
+
+    /// Returns locations matching all `patterns` in order, best first.
+    pub fn get_matching_locations<I>(&self, patterns: I) -> Result<Vec<String>, JumpError>
+    where
+        I: IntoIterator,
+        I::Item: std::fmt::Display,
+    {
+        let entries = self.get_matching_entries(patterns)?;
+        Ok(entries.into_iter().map(|e| e.location).collect())
+    }
+
+    /// Like [`Database::get_matching_locations`] but includes ranking data.
+    pub fn get_matching_entries<I>(&self, patterns: I) -> Result<Vec<Entry>, JumpError>
+    where
+        I: IntoIterator,
+        I::Item: std::fmt::Display,
+    {
+        self.query(&Query::new(patterns))
+    }
+
+    /// Returns entries matching `query`, best first.
+    ///
+    /// This collects every match before returning. To stream matches row by row,
+    /// for example to stop at the first, use [`Database::query_each`] instead.
+    pub fn query(&self, query: &Query) -> Result<Vec<Entry>, JumpError> {
+        let mut entries = Vec::new();
+        self.query_each(query, |e| {
+            entries.push(e);
+            true
+        })?;
+
+        if log::enabled(Level::Debug) {
+            for e in entries.iter().take(5) {
+                debug!(
+                    "{}: score {:.2} from rank {:.2} last accessed at {}{}",
+                    e.location,
+                    e.score,
+                    e.rank,
+                    e.last_access,
+                    if e.pinned { ", pinned" } else { "" }
+                );
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Like [`Database::query`] but passes each entry to `f` as its row is read,
+    /// stopping early once `f` returns `false`, so that scanning a large database
+    /// never holds more than one entry at a time. Fuzzy queries, and any when
+    /// [`Weights::matching`] is set, still read every match first, as their order is
+    /// only known once all are scored.
+    pub fn query_each<F>(&self, query: &Query, mut f: F) -> Result<(), JumpError>
+    where
+        F: FnMut(Entry) -> bool,
+    {
+        let mut stmt = self.connection.prepare_cached(&format!(
+            "select location, rank, lastAccess, \
+             {} * case when ?3 is not null and regexp(?3, location) then {:.1} else 1.0 end \
+             * case when substr(location, 1, length(?4)) = ?4 then {:.1} else 1.0 end as score, \
+             pinned, raw from jump_entry \
+             where (?5 is null or location like ?5 escape '\\') and regexp(?1, location) \
+             and (?2 is null or location in (select location from jump_tag where tag = ?2)) \
+             and (?6 is null or strftime('%s', 'now') - lastAccess <= ?6 * 86400) \
+             and (?7 is null or strftime('%s', 'now') - lastAccess > ?7 * 86400) \
+             and (?8 is null or not regexp(?8, location)) \
+             and (?9 is null or location <> ?9) \
+             order by pinned desc, score desc, lastAccess desc",
+            self.weights.frecency(),
+            LAST_COMPONENT_BOOST,
+            CWD_BOOST
+        ))?;
+        query.check()?;
+        let (regex, last, like, excluded) = (
+            query.regex(),
+            query.last_component_regex(),
+            query.like_pattern(),
+            query.excluded_regex(),
+        );
+        debug!(
+            "Matching {:?}, last component {:?}, prefilter {:?}, excluding {:?}",
+            regex, last, like, excluded
+        );
+        let (within, before) = query.age_window();
+        let params: [&dyn ToSql; 9] = [
+            &regex,
+            &query.tag,
+            &last,
+            &query.cwd_prefix(),
+            &like,
+            &within,
+            &before,
+            &excluded,
+            &query.skipped_location(),
+        ];
+        // Weighing in match quality reorders rows, so they are all read first.
+        let matching = match query.strategy {
+            Strategy::Fuzzy => self.weights.matching.max(1.0),
+            _ => self.weights.matching,
+        };
+        let pattern = query.match_pattern().filter(|_| matching > 0.0);
+        let mut scored = Vec::new();
+        let (mut candidates, mut kept) = (0, 0);
+        let mut rows = stmt.query(&params)?;
+        while let Some(row) = rows.next()? {
+            let e = Entry::from_row(row)?;
+            candidates += 1;
+            if self.is_excluded(&e.location) || (query.require_exists && !self.is_present(&e)) {
+                continue;
+            }
+            kept += 1;
+            if pattern.is_some() {
+                scored.push(e);
+            } else if !f(e) {
+                return Ok(());
+            }
+        }
+        debug!(
+            "{} candidates matched, {} left after exclusions and missing or locked paths",
+            candidates, kept
+        );
+
+        if let Some(pattern) = pattern {
+            for e in scored.iter_mut() {
+                let quality = query::fuzzy_score(&pattern, &e.location, query.ignores_case());
+                e.score *= quality.unwrap_or(1.0).powf(matching);
+            }
+            scored.sort_by(|a, b| {
+                b.pinned
+                    .cmp(&a.pinned)
+                    .then(b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal))
+                    .then_with(|| b.last_access.cmp(&a.last_access))
+            });
+            for e in scored {
+                if !f(e) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the entry's location exists as the kind of location being tracked.
+    fn is_present(&self, e: &Entry) -> bool {
+        match self.kind {
+            Kind::Directory => location::is_enterable(&e.path()),
+            Kind::File => e.path().is_file(),
+        }
+    }
+
+    /// Returns the distinct last components of locations matching `query` whose last
+    /// component matches its last pattern, best first, as candidates for completing
+    /// that pattern.
+    pub fn complete(&self, query: &Query) -> Result<Vec<String>, JumpError> {
+        let last = match query.last_component_regex() {
+            Some(last) => Some(Regex::new(&last)?),
+            None => None,
+        };
+        let mut names = Vec::new();
+        for entry in self.query(query)? {
+            let name = match entry.path().file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            if last.as_ref().is_none_or(|last| last.is_match(&name)) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    /// Returns every entry, best first.
+    pub fn get_entries(&self) -> Result<Vec<Entry>, JumpError> {
+        let mut stmt = self.connection.prepare(&format!(
+            "select location, rank, lastAccess, \
+             {} as score, pinned, raw from jump_entry \
+             order by pinned desc, score desc, lastAccess desc",
+            self.weights.frecency()
+        ))?;
+        let mut entries = stmt
+            .query_map(NO_PARAMS, Entry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.retain(|e| !self.is_excluded(&e.location));
+
+        Ok(entries)
+    }
+
+    /// Returns the repository roots containing entries, see [`crate::find_vcs_root`],
+    /// each with the rank and score of its entries summed and their latest access.
+    /// Entries outside any repository, or whose directory is gone, are left out.
+    pub fn get_projects(&self) -> Result<Vec<Entry>, JumpError> {
+        let mut projects = BTreeMap::<PathBuf, Entry>::new();
+        for e in self.get_entries()? {
+            let path = e.path();
+            let root = match crate::find_vcs_root(&path) {
+                Some(root) => root.to_path_buf(),
+                None => continue,
+            };
+            let project = projects.entry(root).or_insert_with_key(|root| Entry {
+                location: location::key(root),
+                rank: 0.0,
+                last_access: 0,
+                score: 0.0,
+                pinned: false,
+                raw: location::raw(root),
+            });
+            project.rank += e.rank;
+            project.score += e.score;
+            project.pinned |= e.pinned;
+            if e.last_access > project.last_access {
+                project.last_access = e.last_access;
+            }
+        }
+        let mut projects = projects.into_values().collect::<Vec<_>>();
+        projects.sort_by(|a, b| {
+            b.pinned
+                .cmp(&a.pinned)
+                .then(b.score.total_cmp(&a.score))
+                .then_with(|| b.last_access.cmp(&a.last_access))
+        });
+        Ok(projects)
+    }
+
+    /// Returns the current time in the form of [`Entry::last_access`].
+    pub fn now(&self) -> Result<i64, JumpError> {
+        let now = self.connection.query_row(
+            "select cast(strftime('%s', 'now') as integer)",
+            NO_PARAMS,
+            |row| row.get(0),
+        )?;
+        Ok(now)
+    }
+
+    /// Returns summary figures for the whole database.
+    pub fn stats(&self) -> Result<Stats, JumpError> {
+        let stats = self.connection.query_row(
+            "select count(*), coalesce(sum(rank), 0), min(lastAccess), max(lastAccess) from jump_entry",
+            NO_PARAMS,
+            |row| {
+                Ok(Stats {
+                    entries: row.get::<_, i64>(0)? as usize,
+                    total_rank: row.get(1)?,
+                    oldest_access: row.get(2)?,
+                    newest_access: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(stats)
+    }
+
+    /// Rebuilds the database file without free pages, folding in the write-ahead log,
+    /// and refreshes the statistics SQLite plans queries with. Vacuuming cannot be
+    /// rolled back, so a dry run leaves the file as it is.
+    pub fn optimize(&self) -> Result<(), JumpError> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.connection
+            .execute_batch("vacuum; analyze; pragma optimize;")?;
+        self.connection
+            .query_row("pragma wal_checkpoint(truncate)", NO_PARAMS, |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// Pins `location`, adding it if needed, so it outranks unpinned matches.
+    pub fn pin_location<S: AsRef<str>>(&self, location: S) -> Result<(), JumpError> {
+        self.in_transaction(|dbc| {
+            dbc.execute(
+                "insert into jump_location(location, rank, lastAccess, pinned, host, kind) \
+                 values(?, 1, strftime('%s', 'now'), 1, jump_host(), jump_kind()) \
+                 on conflict(location, host) do update set pinned = 1",
+                &[&location.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Unpins `location`, returning the number of entries changed.
+    pub fn unpin_location<S: AsRef<str>>(&self, location: S) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| {
+            let unpinned = dbc.execute(
+                "update jump_location set pinned = 0 where location = ? and host = jump_host() and pinned",
+                &[&location.as_ref()],
+            )?;
+            Ok(unpinned)
+        })
+    }
+
+    /// Names `location` so that [`Database::get_alias_entry`] finds it by `name`,
+    /// replacing any earlier alias of that name.
+    pub fn set_alias<S: AsRef<str>, T: AsRef<str>>(
+        &self,
+        name: S,
+        location: T,
+    ) -> Result<(), JumpError> {
+        self.in_transaction(|dbc| {
+            dbc.execute(
+                "insert or replace into jump_alias(name, location) values(?, ?)",
+                &[&name.as_ref(), &location.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Removes the alias `name`, returning the number removed.
+    pub fn remove_alias<S: AsRef<str>>(&self, name: S) -> Result<usize, JumpError> {
+        self.in_transaction(|dbc| {
+            let removed =
+                dbc.execute("delete from jump_alias where name = ?", &[&name.as_ref()])?;
+            Ok(removed)
+        })
+    }
+
+    /// Returns every alias as `(name, location)`, ordered by name.
+    pub fn get_aliases(&self) -> Result<Vec<(String, String)>, JumpError> {
+        let mut stmt = self
+            .connection
+            .prepare("select name, location from jump_alias order by name")?;
+        let aliases = stmt
+            .query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(aliases)
+    }
+
+    /// Returns the entry for the location aliased as `name`, with zero rank when
+    /// the location has never been visited.
+    pub fn get_alias_entry<S: AsRef<str>>(&self, name: S) -> Result<Option<Entry>, JumpError> {
+        let mut stmt = self.connection.prepare_cached(&format!(
+            "select a.location, coalesce(rank, 0.0), \
+             coalesce(lastAccess, 0), \
+             coalesce({}, 0.0), coalesce(pinned, 0), raw \
+             from jump_alias a left join jump_entry l on l.location = a.location \
+             where a.name = ?",
+            self.weights.frecency()
+        ))?;
+        let mut entries = stmt.query_map(&[&name.as_ref()], Entry::from_row)?;
+        Ok(entries.next().transpose()?)
+    }
+
+    /// Tags `location`, adding it if needed, returning the number of new tags.
+    pub fn tag_location<S, I>(&self, location: S, tags: I) -> Result<usize, JumpError>
+    where
+        S: AsRef<str>,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let location = location.as_ref();
+        self.in_transaction(|dbc| {
+            dbc.execute(
+                "insert or ignore into jump_location(location, rank, lastAccess, host, kind) \
+                 values(?, 1, strftime('%s', 'now'), jump_host(), jump_kind())",
+                &[&location],
+            )?;
+            let mut stmt =
+                dbc.prepare_cached("insert or ignore into jump_tag(location, tag) values(?, ?)")?;
+            let mut added = 0;
+            for tag in tags {
+                added += stmt.execute(&[location, tag.as_ref()])?;
+            }
+            Ok(added)
+        })
+    }
+
+    /// Removes `tags` from `location`, returning the number removed.
+    pub fn untag_location<S, I>(&self, location: S, tags: I) -> Result<usize, JumpError>
+    where
+        S: AsRef<str>,
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let location = location.as_ref();
+        self.in_transaction(|dbc| {
+            let mut stmt =
+                dbc.prepare_cached("delete from jump_tag where location = ? and tag = ?")?;
+            let mut removed = 0;
+            for tag in tags {
+                removed += stmt.execute(&[location, tag.as_ref()])?;
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Returns the tags of `location`, or of every location when `None`, as
+    /// `(location, tag)` ordered by location.
+    pub fn get_tags(&self, location: Option<&str>) -> Result<Vec<(String, String)>, JumpError> {
+        let mut stmt = self.connection.prepare(
+            "select location, tag from jump_tag where ?1 is null or location = ?1 order by location, tag",
+        )?;
+        let tags = stmt
+            .query_map(&[&location], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tags)
+    }
+
+    /// Removes a single location, returning the number of entries removed.
+    pub fn remove_location<S: AsRef<str>>(&self, location: S) -> Result<usize, JumpError> {
+        let location = location.as_ref();
+        self.journaled(&format!("remove {}", location), |dbc| {
+            let removed = dbc.execute(
+                "delete from jump_location where location = ? and host = jump_host()",
+                &[&location],
+            )?;
+            Ok(removed)
+        })
+    }
+
+    /// Removes many locations in one transaction.
+    pub fn remove_locations<I>(&self, locations: I) -> Result<usize, JumpError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.journaled("remove locations", |dbc| {
+            let mut stmt = dbc.prepare_cached(
+                "delete from jump_location where location = ? and host = jump_host()",
+            )?;
+            let mut removed = 0;
+            for location in locations {
+                removed += stmt.execute(&[&location.as_ref()])?;
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Removes every location matching the regex `pattern`.
+    pub fn remove_matching_locations<S: AsRef<str>>(&self, pattern: S) -> Result<usize, JumpError> {
+        let pattern = pattern.as_ref();
+        Regex::new(pattern)?;
+        self.journaled(&format!("remove matching {}", pattern), |dbc| {
+            let removed = dbc.execute(
+                "delete from jump_location where regexp(?, location) and host = jump_host()",
+                &[&pattern],
+            )?;
+            Ok(removed)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::types::Value;
+    use std::process;
+
+    #[test]
+    fn most_visited_location_is_first() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("foo").unwrap();
+        db.add_location("foo").unwrap();
+        db.add_location("bar").unwrap();
+        db.add_location("bar").unwrap();
+        db.add_location("bar").unwrap();
+
+        let locations: Vec<String> = db.get_locations().unwrap();
+
+        assert_eq!(locations[..], ["bar", "foo"]);
+    }
+
+    #[test]
+    fn finds_by_multiple_substr() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+        db.add_location("/foo/bar/doo").unwrap();
+
+        let locations: Vec<String> = db.get_matching_locations(&["bar", "doo"]).unwrap();
+
+        assert_eq!(locations[..], ["/foo/bar/doo"]);
+    }
+
+    #[test]
+    fn finds_by_single_substr() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar/doo").unwrap();
+
+        let locations: Vec<String> = db.get_matching_locations(&["doo"]).unwrap();
+
+        assert_eq!(locations[..], ["/foo/bar/doo"]);
+    }
+
+    #[test]
+    fn finds_in_many() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        for x in 0..10000 {
+            db.add_location(format!("/foo/bar/{}", x)).unwrap();
+        }
+
+        let locations: Vec<String> = db.get_matching_locations(&["bar", "9999"]).unwrap();
+
+        assert_eq!(locations[..], ["/foo/bar/9999"]);
+    }
+
+    #[test]
+    fn removes_location() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        assert_eq!(db.remove_location("/foo/bar").unwrap(), 1);
+        assert_eq!(db.remove_location("/foo/bar").unwrap(), 0);
+
+        let locations: Vec<String> = db.get_locations().unwrap();
+
+        assert_eq!(locations[..], ["/foo/doo"]);
+    }
+
+    #[test]
+    fn removes_matching_locations() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar/doo").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        assert_eq!(db.remove_matching_locations("/bar").unwrap(), 2);
+
+        let locations: Vec<String> = db.get_locations().unwrap();
+
+        assert_eq!(locations[..], ["/foo/doo"]);
+    }
+
+    #[test]
+    fn removes_many_locations() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+        db.add_location("/foo/zoo").unwrap();
+
+        assert_eq!(
+            db.remove_locations(&["/foo/bar", "/foo/zoo", "/foo/none"])
+                .unwrap(),
+            2
+        );
+
+        let locations: Vec<String> = db.get_locations().unwrap();
+
+        assert_eq!(locations[..], ["/foo/doo"]);
+    }
+
+    #[test]
+    fn recent_location_outranks_stale_frequent() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/old").unwrap();
+        db.add_location("/foo/old").unwrap();
+        db.add_location("/foo/old").unwrap();
+        db.add_location("/foo/new").unwrap();
+        db.connection
+            .execute(
+                "update jump_location set lastAccess = strftime('%s', 'now', '-30 days') where location = '/foo/old'",
+                NO_PARAMS,
+            )
+            .unwrap();
+
+        let by_rank: Vec<String> = {
+            let mut stmt = db
+                .connection
+                .prepare("select location from jump_location order by rank desc, lastAccess desc")
+                .unwrap();
+            let rows = stmt.query_map(NO_PARAMS, |row| row.get(0)).unwrap();
+            rows.collect::<Result<_, _>>().unwrap()
+        };
+        let locations: Vec<String> = db.get_matching_locations(&["foo"]).unwrap();
+
+        assert_eq!(by_rank[..], ["/foo/old", "/foo/new"]);
+        assert_eq!(locations[..], ["/foo/new", "/foo/old"]);
+    }
+
+    #[test]
+    fn ages_ranks_every_interval() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_aging(Some(Aging {
+            interval: 4,
+            factor: 0.5,
+            ..Aging::default()
+        }));
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        let ranks: Vec<f64> = {
+            let mut stmt = db
+                .connection
+                .prepare("select rank from jump_location order by location")
+                .unwrap();
+            let rows = stmt.query_map(NO_PARAMS, |row| row.get(0)).unwrap();
+            rows.collect::<Result<_, _>>().unwrap()
+        };
+
+        assert_eq!(ranks[..], [1.5, 0.5]);
+    }
+
+    #[test]
+    fn aging_drops_low_ranks() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_aging(None);
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        assert_eq!(db.age_locations(0.05).unwrap(), 1);
+
+        let locations: Vec<String> = db.get_locations().unwrap();
+
+        assert_eq!(locations[..], ["/foo/bar"]);
+    }
+
+    #[test]
+    fn matching_entries_have_scores() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        let entries: Vec<Entry> = db.get_matching_entries(&["foo"]).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "/foo/bar");
+        assert_eq!(entries[0].rank, 2.0);
+        assert_eq!(entries[0].score, 8.0);
+        assert_eq!(entries[1].score, 4.0);
+    }
+
+    #[test]
+    fn streams_matching_entries() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        for x in 0..100 {
+            db.add_location(format!("/foo/{}", x)).unwrap();
+        }
+        db.add_location("/foo/42").unwrap();
+
+        let mut seen = Vec::new();
+        db.query_each(&Query::new(&["foo"]), |e| {
+            seen.push(e.location);
+            seen.len() < 3
+        })
+        .unwrap();
+
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[0], "/foo/42");
+        assert_eq!(seen, db.get_matching_locations(&["foo"]).unwrap()[..3]);
+    }
+
+    #[test]
+    fn imports_locations_summing_ranks() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        db.add_location("/foo/bar").unwrap();
+        let imported = [
+            ImportedLocation {
+                location: "/foo/bar".to_string(),
+                rank: 2.5,
+                last_access: Some(0),
+            },
+            ImportedLocation {
+                location: "/foo/doo".to_string(),
+                rank: 1.0,
+                last_access: None,
+            },
+        ];
+        db.import_locations(&imported, Conflict::Sum).unwrap();
+
+        let entries: Vec<Entry> = db.get_matching_entries(&["foo"]).unwrap();
+
+        assert_eq!(entries[0].location, "/foo/bar");
+        assert_eq!(entries[0].rank, 3.5);
+        assert!(entries[0].last_access > 0);
+        assert_eq!(entries[1].location, "/foo/doo");
+        assert_eq!(entries[1].rank, 1.0);
+    }
+
+    #[test]
+    fn imports_with_conflict_strategy() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let imported = |rank| {
+            [ImportedLocation {
+                location: "/foo/bar".to_string(),
+                rank,
+                last_access: None,
+            }]
+        };
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+
+        db.import_locations(&imported(1.0), Conflict::Max).unwrap();
+        assert_eq!(db.get_matching_entries(&["bar"]).unwrap()[0].rank, 2.0);
+
+        db.import_locations(&imported(5.0), Conflict::Max).unwrap();
+        assert_eq!(db.get_matching_entries(&["bar"]).unwrap()[0].rank, 5.0);
+
+        db.import_locations(&imported(9.0), Conflict::KeepExisting)
+            .unwrap();
+        assert_eq!(db.get_matching_entries(&["bar"]).unwrap()[0].rank, 5.0);
+
+        db.import_locations(&imported(1.5), Conflict::Sum).unwrap();
+        assert_eq!(db.get_matching_entries(&["bar"]).unwrap()[0].rank, 6.5);
+    }
+
+    #[test]
+    fn exports_what_was_imported() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let imported = [
+            ImportedLocation {
+                location: "/foo/bar".to_string(),
+                rank: 2.5,
+                last_access: Some(1577836800),
+            },
+            ImportedLocation {
+                location: "/foo/doo".to_string(),
+                rank: 1.0,
+                last_access: Some(1577836801),
+            },
+        ];
+
+        db.import_locations(&imported, Conflict::Sum).unwrap();
+
+        assert_eq!(db.export_locations().unwrap()[..], imported);
+    }
+
+    #[test]
+    fn merges_other_database() {
+        let path = std::env::temp_dir().join(format!("jumpjump-merge-{}.db", process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut other = Database::new(Connection::open(&path).unwrap()).unwrap();
+            other.add_location("/foo/bar").unwrap();
+            other.add_location("/foo/bar").unwrap();
+            other.add_location("/foo/other").unwrap();
+            other.set_kind(Kind::File).unwrap();
+            other.add_location("/foo/notes.txt").unwrap();
+        }
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/mine").unwrap();
+
+        assert_eq!(db.merge_database(&path).unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+
+        let entries: Vec<Entry> = db.get_matching_entries(&["foo"]).unwrap();
+        let ranks: Vec<(&str, f64)> = entries
+            .iter()
+            .map(|e| (e.location.as_str(), e.rank))
+            .collect();
+
+        assert_eq!(ranks[0], ("/foo/bar", 3.0));
+        assert_eq!(ranks.len(), 3);
+        db.set_kind(Kind::File).unwrap();
+        assert_eq!(db.get_locations().unwrap(), ["/foo/notes.txt"]);
+    }
+
+    #[test]
+    fn reports_error_kinds() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        match db.remove_matching_locations("(") {
+            Err(JumpError::Pattern(_)) => (),
+            other => panic!("expected pattern error, got {:?}", other),
+        }
+
+        db.connection
+            .execute("update migration_version set version = 99", NO_PARAMS)
+            .unwrap();
+        match migrate(&db.connection, MIGRATIONS.len()) {
+            Err(JumpError::Migration(_)) => (),
+            other => panic!("expected migration error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn migrates_access_times_to_epoch() {
+        let connection = Connection::open_in_memory().unwrap();
+        add_nfc_function(&connection).unwrap();
+        migrate(&connection, 14).unwrap();
+        connection
+            .execute_batch(
+                "insert into jump_location(location, rank, lastAccess, lastLeave) values
+                     ('/src', 2, '2020-01-01 10:00:00.000', '2020-01-01 10:05:00.000'),
+                     ('/tmp', 1, '2019-12-31 23:00:00.000', null);
+                 insert into jump_tag(location, tag) values ('/src', 'code');",
+            )
+            .unwrap();
+
+        migrate(&connection, MIGRATIONS.len()).unwrap();
+
+        let (rank, access, leave, expected): (Value, Value, i64, i64) = connection
+            .query_row(
+                "select rank, lastAccess, lastLeave - lastAccess, \
+                 cast(strftime('%s', '2020-01-01 10:00:00', 'utc') as integer) \
+                 from jump_location where location = '/src'",
+                NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(rank, Value::Real(2.0));
+        assert_eq!(access, Value::Integer(expected));
+        assert_eq!(leave, 300);
+
+        let db = Database::new(connection).unwrap();
+        assert_eq!(db.get_tags(Some("/src")).unwrap().len(), 1);
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries[0].location, "/src");
+        assert_eq!(entries[0].last_access, expected);
+
+        db.add_location("/tmp").unwrap();
+        assert_eq!(db.get_entries().unwrap()[0].location, "/tmp");
+    }
+
+    #[test]
+    fn migrates_history_to_utc() {
+        let connection = Connection::open_in_memory().unwrap();
+        add_nfc_function(&connection).unwrap();
+        migrate(&connection, 15).unwrap();
+        connection
+            .execute_batch(
+                "insert into jump_history(query, location, time)
+                     values ('src', '/src', '2020-06-01 10:00:00.000');
+                 insert into jump_operation(description, time)
+                     values ('add /src', '2020-06-01 10:00:00.000');",
+            )
+            .unwrap();
+
+        migrate(&connection, MIGRATIONS.len()).unwrap();
+
+        let (time, expected): (Value, i64) = connection
+            .query_row(
+                "select time, cast(strftime('%s', '2020-06-01 10:00:00', 'utc') as integer) \
+                 from jump_history",
+                NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(time, Value::Integer(expected));
+        let converted: bool = connection
+            .query_row(
+                "select time = datetime('2020-06-01 10:00:00', 'utc') from jump_operation",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(converted);
+
+        let db = Database::new(connection).unwrap();
+        let history = db.get_history(None).unwrap();
+        assert_eq!(history[0].time, expected);
+    }
+
+    #[test]
+    fn drops_rank_index() {
+        let connection = Connection::open_in_memory().unwrap();
+        add_nfc_function(&connection).unwrap();
+        migrate(&connection, 17).unwrap();
+        connection
+            .execute_batch("create index rank_index on jump_location(host, kind, rank desc)")
+            .unwrap();
+
+        migrate(&connection, MIGRATIONS.len()).unwrap();
+
+        let indexed: bool = connection
+            .query_row(
+                "select exists (select 1 from sqlite_master where name = 'rank_index')",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!indexed);
+    }
+
+    #[test]
+    fn migrates_locations_to_nfc() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/tmp/caf\u{e9}").unwrap();
+        db.connection
+            .execute_batch(
+                "insert into jump_location(location, rank, lastAccess) values
+                     ('/tmp/cafe\u{301}', 2, 1577872800),
+                     ('/tmp/nai\u{308}ve', 1, 1577872800);
+                 insert into jump_tag(location, tag) values ('/tmp/nai\u{308}ve', 'docs');
+                 update migration_version set version = 12;",
+            )
+            .unwrap();
+
+        // Later migrations have already run on this schema.
+        migrate(&db.connection, 13).unwrap();
+        db.connection
+            .execute(
+                "update migration_version set version = ?",
+                [MIGRATIONS.len() as i64],
+            )
+            .unwrap();
+
+        let entries = db.get_entries().unwrap();
+        let ranks = entries
+            .iter()
+            .map(|e| (e.location.as_str(), e.rank))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranks,
+            vec![("/tmp/caf\u{e9}", 3.0), ("/tmp/na\u{ef}ve", 1.0)]
+        );
+        if cfg!(unix) {
+            assert_eq!(entries[1].path(), Path::new("/tmp/nai\u{308}ve"));
+        }
+        assert_eq!(
+            db.get_tags(Some("/tmp/na\u{ef}ve")).unwrap(),
+            vec![("/tmp/na\u{ef}ve".to_string(), "docs".to_string())]
+        );
+        assert_eq!(db.get_matching_locations(&["nai\u{308}"]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn optimizes_database() {
+        let path = std::env::temp_dir().join(format!("jumpjump-optimize-{}", std::process::id()));
+        let mut db = Database::new(Connection::open(&path).unwrap()).unwrap();
+        db.set_aging(None);
+        let locations = (0..2000).map(|i| format!("/src/project/{}", i));
+        db.add_locations(locations).unwrap();
+        db.connection
+            .execute_batch("delete from jump_undo; delete from jump_location;")
+            .unwrap();
+        db.connection
+            .query_row("pragma wal_checkpoint(truncate)", NO_PARAMS, |_| Ok(()))
+            .unwrap();
+        let before = std::fs::metadata(&path).unwrap().len();
+
+        db.optimize().unwrap();
+        let after = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(after < before, "{} < {}", after, before);
+    }
+
+    #[test]
+    fn summarizes_database() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+
+        assert_eq!(db.stats().unwrap().entries, 0);
+        assert_eq!(db.stats().unwrap().oldest_access, None);
+
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+
+        let stats = db.stats().unwrap();
+
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.total_rank, 3.0);
+        assert!(stats.oldest_access <= stats.newest_access);
+    }
+
+    #[test]
+    fn excludes_locations() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/tmp/foo").unwrap();
+        db.set_exclude(&["/tmp", "node_modules"]).unwrap();
+
+        db.add_location("/src/app/node_modules/x").unwrap();
+        db.add_location("/src/app").unwrap();
+
+        assert_eq!(db.get_locations().unwrap(), vec!["/src/app"]);
+        assert_eq!(db.get_matching_locations(&["foo"]).unwrap().len(), 0);
+        assert_eq!(db.stats().unwrap().entries, 2);
+    }
+
+    #[test]
+    fn pinned_locations_come_first_and_never_age() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo/often").unwrap();
+        db.add_location("/foo/often").unwrap();
+        db.add_location("/foo/pinned").unwrap();
+        db.pin_location("/foo/pinned").unwrap();
+
+        db.age_locations(0.01).unwrap();
+
+        let entries = db.get_matching_entries(&["foo"]).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, "/foo/pinned");
+        assert!(entries[0].pinned);
+        assert_eq!(entries[0].rank, 1.0);
+
+        db.add_location("/foo/often").unwrap();
+        db.add_location("/foo/often").unwrap();
+        assert_eq!(db.get_locations().unwrap()[0], "/foo/pinned");
+
+        assert_eq!(db.unpin_location("/foo/pinned").unwrap(), 1);
+        assert_eq!(db.unpin_location("/foo/pinned").unwrap(), 0);
+        assert_eq!(db.get_locations().unwrap()[0], "/foo/often");
+    }
+
+    #[test]
+    fn resolves_aliases() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/client/project").unwrap();
+
+        db.set_alias("work", "/client/project").unwrap();
+        db.set_alias("home", "/home/me").unwrap();
+
+        let work = db.get_alias_entry("work").unwrap().unwrap();
+        assert_eq!(work.location, "/client/project");
+        assert_eq!(work.rank, 1.0);
+        let home = db.get_alias_entry("home").unwrap().unwrap();
+        assert_eq!((home.location.as_str(), home.rank), ("/home/me", 0.0));
+        assert_eq!(db.get_alias_entry("play").unwrap(), None);
+        assert_eq!(db.get_aliases().unwrap()[0].0, "home");
+
+        assert_eq!(db.remove_alias("work").unwrap(), 1);
+        assert_eq!(db.get_alias_entry("work").unwrap(), None);
+    }
+
+    #[test]
+    fn scopes_matches_to_tags() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/work/src").unwrap();
+        db.add_location("/oss/src").unwrap();
+
+        assert_eq!(db.tag_location("/work/src", &["work", "rust"]).unwrap(), 2);
+        assert_eq!(db.tag_location("/dotfiles", &["dotfiles"]).unwrap(), 1);
+
+        let query = Query::new(&["src"]).with_tag(Some("work"));
+        let entries = db.query(&query).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, "/work/src");
+        assert_eq!(
+            db.get_matching_locations(&["dot"]).unwrap(),
+            vec!["/dotfiles"]
+        );
+
+        assert_eq!(db.untag_location("/work/src", &["work"]).unwrap(), 1);
+        assert!(db.query(&query).unwrap().is_empty());
+        assert_eq!(db.get_tags(Some("/work/src")).unwrap().len(), 1);
+
+        db.remove_location("/work/src").unwrap();
+        assert_eq!(db.get_tags(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn prefers_matches_in_last_component() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/home/me/srcball/old").unwrap();
+        db.add_location("/home/me/srcball/old").unwrap();
+        db.add_location("/home/me/project/src").unwrap();
+        db.add_location("/home/me/project/src").unwrap();
+        db.add_location("/home/me/srcball/old").unwrap();
+
+        let entries = db.get_matching_entries(&["src"]).unwrap();
+
+        assert_eq!(entries[0].location, "/home/me/project/src");
+        assert_eq!(entries[0].score, 2.0 * 4.0 * 2.0);
+    }
+
+    #[test]
+    fn fuzzy_scores_combine_with_frecency() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/home/me/src").unwrap();
+        db.add_location("/home/sarah/recipes").unwrap();
+
+        let query = Query::new(&["src"]).with_strategy(Strategy::Fuzzy);
+        let entries = db.query(&query).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "/home/me/src");
+        assert_eq!(entries[0].score, 4.0 * 2.0);
+    }
+
+    #[test]
+    fn blends_match_quality_with_frecency() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let ranks = [("/a/src".to_string(), 1.0), ("/a/xsrcx".to_string(), 1.2)];
+        db.set_ranks(&ranks).unwrap();
+        let query = Query::new(&["src"]);
+
+        assert_eq!(db.query(&query).unwrap()[0].location, "/a/xsrcx");
+
+        db.set_weights(Weights {
+            matching: 2.0,
+            ..Weights::default()
+        });
+        let entries = db.query(&query).unwrap();
+        assert_eq!(entries[0].location, "/a/src");
+        assert_eq!(entries[0].score, 4.0 * 2.0 * 2.0 * 2.0);
+        let regex = Query::new(&["s.c"]).with_strategy(Strategy::Regex);
+        assert_eq!(db.query(&regex).unwrap()[0].location, "/a/xsrcx");
+    }
+
+    #[test]
+    fn excludes_negated_patterns() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/src/api").unwrap();
+        db.add_location("/src/web/node_modules/api").unwrap();
+        db.add_location("/src/web/node_modules/api").unwrap();
+
+        let locations = db
+            .get_matching_locations(&["api", "!node_modules"])
+            .unwrap();
+
+        assert_eq!(locations, ["/src/api"]);
+        assert_eq!(db.get_matching_locations(&["!api"]).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn skips_cwd() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/src/api").unwrap();
+        db.add_location("/src/api").unwrap();
+        db.add_location("/src/api/v2").unwrap();
+        let query = Query::new(&["api"]).with_cwd(Some("/src/api"));
+
+        let kept = db.query(&query).unwrap();
+        let skipped = db.query(&query.with_skip_cwd(true)).unwrap();
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].location, "/src/api/v2");
+    }
+
+    #[test]
+    fn completes_last_components() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/home/projects").unwrap();
+        db.add_location("/home/projects").unwrap();
+        db.add_location("/etc/proxy-config").unwrap();
+        db.add_location("/work/projects").unwrap();
+        db.add_location("/projects/src").unwrap();
+
+        let names = db.complete(&Query::new(&["pro"])).unwrap();
+        let scoped = db.complete(&Query::new(&["work", "pro"])).unwrap();
+        let all = db.complete(&Query::new(&[""])).unwrap();
+
+        assert_eq!(names, vec!["projects", "proxy-config"]);
+        assert_eq!(scoped, vec!["projects"]);
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn sets_ranks() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo").unwrap();
+
+        let ranks = [("/foo".to_string(), 7.5), ("/bar".to_string(), 2.0)];
+        assert_eq!(db.set_ranks(&ranks).unwrap(), 2);
+
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries[0].location, "/foo");
+        assert_eq!(entries[0].rank, 7.5);
+        assert_eq!(entries[1].rank, 2.0);
+    }
+
+    #[test]
+    fn evicts_beyond_caps() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_aging(Some(Aging {
+            max_entries: Some(2),
+            ..Aging::default()
+        }));
+        for location in &["/a", "/a", "/b", "/b", "/b", "/c"] {
+            db.add_location(location).unwrap();
+        }
+        db.pin_location("/pinned").unwrap();
+        db.add_location("/d").unwrap();
+
+        let locations = db.get_locations().unwrap();
+        assert_eq!(locations, vec!["/pinned", "/b", "/a"]);
+
+        db.set_aging(Some(Aging {
+            max_rank: Some(4.0),
+            factor: 0.5,
+            ..Aging::default()
+        }));
+        db.add_location("/b").unwrap();
+
+        let total: f64 = db
+            .get_entries()
+            .unwrap()
+            .iter()
+            .filter(|e| !e.pinned)
+            .map(|e| e.rank)
+            .sum();
+        assert!((total - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn merges_duplicate_locations() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        // Stored by older versions, which did not normalize on add.
+        for location in &["/src/app", "/src/app/", "/src//app", "/src/lib/"] {
+            db.connection
+                .execute(
+                    "insert into jump_location(location, rank, lastAccess) values(?, 1, 1577836800)",
+                    &[location],
+                )
+                .unwrap();
+        }
+        db.tag_location("/src/app/", &["work"]).unwrap();
+        db.set_alias("app", "/src//app").unwrap();
+
+        let diagnosis = db.diagnose().unwrap();
+        assert!(diagnosis.integrity.is_empty());
+        assert_eq!(diagnosis.version, diagnosis.expected_version);
+        assert_eq!(
+            diagnosis.duplicates,
+            vec![(
+                "/src/app".to_string(),
+                vec![
+                    "/src//app".to_string(),
+                    "/src/app".to_string(),
+                    "/src/app/".to_string()
+                ]
+            )]
+        );
+
+        assert_eq!(db.merge_duplicates(&diagnosis.duplicates).unwrap(), 2);
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "/src/app");
+        assert_eq!(entries[0].rank, 3.0);
+        assert_eq!(db.get_tags(Some("/src/app")).unwrap().len(), 1);
+        assert_eq!(
+            db.get_alias_entry("app").unwrap().unwrap().location,
+            "/src/app"
+        );
+        assert!(db.diagnose().unwrap().is_healthy());
+    }
+
+    #[test]
+    fn tracks_files_separately() {
+        let root = std::env::temp_dir().join(format!("jumpjump-files-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let file = root.join("notes.txt");
+        std::fs::write(&file, b"").unwrap();
+
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_path(&root).unwrap();
+        db.set_kind(Kind::File).unwrap();
+        db.add_path(&file).unwrap();
+        db.add_location("/no/such/file.txt").unwrap();
+        let query = Query::default().with_require_exists(true);
+        let files = db.query(&query).unwrap();
+        let all_files = db.get_locations().unwrap().len();
+        db.remove_location(location::key(&file)).unwrap();
+        assert_eq!(
+            db.undo().unwrap(),
+            Some(format!("remove {}", location::key(&file)))
+        );
+        let restored = db.query(&query).unwrap().len();
+        db.set_kind(Kind::Directory).unwrap();
+        let dirs = db.query(&query).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path(), file);
+        assert_eq!(all_files, 2);
+        assert_eq!(restored, 1);
+        assert_eq!(dirs.len(), 1);
+        assert_eq!(dirs[0].path(), root);
+    }
+
+    #[test]
+    fn writes_kind_of_new_entries() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_kind(Kind::File).unwrap();
+        db.pin_location("/src/pinned.rs").unwrap();
+        db.tag_location("/src/tagged.rs", &["work"]).unwrap();
+        db.set_ranks(&[("/src/edited.rs".to_string(), 2.0)])
+            .unwrap();
+        let imported = ImportedLocation {
+            location: "/src/imported.rs".to_string(),
+            rank: 3.0,
+            last_access: None,
+        };
+        db.import_locations(&[imported], Conflict::Sum).unwrap();
+
+        let mut files = db.get_locations().unwrap();
+        files.sort();
+        db.set_kind(Kind::Directory).unwrap();
+
+        assert_eq!(
+            files,
+            [
+                "/src/edited.rs",
+                "/src/imported.rs",
+                "/src/pinned.rs",
+                "/src/tagged.rs"
+            ]
+        );
+        assert!(db.get_locations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn maintains_database_on_add() {
+        let root = std::env::temp_dir().join(format!("jumpjump-maintain-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_path(root.join("deleted")).unwrap();
+        db.add_location("/no/such/mount/dir").unwrap();
+
+        db.set_maintenance(1.0);
+        db.add_path(&root).unwrap();
+        let mut locations = db.get_locations().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        locations.sort();
+        assert_eq!(
+            locations,
+            vec!["/no/such/mount/dir".to_string(), location::key(&root)]
+        );
+    }
+
+    #[test]
+    fn normalizes_added_locations() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/src/app/").unwrap();
+        db.add_location("/src/lib/../app").unwrap();
+        db.add_locations(&["/src//app/."]).unwrap();
+
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, "/src/app");
+        assert_eq!(entries[0].rank, 3.0);
+    }
+
+    #[test]
+    fn records_changes_in_dry_run() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo").unwrap();
+        db.add_location("/bar").unwrap();
+        db.set_dry_run(true);
+
+        db.set_ranks(&[("/foo".to_string(), 3.0), ("/new".to_string(), 2.0)])
+            .unwrap();
+        db.remove_locations(&["/bar"]).unwrap();
+
+        assert_eq!(
+            db.take_changes(),
+            vec![
+                Change::Update {
+                    location: "/foo".to_string(),
+                    from: 1.0,
+                    to: 3.0
+                },
+                Change::Insert {
+                    location: "/new".to_string(),
+                    rank: 2.0
+                },
+                Change::Delete {
+                    location: "/bar".to_string(),
+                    rank: 1.0
+                },
+            ]
+        );
+        assert!(db.take_changes().is_empty());
+        assert_eq!(db.get_locations().unwrap().len(), 2);
+        assert_eq!(db.get_entries().unwrap()[0].rank, 1.0);
+    }
+
+    #[test]
+    fn rolls_back_pins_and_boosts_in_dry_run() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo").unwrap();
+        db.set_dry_run(true);
+
+        db.pin_location("/foo").unwrap();
+        db.pin_location("/new").unwrap();
+        assert_eq!(db.adjust_rank("/foo", 5.0).unwrap(), Some(6.0));
+        db.set_alias("f", "/foo").unwrap();
+        db.optimize().unwrap();
+
+        assert_eq!(
+            db.take_changes(),
+            vec![
+                Change::Insert {
+                    location: "/new".to_string(),
+                    rank: 1.0
+                },
+                Change::Update {
+                    location: "/foo".to_string(),
+                    from: 1.0,
+                    to: 6.0
+                },
+            ]
+        );
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].rank, 1.0);
+        assert!(!entries[0].pinned);
+        assert!(db.get_aliases().unwrap().is_empty());
+    }
+
+    #[test]
+    fn filters_by_access_time() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/recent").unwrap();
+        db.import_locations(
+            &[ImportedLocation {
+                location: "/old".to_string(),
+                rank: 1.0,
+                last_access: Some(0),
+            }],
+            Conflict::Sum,
+        )
+        .unwrap();
+        let day = Duration::from_secs(86400);
+
+        let within = db
+            .query(&Query::new(&[""; 0]).with_accessed_within(Some(day)))
+            .unwrap();
+        let before = db
+            .query(&Query::new(&[""; 0]).with_accessed_before(Some(day)))
+            .unwrap();
+
+        assert_eq!(within.len(), 1);
+        assert_eq!(within[0].location, "/recent");
+        assert_eq!(before.len(), 1);
+        assert_eq!(before[0].location, "/old");
+    }
+
+    #[test]
+    fn weighs_recency() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/recent").unwrap();
+        db.import_locations(
+            &[ImportedLocation {
+                location: "/frequent".to_string(),
+                rank: 3.0,
+                last_access: Some(0),
+            }],
+            Conflict::Sum,
+        )
+        .unwrap();
+
+        assert_eq!(db.get_locations().unwrap()[0], "/recent");
+
+        db.set_weights(Weights {
+            hour: 1.0,
+            day: 1.0,
+            week: 1.0,
+            older: 1.0,
+            dwell: 0.0,
+            ..Weights::default()
+        });
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries[0].location, "/frequent");
+        assert_eq!(entries[0].score, 3.0);
+    }
+
+    #[test]
+    fn prefers_locations_visited_longer() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/passed").unwrap();
+        db.add_location("/stayed").unwrap();
+        db.connection
+            .execute(
+                "update jump_location set lastAccess = strftime('%s', 'now', '-5 minutes')",
+                NO_PARAMS,
+            )
+            .unwrap();
+
+        assert!(db.leave_location("/stayed").unwrap());
+        assert!(!db.leave_location("/stayed").unwrap());
+        assert!(!db.leave_location("/unknown").unwrap());
+
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries[0].location, "/stayed");
+        assert!((entries[0].score / entries[1].score - 1.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn scopes_entries_to_hosts() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_host(Some("alpha")).unwrap();
+        db.add_location("/home/me/src").unwrap();
+        db.tag_location("/home/me/src", &["code"]).unwrap();
+        db.set_host(Some("beta")).unwrap();
+        db.add_location("/home/me/src").unwrap();
+        db.add_location("/home/me/src").unwrap();
+        db.add_location("/mnt/data").unwrap();
+
+        assert_eq!(
+            db.get_locations().unwrap(),
+            vec!["/home/me/src", "/mnt/data"]
+        );
+        assert_eq!(db.get_entries().unwrap()[0].rank, 2.0);
+        db.set_host(Some("alpha")).unwrap();
+        assert_eq!(db.get_locations().unwrap(), vec!["/home/me/src"]);
+        db.set_host(None).unwrap();
+        assert!(db.get_locations().unwrap().is_empty());
+
+        db.set_all_hosts(true).unwrap();
+        let entries = db.get_matching_entries(&["src"]).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].rank, 3.0);
+
+        db.set_host(Some("beta")).unwrap();
+        db.remove_location("/home/me/src").unwrap();
+        assert_eq!(db.get_entries().unwrap().len(), 2);
+        assert_eq!(db.get_tags(Some("/home/me/src")).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undoes_adds_and_removals() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo").unwrap();
+        db.add_location("/bar").unwrap();
+        db.tag_location("/bar", &["work"]).unwrap();
+        db.add_location("/bar").unwrap();
+        db.remove_location("/bar").unwrap();
+
+        assert_eq!(db.undo().unwrap(), Some("remove /bar".to_string()));
+        let entries = db.get_entries().unwrap();
+        let bar = entries.iter().find(|e| e.location == "/bar").unwrap();
+        assert_eq!(bar.rank, 2.0);
+        assert_eq!(db.get_tags(Some("/bar")).unwrap().len(), 1);
+
+        assert_eq!(db.undo().unwrap(), Some("add /bar".to_string()));
+        assert_eq!(db.undo().unwrap(), Some("add /bar".to_string()));
+        assert_eq!(db.get_locations().unwrap(), vec!["/foo"]);
+        assert_eq!(db.undo().unwrap(), Some("add /foo".to_string()));
+        assert_eq!(db.undo().unwrap(), None);
+        assert!(db.get_locations().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_recent_jumps() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_history_limit(Some(2));
+        db.record_jump("src", "/a/src").unwrap();
+        db.record_jump("doc", "/a/doc").unwrap();
+        db.record_jump("src b", "/b/src").unwrap();
+
+        let history = db.get_history(None).unwrap();
+        assert_eq!(
+            history.iter().map(|j| j.query.as_str()).collect::<Vec<_>>(),
+            vec!["src b", "doc"]
+        );
+        assert_eq!(history[0].location, "/b/src");
+        assert_eq!(db.get_history(Some(1)).unwrap().len(), 1);
+
+        db.set_history_limit(Some(0));
+        db.record_jump("src", "/a/src").unwrap();
+        assert_eq!(db.clear_history().unwrap(), 2);
+    }
+
+    #[test]
+    fn retraces_session_visits() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        for location in &["/a", "/b", "/b", "/c"] {
+            db.record_visit("1", location).unwrap();
+        }
+        db.record_visit("2", "/d").unwrap();
+
+        assert_eq!(db.get_session_locations("1").unwrap(), ["/c", "/b", "/a"]);
+        assert_eq!(db.get_session_locations("2").unwrap(), ["/d"]);
+
+        for i in 0..SESSION_LIMIT {
+            db.record_visit("1", format!("/{}", i)).unwrap();
+        }
+        let visits = db.get_session_locations("1").unwrap();
+        assert_eq!(visits.len() as i64, SESSION_LIMIT);
+        assert_eq!(visits[0], format!("/{}", SESSION_LIMIT - 1));
+    }
+
+    #[test]
+    fn pushes_and_pops_stacks() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.push_location("1", "/a").unwrap();
+        db.push_location("1", "/b").unwrap();
+        db.push_location("2", "/c").unwrap();
+
+        assert_eq!(db.get_stack("1").unwrap(), ["/b", "/a"]);
+        assert_eq!(db.pop_location("1").unwrap().as_deref(), Some("/b"));
+        assert_eq!(db.pop_location("1").unwrap().as_deref(), Some("/a"));
+        assert_eq!(db.pop_location("1").unwrap(), None);
+        assert_eq!(db.get_stack("2").unwrap(), ["/c"]);
+    }
+
+    #[test]
+    fn adjusts_ranks() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo").unwrap();
+
+        assert_eq!(db.adjust_rank("/foo", 2.5).unwrap(), Some(3.5));
+        assert_eq!(db.adjust_rank("/foo", -1.0).unwrap(), Some(2.5));
+        assert_eq!(db.adjust_rank("/foo", -10.0).unwrap(), Some(0.0));
+        assert_eq!(db.adjust_rank("/bar", 1.0).unwrap(), None);
+    }
+
+    #[test]
+    fn prefers_descendants_of_cwd() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        for location in &["/other/src", "/project/src", "/other/src", "/project/src"] {
+            db.add_location(location).unwrap();
+        }
+        db.add_location("/other/src").unwrap();
+        db.add_location("/project").unwrap();
+
+        let query = Query::new(&["src"]).with_cwd(Some("/project"));
+        let entries = db.query(&query).unwrap();
+
+        assert_eq!(entries[0].location, "/project/src");
+        assert_eq!(
+            db.get_matching_locations(&["src"]).unwrap()[0],
+            "/other/src"
+        );
+        let entries = db
+            .query(&Query::new(&["project$"]).with_cwd(Some("/project")))
+            .unwrap();
+        assert_eq!(entries[0].score, 4.0 * 2.0);
+    }
+
+    #[test]
+    fn skips_missing_locations() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let existing = std::env::temp_dir().to_string_lossy().into_owned();
+        db.add_location("/surely/missing/dir").unwrap();
+        db.add_location("/surely/missing/dir").unwrap();
+        db.add_location(&existing).unwrap();
+
+        let query = Query::default().with_require_exists(true);
+        let entries = db.query(&query).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].location, existing);
+        assert_eq!(db.query(&Query::default()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn groups_entries_by_project() {
+        let root = std::env::temp_dir().join(format!("jumpjump-projects-{}", std::process::id()));
+        let (app, lib) = (root.join("app"), root.join("lib"));
+        for dir in &[app.join(".git"), app.join("src"), lib.join(".hg")] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_path(app.join("src")).unwrap();
+        db.add_path(app.join("src")).unwrap();
+        db.add_path(&app).unwrap();
+        db.add_path(&lib).unwrap();
+        db.add_location("/surely/missing/dir").unwrap();
+
+        let projects = db.get_projects();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let projects = projects.unwrap();
+        assert_eq!(projects.len(), 2);
+        assert_eq!(projects[0].path(), app);
+        assert_eq!(projects[0].rank, 3.0);
+        assert_eq!(projects[1].path(), lib);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9"));
+
+        db.add_path(path).unwrap();
+        db.add_location("/tmp/caf%E9").unwrap();
+
+        let entries = db.get_matching_entries(&["caf"]).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].rank, 2.0);
+        assert_eq!(entries[0].path(), path);
+        db.add_path("/tmp/plain").unwrap();
+        assert_eq!(
+            db.get_matching_entries(&["plain"]).unwrap()[0].path(),
+            Path::new("/tmp/plain")
+        );
+    }
+
+    #[test]
+    fn waits_for_concurrent_writers() {
+        let path = std::env::temp_dir().join(format!("jumpjump-busy-{}.db", process::id()));
+        let db = Database::new(Connection::open(&path).unwrap()).unwrap();
+        let mode: String = db
+            .connection
+            .query_row("pragma journal_mode", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+
+        let other = Connection::open(&path).unwrap();
+        other.execute_batch("begin immediate").unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            other.execute_batch("commit").unwrap();
+        });
+        let added = db.add_location("/foo");
+        writer.join().unwrap();
+        let locations = db.get_locations();
+        drop(db);
+        for suffix in &["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+
+        assert_eq!(mode, "wal");
+        assert!(added.is_ok());
+        assert_eq!(locations.unwrap(), vec!["/foo"]);
+    }
+
+    #[test]
+    fn adds_many_locations_at_once() {
+        let mut db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.set_aging(Some(Aging {
+            interval: 4,
+            factor: 0.5,
+            ..Aging::default()
+        }));
+        db.set_exclude(&["/tmp"]).unwrap();
+
+        let added = db
+            .add_locations(&["/foo", "/bar", "/foo", "/tmp/x", "/baz"])
+            .unwrap();
+
+        assert_eq!(added, 4);
+        let entries = db.get_entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].location, "/foo");
+        assert_eq!(entries[0].rank, 1.0);
+        assert_eq!(entries[1].rank, 0.5);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io;
+use std::num::{ParseFloatError, ParseIntError};
+
+/// Errors returned by the jumpjump library.
+#[derive(Debug)]
+pub enum JumpError {
+    /// An error from SQLite.
+    Database(rusqlite::Error),
+    /// The database schema could not be read or brought up to date.
+    Migration(String),
+    /// A path could not be made absolute.
+    Path(path_abs::Error),
+    /// There is no home directory to find the default database or config in.
+    NoHomeDir,
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A pattern is not a valid regex.
+    Pattern(regex::Error),
+    /// An import, config or JSON file could not be parsed.
+    Parse(String),
+}
+
+macro_rules! parse_error {
+    ($($arg:tt)*) => {
+        $crate::JumpError::Parse(format!($($arg)*))
+    };
+}
+
+impl fmt::Display for JumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JumpError::Database(err) => write!(f, "{}", err),
+            JumpError::Migration(msg) => write!(f, "{}", msg),
+            JumpError::Path(err) => write!(f, "{}", err),
+            JumpError::NoHomeDir => write!(f, "Could not find home_dir to store jumpjump db"),
+            JumpError::Io(err) => write!(f, "{}", err),
+            JumpError::Pattern(err) => write!(f, "{}", err),
+            JumpError::Parse(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for JumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JumpError::Database(err) => Some(err),
+            JumpError::Path(err) => Some(err),
+            JumpError::Io(err) => Some(err),
+            JumpError::Pattern(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for JumpError {
+    fn from(err: rusqlite::Error) -> Self {
+        JumpError::Database(err)
+    }
+}
+
+impl From<path_abs::Error> for JumpError {
+    fn from(err: path_abs::Error) -> Self {
+        JumpError::Path(err)
+    }
+}
+
+impl From<io::Error> for JumpError {
+    fn from(err: io::Error) -> Self {
+        JumpError::Io(err)
+    }
+}
+
+impl From<regex::Error> for JumpError {
+    fn from(err: regex::Error) -> Self {
+        JumpError::Pattern(err)
+    }
+}
+
+impl From<ParseFloatError> for JumpError {
+    fn from(err: ParseFloatError) -> Self {
+        parse_error!("{}", err)
+    }
+}
+
+impl From<ParseIntError> for JumpError {
+    fn from(err: ParseIntError) -> Self {
+        parse_error!("{}", err)
+    }
+}
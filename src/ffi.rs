@@ -0,0 +1,181 @@
+//! A C ABI over the database, enabled by the `ffi` feature, so that tools written
+//! in other languages share jumpjump's matching and database format. Build the
+//! shared library with `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! ```c
+//! char **jumpjump_query(const char *db_path, const char *const *patterns,
+//!                       size_t pattern_count, size_t *count);
+//! void jumpjump_free_locations(char **locations, size_t count);
+//! int jumpjump_add(const char *db_path, const char *path);
+//! const char *jumpjump_last_error(void);
+//! ```
+//!
+//! A null `db_path` opens the default database. Functions that fail return null or
+//! -1 and leave a message for `jumpjump_last_error`.
+
+use crate::{canonicalize_path_buf, get_database_path, Database, JumpError, Query};
+use rusqlite::Connection;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, recording its error or panic for [`jumpjump_last_error`].
+fn guard<T>(f: impl FnOnce() -> Result<T, JumpError>) -> Option<T> {
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return Some(value),
+        Ok(Err(err)) => err.to_string(),
+        Err(_) => String::from("jumpjump panicked"),
+    };
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    None
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, JumpError> {
+    if s.is_null() {
+        return Err(parse_error!("Unexpected null string"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|err| parse_error!("Invalid UTF-8: {}", err))
+}
+
+unsafe fn open(db_path: *const c_char) -> Result<Database, JumpError> {
+    let path = if db_path.is_null() {
+        get_database_path()?
+    } else {
+        PathBuf::from(to_str(db_path)?)
+    };
+    Database::new(Connection::open(path)?)
+}
+
+/// Returns the locations matching `patterns` in order, best first, as an array of
+/// `*count` strings to release with [`jumpjump_free_locations`], or null on error.
+///
+/// # Safety
+///
+/// `db_path` must be null or a NUL-terminated string, `patterns` must point to
+/// `pattern_count` NUL-terminated strings and `count` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jumpjump_query(
+    db_path: *const c_char,
+    patterns: *const *const c_char,
+    pattern_count: usize,
+    count: *mut usize,
+) -> *mut *mut c_char {
+    let locations = guard(|| {
+        let patterns = if pattern_count == 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(patterns, pattern_count)
+                .iter()
+                .map(|&p| to_str(p))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let entries = open(db_path)?.query(&Query::new(patterns))?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|e| CString::new(e.location).ok())
+            .map(CString::into_raw)
+            .collect::<Vec<_>>()
+            .into_boxed_slice())
+    });
+    match locations {
+        Some(locations) => {
+            *count = locations.len();
+            Box::into_raw(locations) as *mut *mut c_char
+        }
+        None => {
+            *count = 0;
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases locations returned by [`jumpjump_query`].
+///
+/// # Safety
+///
+/// `locations` and `count` must be exactly as returned by one call to
+/// [`jumpjump_query`], and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn jumpjump_free_locations(locations: *mut *mut c_char, count: usize) {
+    if locations.is_null() {
+        return;
+    }
+    let locations = Box::from_raw(ptr::slice_from_raw_parts_mut(locations, count));
+    for &location in locations.iter() {
+        drop(CString::from_raw(location));
+    }
+}
+
+/// Records a visit to `path`, made absolute, returning 0 or -1 on error.
+///
+/// # Safety
+///
+/// `db_path` must be null or a NUL-terminated string and `path` a NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn jumpjump_add(db_path: *const c_char, path: *const c_char) -> c_int {
+    let added = guard(|| {
+        let path = canonicalize_path_buf(to_str(path)?)?;
+        open(db_path)?.add_path(path)
+    });
+    if added.is_some() {
+        0
+    } else {
+        -1
+    }
+}
+
+/// Returns the message of the last error on this thread, or null if there was
+/// none. The string is valid until the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn jumpjump_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn adds_and_queries_over_c_abi() {
+        let root = std::env::temp_dir().join(format!("jumpjump-ffi-{}", process::id()));
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        let db_path = CString::new(root.join("db.sqlite").to_str().unwrap()).unwrap();
+        let path = CString::new(root.join("project").to_str().unwrap()).unwrap();
+        let pattern = CString::new("proj").unwrap();
+
+        let (added, locations) = unsafe {
+            let added = jumpjump_add(db_path.as_ptr(), path.as_ptr());
+            let mut count = 0;
+            let raw = jumpjump_query(db_path.as_ptr(), &pattern.as_ptr(), 1, &mut count);
+            let locations = std::slice::from_raw_parts(raw, count)
+                .iter()
+                .map(|&l| CStr::from_ptr(l).to_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            jumpjump_free_locations(raw, count);
+            (added, locations)
+        };
+        let failed = unsafe { jumpjump_add(db_path.as_ptr(), ptr::null()) };
+        let error = unsafe { CStr::from_ptr(jumpjump_last_error()) };
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(added, 0);
+        assert_eq!(locations, [path.to_str().unwrap()]);
+        assert_eq!(failed, -1);
+        assert_eq!(error.to_str().unwrap(), "Unexpected null string");
+    }
+}
@@ -0,0 +1,168 @@
+//! Translation of shell style globs into regexes for excluding and matching
+//! locations.
+//!
+//! It covers `*`, `**`, `?` and `[...]` classes, not `{a,b}` alternatives.
+
+use crate::JumpError;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Translates `glob` into a regex matching a path or any of its descendants.
+///
+/// `*` and `?` never match `/` while `**` matches anything. A glob without a `/`
+/// matches a single path component anywhere, so `node_modules` excludes every
+/// `node_modules` directory.
+pub fn to_regex(glob: &str) -> Result<String, JumpError> {
+    let mut regex = String::from(if glob.contains('/') { "^" } else { "(^|/)" });
+    push_glob(&mut regex, glob)?;
+    regex.push_str("(/|$)");
+    Ok(regex)
+}
+
+/// Translates `glob` into a regex matching only the path itself, so that a glob
+/// without a `/` matches the last path component.
+pub fn to_location_regex(glob: &str) -> Result<String, JumpError> {
+    let mut regex = String::from(if glob.contains('/') { "^" } else { "(^|/)" });
+    push_glob(&mut regex, glob)?;
+    regex.push('$');
+    Ok(regex)
+}
+
+fn push_glob(regex: &mut String, glob: &str) -> Result<(), JumpError> {
+    let mut chars = glob.trim_end_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => push_class(regex, &mut chars, glob)?,
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// Translates the class whose `[` has been read, escaping every member so that
+/// none is taken as regex class syntax such as `&&` or `~~`. A `!` or `^` first
+/// negates it, a `]` first or a `-` at either end is a member, and `/` is never
+/// matched.
+fn push_class(
+    regex: &mut String,
+    chars: &mut Peekable<Chars>,
+    glob: &str,
+) -> Result<(), JumpError> {
+    let unterminated = || parse_error!("Unterminated [ in glob {}", glob);
+    let negated = chars.next_if(|c| *c == '!' || *c == '^').is_some();
+    let mut members = vec![chars.next().ok_or_else(unterminated)?];
+    loop {
+        match chars.next().ok_or_else(unterminated)? {
+            ']' => break,
+            c => members.push(c),
+        }
+    }
+    let escape = |c: char| regex::escape(&c.to_string());
+    let mut class = String::from(if negated { "[^/" } else { "[" });
+    let mut i = 0;
+    while i < members.len() {
+        match members.get(i..i + 3) {
+            Some(&[start, '-', end]) => {
+                if start > end {
+                    return Err(parse_error!(
+                        "Invalid range {}-{} in glob {}",
+                        start,
+                        end,
+                        glob
+                    ));
+                }
+                class.push_str(&format!("{}-{}", escape(start), escape(end)));
+                i += 3;
+            }
+            _ => {
+                class.push_str(&escape(members[i]));
+                i += 1;
+            }
+        }
+    }
+    class.push(']');
+    regex.push_str(&class);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Regex::new(&to_regex(glob).unwrap()).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(matches("/tmp", "/tmp"));
+        assert!(matches("/tmp/", "/tmp/foo/bar"));
+        assert!(!matches("/tmp", "/tmpfoo"));
+        assert!(!matches("/tmp", "/home/tmp"));
+        assert!(matches("node_modules", "/src/app/node_modules/left-pad"));
+        assert!(!matches("node_modules", "/src/my_node_modules"));
+        assert!(matches("/home/*/.cache", "/home/me/.cache"));
+        assert!(!matches("/home/*/.cache", "/home/me/x/.cache"));
+        assert!(matches("/home/**/.cache", "/home/me/x/.cache"));
+        assert!(matches("build-?", "/src/build-1"));
+        assert!(matches("target.[!c]", "/src/target.d"));
+        assert!(!matches("target.[!c]", "/src/target.c"));
+    }
+
+    #[test]
+    fn matches_whole_locations() {
+        let matches = |glob, path| {
+            Regex::new(&to_location_regex(glob).unwrap())
+                .unwrap()
+                .is_match(path)
+        };
+
+        assert!(matches(
+            "/home/me/src/*/backend",
+            "/home/me/src/shop/backend"
+        ));
+        assert!(!matches(
+            "/home/me/src/*/backend",
+            "/home/me/src/shop/backend/api"
+        ));
+        assert!(!matches(
+            "/home/me/src/*/backend",
+            "/home/me/src/a/b/backend"
+        ));
+        assert!(matches("back*", "/home/me/src/shop/backend"));
+        assert!(!matches("back*", "/home/me/backend/api"));
+    }
+
+    #[test]
+    fn escapes_classes() {
+        assert!(matches("v[0-9]", "/src/v7"));
+        assert!(!matches("v[0-9]", "/src/vx"));
+        assert!(matches("a[-x]", "/src/a-"));
+        assert!(matches("a[x-]", "/src/a-"));
+        assert!(matches("a[]x]", "/src/a]"));
+        assert!(matches("a[&&b]", "/src/a&"));
+        assert!(matches("a[~~b]", "/src/a~"));
+        assert!(!matches("a[~~b]", "/src/ac"));
+        assert!(matches("a[^b]", "/src/ac"));
+        assert!(!matches("/src/a[!b]c", "/src/a/c"));
+        assert!(matches(r"a[\\]", r"/src/a\"));
+    }
+
+    #[test]
+    fn rejects_invalid_classes() {
+        assert!(matches!(to_regex("/tmp/[ab"), Err(JumpError::Parse(_))));
+        assert!(matches!(to_location_regex("[]"), Err(JumpError::Parse(_))));
+        assert!(matches!(to_location_regex("[!"), Err(JumpError::Parse(_))));
+        assert!(matches!(
+            to_location_regex("[z-a]"),
+            Err(JumpError::Parse(_))
+        ));
+    }
+}
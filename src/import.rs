@@ -0,0 +1,491 @@
+//! Parsers and writers for the data files of other directory jumpers.
+
+use crate::json::{self, Value};
+use crate::JumpError;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// A location read from, or written to, another tool's data file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedLocation {
+    pub location: String,
+    pub rank: f64,
+    /// Unix timestamp of the last visit, `None` for now.
+    pub last_access: Option<i64>,
+}
+
+/// Parses z's `path|rank|timestamp` datafile.
+pub fn parse_z<R: BufRead>(reader: R) -> Result<Vec<ImportedLocation>, JumpError> {
+    let mut locations = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.rsplitn(3, '|');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(time), Some(rank), Some(location)) => locations.push(ImportedLocation {
+                location: location.to_string(),
+                rank: rank.parse()?,
+                last_access: Some(time.parse()?),
+            }),
+            _ => return Err(parse_error!("Invalid z entry on line {}: {}", n + 1, line)),
+        }
+    }
+    Ok(locations)
+}
+
+/// Parses fasd's datafile, keeping only directories as fasd tracks files too.
+pub fn parse_fasd<R: BufRead>(reader: R) -> Result<Vec<ImportedLocation>, JumpError> {
+    let mut locations = parse_z(reader)?;
+    locations.retain(|l| Path::new(&l.location).is_dir());
+    Ok(locations)
+}
+
+// autojump grows weights as sqrt(weight^2 + 10^2) per visit, so this undoes that to
+// approximate a visit count.
+fn autojump_weight_to_rank(weight: f64) -> f64 {
+    (weight / 10.0).powi(2).max(1.0)
+}
+
+/// Parses autojump's `weight<TAB>path` database.
+pub fn parse_autojump<R: BufRead>(reader: R) -> Result<Vec<ImportedLocation>, JumpError> {
+    let mut locations = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(2, '\t');
+        let (first, second) = match (fields.next(), fields.next()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => {
+                return Err(parse_error!(
+                    "Invalid autojump entry on line {}: {}",
+                    n + 1,
+                    line
+                ))
+            }
+        };
+        let (weight, location) = match first.parse::<f64>() {
+            Ok(weight) => (weight, second),
+            Err(_) => (second.parse::<f64>()?, first),
+        };
+        locations.push(ImportedLocation {
+            location: location.to_string(),
+            rank: autojump_weight_to_rank(weight),
+            last_access: None,
+        });
+    }
+    Ok(locations)
+}
+
+/// Parses the output of `zoxide query --list --score`.
+pub fn parse_zoxide<R: BufRead>(reader: R) -> Result<Vec<ImportedLocation>, JumpError> {
+    let mut locations = Vec::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim_start();
+        if line.is_empty() {
+            continue;
+        }
+        match line.find(' ') {
+            Some(i) => locations.push(ImportedLocation {
+                location: line[i + 1..].to_string(),
+                rank: line[..i].parse()?,
+                last_access: None,
+            }),
+            None => {
+                return Err(parse_error!(
+                    "Invalid zoxide entry on line {}: {}",
+                    n + 1,
+                    line
+                ))
+            }
+        }
+    }
+    Ok(locations)
+}
+
+/// Shells whose history files [`parse_history`] reads.
+pub const HISTORY_SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+/// Parses the `cd` and `pushd` commands in a bash, zsh or fish history file, counting
+/// a visit for each directory they lead to that still exists.
+///
+/// The working directory is followed from `home`, where shells usually start, so
+/// relative targets resolve best-effort; targets using variables, globs or command
+/// substitutions are skipped.
+pub fn parse_history<R: BufRead>(
+    reader: R,
+    shell: &str,
+    home: &Path,
+) -> Result<Vec<ImportedLocation>, JumpError> {
+    let commands = match shell {
+        "bash" => bash_commands(reader)?,
+        "zsh" => zsh_commands(reader)?,
+        "fish" => fish_commands(reader)?,
+        _ => return Err(parse_error!("Unknown shell {}", shell)),
+    };
+
+    let mut visits: BTreeMap<String, ImportedLocation> = BTreeMap::new();
+    let mut cwd = home.to_path_buf();
+    let mut previous = home.to_path_buf();
+    for (command, time) in commands {
+        for target in cd_targets(&command) {
+            let next = match target.as_deref() {
+                None => home.to_path_buf(),
+                Some("-") => previous.clone(),
+                Some("~") => home.to_path_buf(),
+                Some(target) if target.starts_with("~/") => home.join(&target[2..]),
+                Some(target) => cwd.join(target),
+            };
+            let location = match crate::canonicalize_path(&next) {
+                Ok(location) if Path::new(&location).is_dir() => location,
+                _ => continue,
+            };
+            previous = std::mem::replace(&mut cwd, PathBuf::from(&location));
+            let visit = visits
+                .entry(location.clone())
+                .or_insert_with(|| ImportedLocation {
+                    location,
+                    rank: 0.0,
+                    last_access: None,
+                });
+            visit.rank += 1.0;
+            visit.last_access = visit.last_access.max(time);
+        }
+    }
+    Ok(visits.into_values().collect())
+}
+
+/// Reads bash history, where `HISTTIMEFORMAT` adds `#<timestamp>` lines.
+fn bash_commands<R: BufRead>(reader: R) -> Result<Vec<(String, Option<i64>)>, JumpError> {
+    let mut commands = Vec::new();
+    let mut time = None;
+    for line in reader.lines() {
+        let line = lossy(line)?;
+        match line.strip_prefix('#').map(str::parse::<i64>) {
+            Some(Ok(t)) => time = Some(t),
+            _ => commands.push((line, time.take())),
+        }
+    }
+    Ok(commands)
+}
+
+/// Reads zsh history, in the plain or the `: <timestamp>:<duration>;command`
+/// extended format.
+fn zsh_commands<R: BufRead>(reader: R) -> Result<Vec<(String, Option<i64>)>, JumpError> {
+    let mut commands = Vec::new();
+    for line in reader.lines() {
+        let line = lossy(line)?;
+        let extended = line
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(stamp, command)| {
+                let time = stamp.split(':').next()?.trim().parse().ok()?;
+                Some((command.to_string(), Some(time)))
+            });
+        commands.push(extended.unwrap_or((line, None)));
+    }
+    Ok(commands)
+}
+
+/// Reads fish's `- cmd: <command>` / `  when: <timestamp>` history.
+fn fish_commands<R: BufRead>(reader: R) -> Result<Vec<(String, Option<i64>)>, JumpError> {
+    let mut commands: Vec<(String, Option<i64>)> = Vec::new();
+    for line in reader.lines() {
+        let line = lossy(line)?;
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            commands.push((command.replace("\\\\", "\\"), None));
+        } else if let (Some(when), Some(last)) =
+            (line.trim().strip_prefix("when: "), commands.last_mut())
+        {
+            last.1 = when.parse().ok();
+        }
+    }
+    Ok(commands)
+}
+
+/// History files are not always valid UTF-8, commands that are not are kept lossily.
+fn lossy(line: std::io::Result<String>) -> Result<String, JumpError> {
+    match line {
+        Ok(line) => Ok(line),
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidData => Ok(String::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// The targets of each `cd` or `pushd` in a command line, in order, `None` for a
+/// bare `cd`. Targets the shell would expand are left out.
+fn cd_targets(command: &str) -> Vec<Option<String>> {
+    let mut targets = Vec::new();
+    for segment in command.split([';', '&', '|']) {
+        let words = shell_words(segment.trim());
+        let mut words = words.iter().map(String::as_str);
+        match words.next() {
+            Some("cd") | Some("pushd") => (),
+            _ => continue,
+        }
+        let target = words.find(|w| !w.starts_with('-') || *w == "-");
+        match target {
+            None => targets.push(None),
+            Some(t) if t.contains(|c| "$`*?[{".contains(c)) => (),
+            Some(t) => targets.push(Some(t.to_string())),
+        }
+    }
+    targets
+}
+
+/// Splits `s` into words with simple quoting and backslash escapes.
+fn shell_words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Parses a jumpjump JSON export.
+pub fn parse_json<R: BufRead>(mut reader: R) -> Result<Vec<ImportedLocation>, JumpError> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    let entries = match json::parse(&input)? {
+        Value::Array(entries) => entries,
+        _ => return Err(parse_error!("Expected a JSON array of locations")),
+    };
+    entries
+        .iter()
+        .enumerate()
+        .map(|(n, entry)| {
+            let location = entry
+                .get("location")
+                .and_then(Value::as_str)
+                .ok_or_else(|| parse_error!("Missing location in JSON entry {}", n))?;
+            let rank = entry
+                .get("rank")
+                .and_then(Value::as_f64)
+                .ok_or_else(|| parse_error!("Missing rank in JSON entry {}", n))?;
+            Ok(ImportedLocation {
+                location: location.to_string(),
+                rank,
+                last_access: entry
+                    .get("lastAccess")
+                    .and_then(Value::as_f64)
+                    .map(|t| t as i64),
+            })
+        })
+        .collect()
+}
+
+/// Writes locations as a JSON array, readable by [`parse_json`].
+pub fn write_json<W: Write>(
+    mut writer: W,
+    locations: &[ImportedLocation],
+) -> Result<(), JumpError> {
+    writeln!(writer, "[")?;
+    for (i, l) in locations.iter().enumerate() {
+        let last_access = match l.last_access {
+            Some(t) => t.to_string(),
+            None => "null".to_string(),
+        };
+        writeln!(
+            writer,
+            "  {{\"location\": {}, \"rank\": {}, \"lastAccess\": {}}}{}",
+            json::escape(&l.location),
+            l.rank,
+            last_access,
+            if i + 1 < locations.len() { "," } else { "" }
+        )?;
+    }
+    writeln!(writer, "]")?;
+    Ok(())
+}
+
+/// Writes locations in z's datafile format.
+pub fn write_z<W: Write>(mut writer: W, locations: &[ImportedLocation]) -> Result<(), JumpError> {
+    for l in locations {
+        writeln!(
+            writer,
+            "{}|{}|{}",
+            l.location,
+            l.rank,
+            l.last_access.unwrap_or(0)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_z_datafile() {
+        let data = "/foo/bar|12.5|1577836800\n\n/foo/with|pipe|3|1577836801\n";
+
+        let locations = parse_z(data.as_bytes()).unwrap();
+
+        assert_eq!(
+            locations[..],
+            [
+                ImportedLocation {
+                    location: "/foo/bar".to_string(),
+                    rank: 12.5,
+                    last_access: Some(1577836800),
+                },
+                ImportedLocation {
+                    location: "/foo/with|pipe".to_string(),
+                    rank: 3.0,
+                    last_access: Some(1577836801),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_z_line() {
+        assert!(parse_z("/foo/bar|12".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parses_autojump_database() {
+        let data = "30.0\t/foo/bar\n/foo/doo\t10\n";
+
+        let locations = parse_autojump(data.as_bytes()).unwrap();
+
+        assert_eq!(
+            locations[..],
+            [
+                ImportedLocation {
+                    location: "/foo/bar".to_string(),
+                    rank: 9.0,
+                    last_access: None,
+                },
+                ImportedLocation {
+                    location: "/foo/doo".to_string(),
+                    rank: 1.0,
+                    last_access: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_zoxide_scores() {
+        let data = "  24.0 /foo/bar\n   4.5 /foo/with space\n";
+
+        let locations = parse_zoxide(data.as_bytes()).unwrap();
+
+        assert_eq!(locations[0].location, "/foo/bar");
+        assert_eq!(locations[0].rank, 24.0);
+        assert_eq!(locations[1].location, "/foo/with space");
+        assert_eq!(locations[1].rank, 4.5);
+    }
+
+    #[test]
+    fn parses_fasd_directories_only() {
+        let dir = std::env::temp_dir();
+        let file = file!();
+        let data = format!("{}|4|1577836800\n{}|2|1577836801\n", dir.display(), file);
+
+        let locations = parse_fasd(data.as_bytes()).unwrap();
+
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].location, dir.display().to_string());
+        assert_eq!(locations[0].rank, 4.0);
+    }
+
+    #[test]
+    fn parses_cd_commands_in_history() {
+        let home = std::env::temp_dir().join(format!("jumpjump-history-{}", std::process::id()));
+        std::fs::create_dir_all(home.join("src").join("my app")).unwrap();
+        let home = PathBuf::from(crate::canonicalize_path(&home).unwrap());
+        let src = home.join("src").display().to_string();
+        let app = home.join("src").join("my app").display().to_string();
+
+        let bash = "#1577836800\ncd src\nls\ncd 'my app' && make\ncd ..\ncd -\ncd $OLDPWD\ncd /no/such/dir\n";
+        let bash = parse_history(bash.as_bytes(), "bash", &home).unwrap();
+        let zsh = ": 1577836801:0;cd ~/src\npushd my\\ app\n";
+        let zsh = parse_history(zsh.as_bytes(), "zsh", &home).unwrap();
+        let fish = "- cmd: cd src\n  when: 1577836802\n- cmd: cd\n";
+        let fish = parse_history(fish.as_bytes(), "fish", &home).unwrap();
+        std::fs::remove_dir_all(&home).unwrap();
+
+        let summary = |locations: &[ImportedLocation]| {
+            locations
+                .iter()
+                .map(|l| (l.location.clone(), l.rank, l.last_access))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(&bash),
+            vec![
+                (src.clone(), 2.0, Some(1577836800)),
+                (app.clone(), 2.0, None)
+            ]
+        );
+        assert_eq!(
+            summary(&zsh),
+            vec![(src.clone(), 1.0, Some(1577836801)), (app, 1.0, None)]
+        );
+        assert_eq!(
+            summary(&fish),
+            vec![
+                (home.display().to_string(), 1.0, None),
+                (src, 1.0, Some(1577836802))
+            ]
+        );
+        assert!(parse_history(&b""[..], "csh", &home).is_err());
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let locations = vec![
+            ImportedLocation {
+                location: "C:\\Users\\me \"quoted\"".to_string(),
+                rank: 2.5,
+                last_access: Some(1577836800),
+            },
+            ImportedLocation {
+                location: "/foo/bar".to_string(),
+                rank: 1.0,
+                last_access: None,
+            },
+        ];
+        let mut output = Vec::new();
+
+        write_json(&mut output, &locations).unwrap();
+
+        assert_eq!(parse_json(&output[..]).unwrap(), locations);
+    }
+}
@@ -0,0 +1,16 @@
+//! Shell integration scripts printed by `jumpjump init`.
+
+/// Shells that have an integration script.
+pub const SHELLS: [&str; 5] = ["bash", "zsh", "fish", "powershell", "nu"];
+
+/// Returns the integration script for `shell`.
+pub fn script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(include_str!("init/jumpjump.bash")),
+        "zsh" => Some(include_str!("init/jumpjump.zsh")),
+        "fish" => Some(include_str!("init/jumpjump.fish")),
+        "powershell" => Some(include_str!("init/jumpjump.ps1")),
+        "nu" => Some(include_str!("init/jumpjump.nu")),
+        _ => None,
+    }
+}
@@ -0,0 +1,41 @@
+export JUMPJUMP_SESSION="$$.$RANDOM"
+
+__jumpjump_hook() {
+    if [ "${__jumpjump_pwd:-}" != "$PWD" ]; then
+        if [ -n "${__jumpjump_pwd:-}" ]; then
+            command jumpjump leave "$__jumpjump_pwd" 2>/dev/null
+        fi
+        __jumpjump_pwd="$PWD"
+        command jumpjump add "$PWD" 2>/dev/null
+    fi
+}
+
+case ";${PROMPT_COMMAND:-};" in
+    *";__jumpjump_hook;"*) ;;
+    *) PROMPT_COMMAND="__jumpjump_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+
+j() {
+    local dir
+    if [ "$*" = "-" ]; then
+        dir="$(command jumpjump back)"
+    else
+        dir="$(command jumpjump get --skip-cwd "$@")"
+    fi && [ -n "$dir" ] && cd -- "$dir"
+}
+
+jpush() {
+    command jumpjump push "$PWD" && { [ "$#" -eq 0 ] || j "$@"; }
+}
+
+jpop() {
+    local dir
+    dir="$(command jumpjump pop)" && [ -n "$dir" ] && cd -- "$dir"
+}
+
+__jumpjump_complete() {
+    local IFS=$'\n'
+    COMPREPLY=($(command jumpjump complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+
+complete -o dirnames -F __jumpjump_complete j
@@ -0,0 +1,35 @@
+set -gx JUMPJUMP_SESSION $fish_pid.(random)
+
+function __jumpjump_hook --on-variable PWD
+    set -q __jumpjump_pwd
+    and command jumpjump leave "$__jumpjump_pwd" 2>/dev/null
+    set -g __jumpjump_pwd $PWD
+    command jumpjump add "$PWD" 2>/dev/null
+end
+
+function j
+    set -l dir
+    if test "$argv" = -
+        set dir (command jumpjump back)
+    else
+        set dir (command jumpjump get --skip-cwd $argv)
+    end
+    and test -n "$dir"
+    and cd -- "$dir"
+end
+
+function jpush
+    command jumpjump push "$PWD"
+    and begin
+        test (count $argv) -eq 0
+        or j $argv
+    end
+end
+
+function jpop
+    set -l dir (command jumpjump pop)
+    and test -n "$dir"
+    and cd -- "$dir"
+end
+
+complete -c j -f -a '(command jumpjump complete -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'
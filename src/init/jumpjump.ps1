@@ -0,0 +1,50 @@
+$env:JUMPJUMP_SESSION = "$PID.$(Get-Random)"
+
+function global:__jumpjump_hook {
+    $location = Get-Location
+    if ($location.Provider.Name -ne 'FileSystem') {
+        return
+    }
+    # ProviderPath is the native path (C:\Users\me), not the PSDrive form.
+    $path = $location.ProviderPath
+    if ($path -ne $global:__jumpjump_pwd) {
+        if ($global:__jumpjump_pwd) {
+            & jumpjump leave $global:__jumpjump_pwd 2>$null | Out-Null
+        }
+        $global:__jumpjump_pwd = $path
+        & jumpjump add $path 2>$null | Out-Null
+    }
+}
+
+if (-not $global:__jumpjump_prompt) {
+    $global:__jumpjump_prompt = $function:prompt
+    function global:prompt {
+        __jumpjump_hook
+        & $global:__jumpjump_prompt
+    }
+}
+
+function global:j {
+    $dir = if ($args.Count -eq 1 -and $args[0] -eq '-') {
+        & jumpjump back
+    } else {
+        & jumpjump get --skip-cwd @args
+    }
+    if ($LASTEXITCODE -eq 0 -and $dir) {
+        Set-Location -LiteralPath $dir
+    }
+}
+
+function global:jpush {
+    & jumpjump push (Get-Location).ProviderPath
+    if ($LASTEXITCODE -eq 0 -and $args.Count -gt 0) {
+        j @args
+    }
+}
+
+function global:jpop {
+    $dir = & jumpjump pop
+    if ($LASTEXITCODE -eq 0 -and $dir) {
+        Set-Location -LiteralPath $dir
+    }
+}
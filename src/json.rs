@@ -0,0 +1,277 @@
+//! Just enough JSON for import, export and machine readable output.
+
+use crate::JumpError;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// A parsed JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Parses a complete JSON document.
+pub fn parse(input: &str) -> Result<Value, JumpError> {
+    let mut parser = Parser {
+        chars: input.char_indices().peekable(),
+        input,
+    };
+    let value = parser.value()?;
+    parser.whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some((i, _)) => Err(parse_error!("Unexpected trailing JSON at offset {}", i)),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while let Some((_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JumpError> {
+        self.whitespace();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, c)) => Err(parse_error!(
+                "Expected '{}' but found '{}' at offset {}",
+                expected,
+                c,
+                i
+            )),
+            None => Err(parse_error!(
+                "Expected '{}' but found end of JSON",
+                expected
+            )),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, JumpError> {
+        for expected in word.chars() {
+            match self.chars.next() {
+                Some((_, c)) if c == expected => (),
+                _ => return Err(parse_error!("Invalid JSON literal, expected {}", word)),
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, JumpError> {
+        self.whitespace();
+        match self.chars.peek().cloned() {
+            Some((_, '{')) => self.object(),
+            Some((_, '[')) => self.array(),
+            Some((_, '"')) => Ok(Value::String(self.string()?)),
+            Some((_, 't')) => self.literal("true", Value::Bool(true)),
+            Some((_, 'f')) => self.literal("false", Value::Bool(false)),
+            Some((_, 'n')) => self.literal("null", Value::Null),
+            Some((start, c)) if c == '-' || c.is_ascii_digit() => {
+                let mut end = start;
+                while let Some((i, c)) = self.chars.peek().cloned() {
+                    if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    self.chars.next();
+                }
+                Ok(Value::Number(self.input[start..end].parse()?))
+            }
+            Some((i, c)) => Err(parse_error!("Unexpected '{}' at offset {}", c, i)),
+            None => Err(parse_error!("Unexpected end of JSON")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JumpError> {
+        self.expect('{')?;
+        let mut map = BTreeMap::new();
+        self.whitespace();
+        if let Some((_, '}')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Object(map));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            map.insert(key, self.value()?);
+            self.whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Value::Object(map)),
+                _ => return Err(parse_error!("Expected ',' or '}}' in JSON object")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JumpError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.whitespace();
+        if let Some((_, ']')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Value::Array(values)),
+                _ => return Err(parse_error!("Expected ',' or ']' in JSON array")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JumpError> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'b')) => s.push('\u{8}'),
+                    Some((_, 'f')) => s.push('\u{c}'),
+                    Some((_, 'u')) => s.push(self.unicode_escape()?),
+                    Some((_, c)) => s.push(c),
+                    None => return Err(parse_error!("Unterminated JSON string")),
+                },
+                Some((_, c)) => s.push(c),
+                None => return Err(parse_error!("Unterminated JSON string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, JumpError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| parse_error!("Invalid \\u escape in JSON string"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, JumpError> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        std::char::from_u32(code).ok_or_else(|| parse_error!("Invalid \\u escape in JSON string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape("C:\\foo \"bar\"\n"), "\"C:\\\\foo \\\"bar\\\"\\n\"");
+        assert_eq!(escape("\u{1}"), "\"\\u0001\"");
+    }
+
+    #[test]
+    fn parses_values() {
+        let value =
+            parse(r#" [{"a": "x\"y\u00e9\ud83d\ude00", "b": -1.5e2, "c": [true, null]}, {}] "#)
+                .unwrap();
+
+        let first = match &value {
+            Value::Array(values) => &values[0],
+            _ => panic!("expected array"),
+        };
+        assert_eq!(
+            first.get("a").and_then(Value::as_str),
+            Some("x\"y\u{e9}\u{1f600}")
+        );
+        assert_eq!(first.get("b").and_then(Value::as_f64), Some(-150.0));
+        assert_eq!(
+            first.get("c"),
+            Some(&Value::Array(vec![Value::Bool(true), Value::Null]))
+        );
+    }
+
+    #[test]
+    fn round_trips_escaped_strings() {
+        let s = "tab\there \\ \"quoted\" \u{7f}";
+
+        assert_eq!(parse(&escape(s)).unwrap(), Value::String(s.to_string()));
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{\"a\" 1}").is_err());
+        assert!(parse("[1] x").is_err());
+    }
+}
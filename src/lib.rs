@@ -0,0 +1,299 @@
+//! Jump around! Jump around! Jump up, jump up and get down!
+//!
+//! jumpjump remembers the directories you visit and finds the best one for a few
+//! fragments of its path. This crate holds the database and matching so that other
+//! tools can use them directly; the `jumpjump` binary is a thin CLI over it.
+//!
+//! ```no_run
+//! use jumpjump::{canonicalize_path, get_database_path, Database};
+//! use rusqlite::Connection;
+//!
+//! # fn main() -> Result<(), jumpjump::JumpError> {
+//! let db = Database::new(Connection::open(get_database_path()?)?)?;
+//! db.add_location(canonicalize_path(".")?)?;
+//! if let Some(best) = db.get_matching_locations(&["src"])?.first() {
+//!     println!("{}", best);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use path_abs::PathAbs;
+use std::path::{Path, PathBuf};
+
+#[macro_use]
+mod error;
+#[macro_use]
+pub mod log;
+
+pub mod bench;
+pub mod config;
+mod database;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod glob;
+pub mod import;
+pub mod init;
+pub mod json;
+mod location;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+mod query;
+
+pub use database::{
+    Aging, Change, Conflict, Database, Diagnosis, Entry, Jump, Kind, Stats, Weights,
+};
+pub use error::JumpError;
+pub use query::{parse_age, Case, Query, Strategy};
+
+/// Returns the default database path, `$XDG_DATA_HOME/jumpjump/db.sqlite` or the
+/// platform data dir equivalent, moving a legacy `~/.jumpjump` there if one exists.
+pub fn get_database_path() -> Result<PathBuf, JumpError> {
+    let data_dir = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if Path::new(&dir).is_absolute() => Some(PathBuf::from(dir)),
+        _ => dirs::data_dir(),
+    };
+    resolve_database_path(data_dir, dirs::home_dir())
+}
+
+fn resolve_database_path(
+    data_dir: Option<PathBuf>,
+    home_dir: Option<PathBuf>,
+) -> Result<PathBuf, JumpError> {
+    let legacy = home_dir.map(|home| home.join(".jumpjump"));
+    let path = match data_dir {
+        Some(data_dir) => data_dir.join("jumpjump").join("db.sqlite"),
+        None => return legacy.ok_or(JumpError::NoHomeDir),
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Some(legacy) = legacy {
+        if legacy.is_file() && !path.exists() {
+            if std::fs::rename(&legacy, &path).is_err() {
+                std::fs::copy(&legacy, &path)?;
+                std::fs::remove_file(&legacy)?;
+            }
+            eprintln!("Moved {} to {}", legacy.display(), path.display());
+        }
+    }
+    Ok(path)
+}
+
+/// Name of the profile that uses the default database.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Returns the database path of `profile`, kept in a `profiles` directory next to
+/// the default database so that each profile has its own history.
+pub fn get_profile_database_path(profile: &str) -> Result<PathBuf, JumpError> {
+    let default = get_database_path()?;
+    if profile == DEFAULT_PROFILE {
+        return Ok(default);
+    }
+    let valid = |c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '.';
+    if profile.is_empty() || profile.starts_with('.') || !profile.chars().all(valid) {
+        return Err(parse_error!("Invalid profile name {:?}", profile));
+    }
+    let dir = profiles_dir(&default);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(profile).with_extension("sqlite"))
+}
+
+/// Returns the names of the profiles with a database, the default first and the
+/// rest sorted.
+pub fn get_profiles() -> Result<Vec<String>, JumpError> {
+    list_profiles(&profiles_dir(&get_database_path()?))
+}
+
+fn profiles_dir(default: &Path) -> PathBuf {
+    let legacy = default.file_name() == Some(".jumpjump".as_ref());
+    default.with_file_name(if legacy {
+        ".jumpjump-profiles"
+    } else {
+        "profiles"
+    })
+}
+
+fn list_profiles(dir: &Path) -> Result<Vec<String>, JumpError> {
+    let mut profiles = Vec::new();
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some("sqlite".as_ref()) {
+                if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                    profiles.push(name.to_string());
+                }
+            }
+        }
+    }
+    profiles.sort();
+    profiles.insert(0, DEFAULT_PROFILE.to_string());
+    Ok(profiles)
+}
+
+/// Makes `path` absolute and normalized, in the form locations are stored.
+///
+/// Paths that are not valid UTF-8 have their invalid bytes percent-encoded, use
+/// [`canonicalize_path_buf`] and [`Database::add_path`] to keep them intact.
+pub fn canonicalize_path<P: AsRef<Path>>(path: P) -> Result<String, JumpError> {
+    Ok(location::key(&canonicalize_path_buf(path)?))
+}
+
+/// Like [`canonicalize_path`] but returns the path itself.
+pub fn canonicalize_path_buf<P: AsRef<Path>>(path: P) -> Result<PathBuf, JumpError> {
+    let canonical = PathAbs::new(path.as_ref())?;
+    Ok(normalize_path(canonical.as_path()))
+}
+
+/// Like [`canonicalize_path_buf`] but resolves symlinks to the physical path,
+/// which must exist.
+pub fn resolve_symlinks<P: AsRef<Path>>(path: P) -> Result<PathBuf, JumpError> {
+    let resolved = std::fs::canonicalize(path.as_ref())?;
+    Ok(normalize_path(&resolved))
+}
+
+/// Directories whose presence marks the root of a repository.
+pub const VCS_DIRS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Returns the nearest ancestor of `path` that is a repository root, `path` itself
+/// included, or `None` when `path` is not inside a repository.
+pub fn find_vcs_root(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .find(|dir| VCS_DIRS.iter().any(|vcs| dir.join(vcs).exists()))
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(path) if cfg!(windows) => PathBuf::from(normalize_windows_path(path)),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Drops verbatim `\\?\` prefixes, which shells do not print, and uppercases the
+/// drive letter so that every spelling of a Windows path is stored the same way.
+fn normalize_windows_path(path: &str) -> String {
+    let path = match path.strip_prefix(r"\\?\") {
+        Some(unc) if unc.starts_with(r"UNC\") => format!(r"\\{}", &unc[4..]),
+        Some(local) => local.to_string(),
+        None => path.to_string(),
+    };
+    let path = path.replace('/', r"\");
+    let mut chars = path.chars();
+    match (chars.next(), chars.next()) {
+        (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => {
+            format!("{}{}", drive.to_ascii_uppercase(), &path[1..])
+        }
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn moves_legacy_database() {
+        let root = std::env::temp_dir().join(format!("jumpjump-legacy-{}", process::id()));
+        let home = root.join("home");
+        let data = root.join("data");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(home.join(".jumpjump"), b"legacy").unwrap();
+
+        let path = resolve_database_path(Some(data.clone()), Some(home.clone())).unwrap();
+        let contents = std::fs::read(&path);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(path, data.join("jumpjump").join("db.sqlite"));
+        assert_eq!(contents.unwrap(), b"legacy");
+        assert_eq!(
+            resolve_database_path(None, Some(home.clone())).unwrap(),
+            home.join(".jumpjump")
+        );
+    }
+
+    #[test]
+    fn lists_profiles() {
+        let root = std::env::temp_dir().join(format!("jumpjump-profiles-{}", process::id()));
+        let dir = profiles_dir(&root.join("db.sqlite"));
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in &["work.sqlite", "personal.sqlite", "notes.txt"] {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+
+        let profiles = list_profiles(&dir);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(profiles.unwrap(), vec!["default", "personal", "work"]);
+        assert_eq!(
+            profiles_dir(Path::new("/home/me/.jumpjump")),
+            Path::new("/home/me/.jumpjump-profiles")
+        );
+    }
+
+    #[test]
+    fn finds_vcs_roots() {
+        let root = std::env::temp_dir().join(format!("jumpjump-vcs-{}", process::id()));
+        let repo = root.join("repo");
+        let deep = repo.join("src").join("app");
+        std::fs::create_dir_all(&deep).unwrap();
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+
+        let found = find_vcs_root(&deep).map(Path::to_path_buf);
+        let itself = find_vcs_root(&repo).map(Path::to_path_buf);
+        let outside = find_vcs_root(&root).map(Path::to_path_buf);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(found, Some(repo.clone()));
+        assert_eq!(itself, Some(repo));
+        // The temp dir itself may sit inside a repository, but never below `root`.
+        assert!(outside.is_none_or(|dir| !dir.starts_with(&root)));
+    }
+
+    #[test]
+    fn normalizes_windows_paths() {
+        assert_eq!(normalize_windows_path(r"c:\Users\me"), r"C:\Users\me");
+        assert_eq!(normalize_windows_path(r"\\?\d:\src"), r"D:\src");
+        assert_eq!(normalize_windows_path("e:/src/app"), r"E:\src\app");
+        assert_eq!(
+            normalize_windows_path(r"\\?\UNC\server\share\dir"),
+            r"\\server\share\dir"
+        );
+        assert_eq!(normalize_windows_path(r"\\server\share"), r"\\server\share");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn canonicalizes_windows_paths() {
+        let cwd = std::env::current_dir().unwrap();
+        let lower = cwd.to_string_lossy().to_lowercase().replace('\\', "/");
+
+        let canonical = canonicalize_path(&lower).unwrap();
+
+        assert!(canonical.starts_with(|c: char| c.is_ascii_uppercase()));
+        assert!(!canonical.contains('/'));
+        let resolved = resolve_symlinks(&cwd).unwrap();
+        assert!(!resolved.to_string_lossy().starts_with(r"\\?\"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_symlinks_on_request() {
+        let root = std::env::temp_dir().join(format!("jumpjump-symlink-{}", process::id()));
+        let target = root.join("target");
+        let link = root.join("link");
+        std::fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let logical = canonicalize_path(&link);
+        let physical = resolve_symlinks(&link);
+        let expected = resolve_symlinks(&target);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(logical.unwrap(), link.to_string_lossy());
+        let expected = expected.unwrap();
+        assert_eq!(physical.unwrap(), expected);
+        assert_ne!(expected, link);
+    }
+}
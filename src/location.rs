@@ -0,0 +1,176 @@
+//! Conversion between paths and stored locations, which are text so that they can
+//! be matched by regex. Locations are in Unicode NFC, as macOS may return the same
+//! path decomposed. Paths that are not valid UTF-8 are stored with their invalid
+//! bytes percent-encoded, and paths that are not in NFC or not valid UTF-8 keep
+//! their original bytes alongside.
+
+use std::path::{Component, Path, PathBuf};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Returns the text stored as the location of `path`.
+#[cfg(unix)]
+pub fn key(path: &Path) -> String {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut bytes = path.as_os_str().as_bytes();
+    let mut key = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                key.push_str(valid);
+                return nfc(&key);
+            }
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                key.push_str(std::str::from_utf8(valid).unwrap());
+                let invalid = err.error_len().unwrap_or(rest.len());
+                for b in &rest[..invalid] {
+                    let _ = write!(key, "%{:02X}", b);
+                }
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn key(path: &Path) -> String {
+    nfc(&path.to_string_lossy())
+}
+
+/// Returns `text` in Unicode NFC, the form locations and patterns are compared in.
+pub fn nfc(text: &str) -> String {
+    if is_nfc(text) {
+        text.to_string()
+    } else {
+        text.nfc().collect()
+    }
+}
+
+/// Rewrites a stored location the way [`crate::canonicalize_path`] stores it now,
+/// dropping repeated and trailing separators and `.` components and resolving `..`
+/// components, without touching the filesystem.
+pub fn normalize(location: &str) -> String {
+    let mut path = PathBuf::new();
+    for component in Path::new(location).components() {
+        match (component, path.components().next_back()) {
+            (Component::ParentDir, Some(Component::Normal(_))) => {
+                path.pop();
+            }
+            // `..` at the root is the root.
+            (Component::ParentDir, Some(Component::RootDir)) => (),
+            (component, _) => path.push(component),
+        }
+    }
+    nfc(&crate::normalize_path(&path).to_string_lossy())
+}
+
+/// Whether this process may enter the directory at `path`, which on Unix needs
+/// execute permission as well as the directory existing.
+#[cfg(unix)]
+pub fn is_enterable(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    match CString::new(path.as_os_str().as_bytes()) {
+        // SAFETY: `path` is a valid NUL terminated string for the duration of the call.
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::X_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_enterable(path: &Path) -> bool {
+    path.exists()
+}
+
+/// Returns the original bytes of `path` when its key cannot represent it.
+#[cfg(unix)]
+pub fn raw(path: &Path) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    match path.to_str() {
+        Some(path) if is_nfc(path) => None,
+        _ => Some(path.as_os_str().as_bytes().to_vec()),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn raw(_path: &Path) -> Option<Vec<u8>> {
+    None
+}
+
+/// Rebuilds a path from its stored location and original bytes.
+#[cfg(unix)]
+pub fn path(location: &str, raw: Option<&[u8]>) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    match raw {
+        Some(raw) => PathBuf::from(OsStr::from_bytes(raw)),
+        None => PathBuf::from(location),
+    }
+}
+
+#[cfg(not(unix))]
+pub fn path(location: &str, _raw: Option<&[u8]>) -> PathBuf {
+    PathBuf::from(location)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn checks_directories_can_be_entered() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("jumpjump-locked-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let locked = is_enterable(&dir);
+        std::fs::remove_dir(&dir).unwrap();
+
+        assert!(is_enterable(&std::env::temp_dir()));
+        assert!(!is_enterable(Path::new("/no/such/dir")));
+        // Permissions do not stop root.
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(!locked);
+        }
+    }
+
+    #[test]
+    fn round_trips_invalid_utf8() {
+        let invalid = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9/\xff\xfe"));
+
+        assert_eq!(key(invalid), "/tmp/caf%E9/%FF%FE");
+        assert_eq!(path(&key(invalid), raw(invalid).as_deref()), invalid);
+        assert_eq!(key(Path::new("/tmp/café")), "/tmp/café");
+        assert_eq!(raw(Path::new("/tmp/café")), None);
+    }
+
+    #[test]
+    fn stores_decomposed_paths_in_nfc() {
+        let decomposed = Path::new("/tmp/cafe\u{301}");
+
+        assert_eq!(key(decomposed), "/tmp/caf\u{e9}");
+        assert_eq!(
+            path(&key(decomposed), raw(decomposed).as_deref()),
+            decomposed
+        );
+        assert_eq!(raw(Path::new("/tmp/caf\u{e9}")), None);
+    }
+
+    #[test]
+    fn normalizes_locations() {
+        assert_eq!(normalize("/tmp//src/./app/"), "/tmp/src/app");
+        assert_eq!(normalize("/tmp/cafe\u{301}"), "/tmp/caf\u{e9}");
+        assert_eq!(normalize("/tmp/src/../app/.."), "/tmp");
+        assert_eq!(normalize("/../tmp"), "/tmp");
+        assert_eq!(normalize("/tmp/caf%E9"), "/tmp/caf%E9");
+        assert_eq!(normalize("/"), "/");
+    }
+}
@@ -0,0 +1,90 @@
+//! Logging to stderr for debugging matches and ranking, enabled with `-v`, `-vv` or
+//! `$JUMPJUMP_LOG`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How much is logged, each level including those before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    /// Queries, candidate counts and how the best matches scored.
+    Debug,
+    /// Every SQL statement run.
+    Trace,
+}
+
+/// Level names accepted in `$JUMPJUMP_LOG`.
+pub const LEVELS: [&str; 3] = ["off", "debug", "trace"];
+
+static LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name.trim().to_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    /// The level for `-v` given `count` times.
+    pub fn from_verbosity(count: u64) -> Level {
+        match count {
+            0 => Level::Off,
+            1 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+}
+
+/// Sets the level for the whole process.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Whether messages at `level` are logged.
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && LEVEL.load(Ordering::Relaxed) >= level as usize
+}
+
+pub fn debug(args: fmt::Arguments) {
+    log(Level::Debug, args)
+}
+
+pub fn trace(args: fmt::Arguments) {
+    log(Level::Trace, args)
+}
+
+fn log(level: Level, args: fmt::Arguments) {
+    if enabled(level) {
+        eprintln!("jumpjump: {}", args);
+    }
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::debug(format_args!($($arg)*))
+    };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log::trace(format_args!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(Level::from_name("Debug"), Some(Level::Debug));
+        assert_eq!(Level::from_name("loud"), None);
+        assert_eq!(Level::from_verbosity(0), Level::Off);
+        assert_eq!(Level::from_verbosity(3), Level::Trace);
+        assert!(Level::Trace > Level::Debug);
+    }
+}
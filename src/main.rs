@@ -1,282 +1,2588 @@
-use clap;
-use rusqlite;
-use dirs;
-
-#[macro_use(anyhow)]
-extern crate anyhow;
-
-use anyhow::Error;
-use path_abs::PathAbs;
-use regex::Regex;
-use rusqlite::{Connection, NO_PARAMS};
-use std::collections::HashMap;
+use anyhow::{anyhow, Error};
+use rusqlite::Connection;
+use std::cmp::{Ordering, Reverse};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
 
-use itertools::join;
+use jumpjump::config::{self, Config};
+use jumpjump::log::{self, Level, LEVELS};
+use jumpjump::{bench, import, init, json, parse_age, DEFAULT_PROFILE};
+use jumpjump::{canonicalize_path, canonicalize_path_buf, get_database_path, resolve_symlinks};
+use jumpjump::{find_vcs_root, get_profile_database_path, get_profiles};
+use jumpjump::{Aging, Case, Change, Conflict, Database, Entry, JumpError, Kind, Query, Strategy};
 
-const MIGRATIONS: [&str; 3] = [
-    "
-        begin transaction;
+mod daemon;
+mod output;
+mod server;
+#[cfg(feature = "tui")]
+mod tui;
 
-        create table if not exists jump_location (id INTEGER PRIMARY KEY ASC, location STRING UNIQUE, rank INTEGER);
-        create index if not exists location_index on jump_location(location);
+use output::Format;
 
-        insert into migration_version(version) values (1);
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_AMBIGUOUS: i32 = 2;
+const EXIT_DB_ERROR: i32 = 3;
+const EXIT_ERROR: i32 = 4;
 
-        commit;
-    ",
-    "
-        begin transaction;
+const STACK_HELP: &str = "The stack belongs to JUMPJUMP_SESSION, set by the scripts from \
+jumpjump init, and is kept in the db until popped. Set it yourself to share a stack between \
+shells.";
 
-        drop table if exists temp_jump_location;
-        alter table jump_location rename to temp_jump_location;
+const EXIT_STATUS: &str = "EXIT STATUS:
+    0    A location was printed
+    1    No location matched
+    2    More than one location matched with --select-1
+    3    The database could not be opened, read or written
+    4    Any other error, such as invalid arguments";
 
-        create table jump_location (id INTEGER PRIMARY KEY ASC, location STRING UNIQUE COLLATE NOCASE, rank INTEGER);
-        create index if not exists location_index on jump_location(location);
+const MAX_CHOICES: usize = 10;
 
-        insert or ignore into jump_location
-            select id, location, rank from temp_jump_location;
+/// Set by the shell integration to a value unique to each shell, under which `add`
+/// records the shell's visits for `back`.
+const SESSION_VAR: &str = "JUMPJUMP_SESSION";
 
-        update migration_version set version = 2 where id = 1;
+/// When set, other than to `0`, `get` leaves out the working directory as if given
+/// `--skip-cwd`.
+const SKIP_CWD_VAR: &str = "JUMPJUMP_SKIP_CWD";
 
-        commit;
-    ",
-    "
-        begin transaction;
+/// Chance that an add also drops deleted directories, see `maintenance.probability`.
+const DEFAULT_MAINTENANCE_PROBABILITY: f64 = 0.01;
 
-        alter table jump_location add column lastAccess TEXT;
+type Line = fn(&Entry) -> OsString;
 
-        update jump_location set lastAccess = strftime('%Y-%m-%d %H:%M:%f', 'now', 'localtime');
+fn location_line(e: &Entry) -> OsString {
+    e.path().into()
+}
+
+/// The stable `path<TAB>score<TAB>lastAccess` line printed with `--score`.
+fn score_line(e: &Entry) -> OsString {
+    let mut line = e.path().into_os_string();
+    let last_access = output::utc_time(e.last_access);
+    line.push(format!("\t{:.2}\t{}", e.score, last_access));
+    line
+}
+
+/// Returns [`score_line`] when `--score` is given, otherwise `line`.
+fn line_for(matches: &clap::ArgMatches, line: Line) -> Line {
+    if matches.is_present("score") {
+        score_line
+    } else {
+        line
+    }
+}
+
+fn show_line(e: &Entry) -> OsString {
+    let pinned = if e.pinned { " pinned" } else { "" };
+    let last_access = output::utc_time(e.last_access);
+    format!("{} {:.2} {}{}", e.location, e.rank, last_access, pinned).into()
+}
+
+fn report_locations(
+    db: &Database,
+    limit: Option<usize>,
+    line: Line,
+    format: Format,
+) -> Result<(), Error> {
+    let mut entries = db.get_entries()?;
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    output::print_entries(&entries, format, line)?;
+    Ok(())
+}
+
+/// Prints every entry as a table, in color when asked for and printing to a terminal
+/// unless `NO_COLOR` is set.
+fn show_table(db: &Database, entries: &[Entry], color: bool) -> Result<(), Error> {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let color = color && !no_color && io::stdout().is_terminal();
+    output::print_table(entries, db.now()?, color)?;
+    Ok(())
+}
+
+const SORTS: [&str; 4] = ["score", "rank", "recent", "alpha"];
+
+/// Orders entries for `show --sort`, best, most recent or first alphabetically at
+/// the top unless `reverse`. Entries come from the db ordered by score.
+fn sort_entries(entries: &mut [Entry], sort: &str, reverse: bool) {
+    match sort {
+        "rank" => entries.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap_or(Ordering::Equal)),
+        "recent" => entries.sort_by_key(|e| Reverse(e.last_access)),
+        "alpha" => entries.sort_by(|a, b| a.location.cmp(&b.location)),
+        _ => (),
+    }
+    if reverse {
+        entries.reverse();
+    }
+}
+
+/// Prints the first `limit` entries in `sort` order, for `recent` and `frequent`.
+fn report_sorted(db: &Database, sort: &str, limit: usize, format: Format) -> Result<(), Error> {
+    let mut entries = db.get_entries()?;
+    sort_entries(&mut entries, sort, false);
+    entries.truncate(limit);
+    output::print_entries(&entries, format, location_line)?;
+    Ok(())
+}
 
-        update migration_version set version = 3 where id = 1;
+fn parse_choice(input: &str, count: usize) -> Option<usize> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Some(0);
+    }
+    match input.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= count => Some(n - 1),
+        _ => None,
+    }
+}
 
-        commit;
-    ",
-];
+fn choose_entry(entries: &[Entry]) -> Result<Option<&Entry>, Error> {
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    for (i, e) in entries.iter().enumerate() {
+        writeln!(stderr, "{:>2}) {}", i + 1, e.location)?;
+    }
+    write!(stderr, "Select [1-{}, default 1]: ", entries.len())?;
+    stderr.flush()?;
 
-struct Database {
-    connection: Connection,
+    let mut input = String::new();
+    if io::stdin().lock().read_line(&mut input)? == 0 {
+        return Ok(None);
+    }
+    match parse_choice(&input, entries.len()) {
+        Some(i) => Ok(entries.get(i)),
+        None => Err(anyhow!("Invalid selection {}", input.trim())),
+    }
 }
 
-fn get_database_path() -> Result<PathBuf, Error> {
-    if let Some(mut home) = dirs::home_dir() {
-        home.push(".jumpjump");
-        Ok(home)
+/// Builds the query for `get` from its arguments, falling back to `case`.
+fn get_query(matches: &clap::ArgMatches, case: Case) -> Result<Query, Error> {
+    let case = if matches.is_present("case-sensitive") {
+        Case::Sensitive
+    } else if matches.is_present("ignore-case") {
+        Case::Insensitive
+    } else {
+        case
+    };
+    let strategy = if matches.is_present("regex") {
+        Strategy::Regex
+    } else if matches.is_present("glob") {
+        Strategy::Glob
     } else {
-        Err(anyhow!("Could not find home_dir to store jumpjump db"))
+        matches
+            .value_of("match")
+            .and_then(Strategy::from_name)
+            .unwrap_or_default()
+    };
+    let cwd = match matches.value_of_os("cwd") {
+        Some(cwd) => Some(canonicalize_path(cwd)?),
+        // The working directory may have been removed, so it is only a preference.
+        None => std::env::current_dir()
+            .ok()
+            .and_then(|cwd| canonicalize_path(cwd).ok()),
+    };
+    // A raw regex is used as given, without expanding `~` or `$NAME`.
+    let mut patterns = matches.values_of_lossy("regex").unwrap_or_default();
+    for pattern in matches.values_of_lossy("pattern").unwrap_or_default() {
+        patterns.push(config::expand(&pattern));
     }
+    let query = Query::new(patterns)
+        .with_tag(matches.value_of("tag"))
+        .with_case(case)
+        .with_strategy(strategy)
+        .with_any(matches.is_present("any"))
+        .with_cwd(cwd)
+        .with_skip_cwd(skips_cwd(matches))
+        .with_require_exists(!matches.is_present("include-missing"))
+        .with_accessed_within(age_arg(matches, "accessed-within")?)
+        .with_accessed_before(age_arg(matches, "accessed-before")?);
+    Ok(query)
 }
 
-fn ensure_tables(dbc: &Connection) -> Result<(), Error> {
-    migrate(dbc, MIGRATIONS.len())?;
-    add_regexp_function(dbc)
+/// Whether `get` should leave out the working directory, by `--skip-cwd` or
+/// [`SKIP_CWD_VAR`].
+fn skips_cwd(matches: &clap::ArgMatches) -> bool {
+    matches.is_present("skip-cwd")
+        || std::env::var_os(SKIP_CWD_VAR).is_some_and(|v| !v.is_empty() && v != "0")
 }
 
-fn migrate(dbc: &Connection, desired_version: usize) -> Result<(), Error> {
-    dbc.execute("create table if not exists migration_version (id INTEGER PRIMARY KEY ASC, version INTEGER);", NO_PARAMS)?;
+fn age_arg(matches: &clap::ArgMatches, name: &str) -> Result<Option<Duration>, Error> {
+    Ok(matches.value_of(name).map(parse_age).transpose()?)
+}
 
-    loop {
-        let migration_version: usize = {
-            let mut stmt =
-                dbc.prepare("select version from migration_version where id = 1 limit 1")?;
-            let mut results_iter = stmt.query_map(NO_PARAMS, |row| row.get::<_, u32>(0))?;
-            match results_iter.next() {
-                None => 0,
-                Some(Ok(version)) => version as usize,
-                Some(Err(err)) => {
-                    return Err(anyhow!("Failed to get database version: {}", err))
-                }
+/// The `--accessed-within` and `--accessed-before` options shared by get and show.
+fn age_args() -> [clap::Arg<'static, 'static>; 2] {
+    [
+        clap::Arg::with_name("accessed-within")
+            .long("accessed-within")
+            .value_name("AGE")
+            .takes_value(true)
+            .help("Only locations accessed within AGE, such as 30m, 12h, 7d or 2w"),
+        clap::Arg::with_name("accessed-before")
+            .long("accessed-before")
+            .value_name("AGE")
+            .takes_value(true)
+            .help("Only locations not accessed for AGE"),
+    ]
+}
+
+/// Matches `query`, preceded by the aliased location when a single untagged
+/// pattern names an alias.
+fn matching_entries(db: &Database, query: &Query) -> Result<Vec<Entry>, Error> {
+    let mut entries = db.query(query)?;
+    if let ([name], None) = (&query.patterns[..], &query.tag) {
+        if let Some(alias) = db.get_alias_entry(name)? {
+            entries.retain(|e| e.location != alias.location);
+            entries.insert(0, alias);
+        }
+    }
+    Ok(entries)
+}
+
+fn report_best_location(
+    db: &Database,
+    query: &Query,
+    interactive: bool,
+    limit: Option<usize>,
+    line: Line,
+    format: Format,
+    print: bool,
+) -> Result<Option<PathBuf>, Error> {
+    let entries = matching_entries(db, query)?;
+    if entries.is_empty() {
+        process::exit(EXIT_NO_MATCH);
+    }
+    if let Some(limit) = limit {
+        let top = &entries[..entries.len().min(limit)];
+        output::print_entries(top, format, line)?;
+        return Ok(None);
+    }
+    let entry = if interactive && entries.len() > 1 {
+        choose_entry(&entries[..entries.len().min(MAX_CHOICES)])?
+    } else {
+        entries.first()
+    };
+    match entry {
+        Some(entry) => {
+            if print {
+                output::print_entries(std::slice::from_ref(entry), format, line)?;
             }
-        };
+            // The history is a convenience, so failing to record it is not an error.
+            let excluded = query.excluded.iter().map(|e| format!("!{}", e));
+            let typed = query.patterns.iter().cloned().chain(excluded);
+            if let Err(err) = db.record_jump(itertools::join(typed, " "), &entry.location) {
+                log::debug(format_args!("Could not record jump: {}", err));
+            }
+            Ok(Some(entry.path()))
+        }
+        None => process::exit(EXIT_NO_MATCH),
+    }
+}
 
-        if migration_version == desired_version {
-            return Ok(());
+/// A shell command running `template` with `{}` replaced by the quoted `path`,
+/// which is also in `JUMPJUMP_PATH`.
+fn shell_command(template: &str, path: &Path) -> process::Command {
+    let quoted = output::shell_quote(&output::os_bytes(path.as_os_str()));
+    let mut command = Vec::new();
+    for (i, part) in template.split("{}").enumerate() {
+        if i > 0 {
+            command.extend_from_slice(&quoted);
         }
+        command.extend_from_slice(part.as_bytes());
+    }
+    let command = output::os_string(command);
+    log::debug(format_args!("Running {}", command.to_string_lossy()));
+    let mut shell = if cfg!(windows) {
+        process::Command::new("cmd")
+    } else {
+        process::Command::new("sh")
+    };
+    shell
+        .arg(if cfg!(windows) { "/C" } else { "-c" })
+        .arg(command)
+        .env("JUMPJUMP_PATH", path);
+    shell
+}
 
-        if migration_version > MIGRATIONS.len() {
-            return Err(anyhow!(
-                "Unrecognized database version {}",
-                migration_version
-            ));
+/// Starts the `hooks.get` command for the chosen `path` without waiting for it.
+fn run_get_hook(command: &str, path: &Path) {
+    let spawned = shell_command(command, path)
+        .stdin(process::Stdio::null())
+        // Standard output is where the shell function reads the path from.
+        .stdout(process::Stdio::null())
+        .spawn();
+    if let Err(err) = spawned {
+        eprintln!("Could not run hook {}: {}", command, err);
+    }
+}
+
+/// Runs `get --exec` on the chosen `path` and exits with the command's status.
+fn exec_on(command: &str, path: &Path) -> Result<(), Error> {
+    let status = shell_command(command, path).status()?;
+    process::exit(status.code().unwrap_or(EXIT_ERROR));
+}
+
+fn report_matching_locations(
+    db: &Database,
+    query: &Query,
+    line: Line,
+    select_1: bool,
+    exit_0: bool,
+    limit: Option<usize>,
+    format: Format,
+) -> Result<(), Error> {
+    let mut entries = matching_entries(db, query)?;
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    if entries.is_empty() && exit_0 {
+        process::exit(EXIT_NO_MATCH);
+    }
+    if entries.len() == 1 && select_1 {
+        output::print_entries(&entries, format, location_line)?;
+        return Ok(());
+    }
+    output::print_entries(&entries, format, line)?;
+    if entries.len() > 1 && select_1 {
+        process::exit(EXIT_AMBIGUOUS);
+    }
+    Ok(())
+}
+
+/// Ages ranks as configured unless `--no-aging` or the `--aging-*` options say otherwise.
+fn aging_for(matches: &clap::ArgMatches, config: &Config) -> Result<Option<Aging>, Error> {
+    if matches.is_present("no-aging") || !config.aging {
+        return Ok(None);
+    }
+    let mut aging = Aging::default();
+    if let Some(factor) = matches.value_of("aging-factor") {
+        aging.factor = factor.parse()?;
+    } else if let Some(factor) = config.aging_factor {
+        aging.factor = factor;
+    }
+    if let Some(interval) = matches.value_of("aging-interval") {
+        aging.interval = interval.parse()?;
+    } else if let Some(interval) = config.aging_interval {
+        aging.interval = interval;
+    }
+    aging.max_rank = config.aging_max_rank;
+    aging.max_entries = config.aging_max_entries;
+    Ok(Some(aging))
+}
+
+fn resolves_symlinks(matches: &clap::ArgMatches, config: &Config) -> bool {
+    if matches.is_present("resolve-symlinks") {
+        true
+    } else if matches.is_present("logical") {
+        false
+    } else {
+        config.resolve_symlinks
+    }
+}
+
+/// Whether the global flag `name` was given, before or after the subcommand.
+fn global_flag(matches: &clap::ArgMatches, name: &str) -> bool {
+    match matches.subcommand() {
+        (_, Some(sub)) => sub.is_present(name),
+        _ => matches.is_present(name),
+    }
+}
+
+/// The value of the global option `name`, given before or after the subcommand.
+fn global_value<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a str> {
+    match matches.subcommand() {
+        (_, Some(sub)) => sub.value_of(name),
+        _ => matches.value_of(name),
+    }
+}
+
+fn global_value_os<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a OsStr> {
+    match matches.subcommand() {
+        (_, Some(sub)) => sub.value_of_os(name),
+        _ => matches.value_of_os(name),
+    }
+}
+
+/// Keys an SQLCipher database with the passphrase in the file at `path`, which
+/// must come before anything else reads the database.
+#[cfg(feature = "sqlcipher")]
+fn unlock(connection: &Connection, path: &Path) -> Result<(), Error> {
+    let passphrase = std::fs::read_to_string(path)?;
+    let passphrase = passphrase.trim_end_matches(&['\r', '\n'][..]);
+    connection.pragma_update(None, "key", &passphrase)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn unlock(_: &Connection, _: &Path) -> Result<(), Error> {
+    Err(anyhow!(
+        "--passphrase-file needs jumpjump built with the sqlcipher feature"
+    ))
+}
+
+#[cfg(feature = "tui")]
+fn browse(db: &Database, case: Case) -> Result<Option<PathBuf>, Error> {
+    tui::browse(db, case)
+}
+
+#[cfg(not(feature = "tui"))]
+fn browse(_: &Database, _: Case) -> Result<Option<PathBuf>, Error> {
+    Err(anyhow!("tui needs jumpjump built with the tui feature"))
+}
+
+/// Opens the database at `db_path` set up by the config and global options.
+fn open_database(
+    matches: &clap::ArgMatches,
+    config: &Config,
+    db_path: &Path,
+) -> Result<Database, Error> {
+    log::debug(format_args!("Opening {}", db_path.display()));
+    let connection = Connection::open(db_path)?;
+    if let Some(path) = global_value_os(matches, "passphrase-file") {
+        unlock(&connection, Path::new(path))?;
+    }
+    let mut db = Database::new(connection)?;
+    db.set_weights(config.weights);
+    db.set_maintenance(
+        config
+            .maintenance_probability
+            .unwrap_or(DEFAULT_MAINTENANCE_PROBABILITY),
+    );
+    if !config.history {
+        db.set_history_limit(Some(0));
+    } else if let Some(max) = config.history_max_entries {
+        db.set_history_limit(Some(max));
+    }
+    let mut exclude = config.exclude.clone();
+    if let Some(globs) = matches.values_of("exclude") {
+        exclude.extend(globs.map(|glob| config::expand_home(glob).to_string_lossy().into_owned()));
+    }
+    db.set_exclude(exclude)?;
+    if global_flag(matches, "per-host") || config.per_host {
+        let host = host_name().ok_or_else(|| anyhow!("Could not find the name of this host"))?;
+        log::debug(format_args!("Scoping entries to host {}", host));
+        db.set_host(Some(&host))?;
+    }
+    if global_flag(matches, "all-hosts") {
+        db.set_all_hosts(true)?;
+    }
+    Ok(db)
+}
+
+/// The name of this machine for `--per-host`.
+fn host_name() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .filter(|host| !host.is_empty())
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+/// The request for a running daemon when the command is a plain `add` or `get`,
+/// which the daemon answers the same way with its own configuration.
+fn daemon_request(
+    matches: &clap::ArgMatches,
+    config: &Config,
+    format: Format,
+) -> Result<Option<Vec<OsString>>, Error> {
+    if matches.is_present("exclude") || format != Format::Plain {
+        return Ok(None);
+    }
+    if global_flag(matches, "per-host") || global_flag(matches, "all-hosts") {
+        return Ok(None);
+    }
+    // Only an opened database can roll back what a dry run changes.
+    if global_flag(matches, "dry-run") {
+        return Ok(None);
+    }
+    // The daemon answers from the database it opened, which may not be this one.
+    let databases = ["db", "profile", "passphrase-file"];
+    if databases
+        .iter()
+        .any(|o| global_value_os(matches, o).is_some())
+    {
+        return Ok(None);
+    }
+    let request = match matches.subcommand() {
+        ("add", Some(matches)) => {
+            let options = [
+                "no-aging",
+                "aging-factor",
+                "aging-interval",
+                "vcs-root",
+                "files",
+            ];
+            if options.iter().any(|o| matches.is_present(o)) {
+                return Ok(None);
+            }
+            // The daemon only records the location itself.
+            if config.vcs_roots && !matches.is_present("no-vcs-root") {
+                return Ok(None);
+            }
+            let location = expand_arg(matches.value_of_os("location").unwrap());
+            let path = if resolves_symlinks(matches, config) {
+                resolve_symlinks(location)?
+            } else {
+                canonicalize_path_buf(location)?
+            };
+            let mut request = vec!["add".into(), path.into_os_string()];
+            request.extend(std::env::var_os(SESSION_VAR));
+            request
         }
+        ("get", Some(matches)) => {
+            let options = [
+                "list",
+                "limit",
+                "tag",
+                "match",
+                "cwd",
+                "include-missing",
+                "case-sensitive",
+                "ignore-case",
+                "score",
+                "select-1",
+                "exit-0",
+                "interactive",
+                "accessed-within",
+                "accessed-before",
+                "exec",
+                "explain",
+                "recent-index",
+                "any",
+                "regex",
+                "glob",
+                "files",
+            ];
+            if !matches.is_present("pattern") || options.iter().any(|o| matches.is_present(o)) {
+                return Ok(None);
+            }
+            // The daemon's working directory is not the client's.
+            let cwd = match std::env::current_dir() {
+                Ok(cwd) => canonicalize_path_buf(cwd)?,
+                Err(_) => return Ok(None),
+            };
+            let verb = if skips_cwd(matches) {
+                "get-skip-cwd"
+            } else {
+                "get"
+            };
+            let mut request = vec![verb.into(), cwd.into_os_string()];
+            request.extend(matches.values_of_os("pattern").unwrap().map(expand_arg));
+            request
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(request))
+}
 
-        dbc.execute_batch(MIGRATIONS[migration_version as usize])?;
+/// Expands `~` and environment variables in an argument the shell left alone,
+/// such as a quoted one, see [`config::expand`].
+fn expand_arg(arg: &OsStr) -> OsString {
+    match arg.to_str() {
+        Some(arg) => config::expand(arg).into(),
+        None => arg.to_owned(),
     }
 }
 
-fn add_regexp_function(db: &Connection) -> Result<(), Error> {
-    let mut cached_regexes = HashMap::new();
-    db.create_scalar_function("regexp", 2, true, move |ctx| {
-        let regex_s = ctx.get::<String>(0)?;
-        let entry = cached_regexes.entry(regex_s.clone());
-        let regex = {
-            use std::collections::hash_map::Entry::{Occupied, Vacant};
-            match entry {
-                Occupied(occ) => occ.into_mut(),
-                Vacant(vac) => match Regex::new(&regex_s) {
-                    Ok(r) => vac.insert(r),
-                    Err(err) => return Err(rusqlite::Error::UserFunctionError(Box::new(err))),
-                },
+fn add_path<P: AsRef<Path>>(
+    db: &Database,
+    path: P,
+    physical: bool,
+    vcs_roots: bool,
+) -> Result<(), Error> {
+    let abs_path = if physical {
+        resolve_symlinks(path.as_ref())?
+    } else {
+        canonicalize_path_buf(path.as_ref())?
+    };
+    db.add_path(&abs_path)?;
+    if vcs_roots {
+        match find_vcs_root(&abs_path) {
+            Some(root) if root != abs_path => db.add_path(root)?,
+            _ => (),
+        }
+    }
+    if let Some(session) = std::env::var_os(SESSION_VAR) {
+        db.record_visit(session.to_string_lossy(), canonicalize_path(&abs_path)?)?;
+    }
+    Ok(())
+}
+
+/// Returns the `n`th distinct location before `cwd` in a session's `visits`, newest
+/// first, skipping those that no longer `exist`.
+fn previous_location<'a, F>(visits: &'a [String], cwd: &str, n: usize, exists: F) -> Option<&'a str>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut seen = vec![cwd];
+    for visit in visits {
+        if seen.contains(&visit.as_str()) || !exists(visit) {
+            continue;
+        }
+        seen.push(visit);
+        if seen.len() > n {
+            return Some(visit);
+        }
+    }
+    None
+}
+
+/// The shell session from [`SESSION_VAR`], which `back` and the stack need.
+fn session() -> Result<String, Error> {
+    match std::env::var_os(SESSION_VAR) {
+        Some(session) => Ok(session.to_string_lossy().into_owned()),
+        None => Err(anyhow!("{} is not set, see jumpjump init", SESSION_VAR)),
+    }
+}
+
+fn report_previous_location(db: &Database, n: usize) -> Result<(), Error> {
+    let visits = db.get_session_locations(session()?)?;
+    let cwd = canonicalize_path(std::env::current_dir()?)?;
+    match previous_location(&visits, &cwd, n, |l| Path::new(l).is_dir()) {
+        Some(location) => output::print_path(location.as_ref())?,
+        None => process::exit(EXIT_NO_MATCH),
+    }
+    Ok(())
+}
+
+fn leave_path<P: AsRef<Path>>(db: &Database, path: P) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    db.leave_location(abs_path)?;
+    Ok(())
+}
+
+fn remove_path<P: AsRef<Path>>(db: &Database, path: P) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    let removed = db.remove_location(&abs_path)?;
+    if removed == 0 {
+        return Err(anyhow!("No entry for {}", abs_path));
+    }
+    Ok(())
+}
+
+fn pin_path<P: AsRef<Path>>(db: &Database, path: P) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    db.pin_location(abs_path)?;
+    Ok(())
+}
+
+fn unpin_path<P: AsRef<Path>>(db: &Database, path: P) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    if db.unpin_location(&abs_path)? == 0 {
+        return Err(anyhow!("{} is not pinned", abs_path));
+    }
+    Ok(())
+}
+
+fn adjust_rank<P: AsRef<Path>>(db: &Database, path: P, amount: f64) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    match db.adjust_rank(&abs_path, amount)? {
+        Some(rank) => eprintln!("Rank of {} is now {:.2}", abs_path, rank),
+        None => return Err(anyhow!("No entry for {}", abs_path)),
+    }
+    Ok(())
+}
+
+fn set_rank<P: AsRef<Path>>(db: &Database, path: P, rank: f64) -> Result<(), Error> {
+    if rank <= 0.0 {
+        return Err(anyhow!("Rank must be positive"));
+    }
+    let abs_path = canonicalize_path(path.as_ref())?;
+    db.set_ranks(&[(abs_path, rank)])?;
+    Ok(())
+}
+
+fn report_aliases(db: &Database) -> Result<(), Error> {
+    for (name, location) in db.get_aliases()? {
+        println!("{}\t{}", name, location);
+    }
+    Ok(())
+}
+
+fn set_alias<P: AsRef<Path>>(db: &Database, name: &str, path: P) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    db.set_alias(name, abs_path)?;
+    Ok(())
+}
+
+fn remove_alias(db: &Database, name: &str) -> Result<(), Error> {
+    if db.remove_alias(name)? == 0 {
+        return Err(anyhow!("No alias {}", name));
+    }
+    Ok(())
+}
+
+fn tag_path<P: AsRef<Path>>(db: &Database, path: P, tags: &[&str]) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    db.tag_location(abs_path, tags)?;
+    Ok(())
+}
+
+fn untag_path<P: AsRef<Path>>(db: &Database, path: P, tags: &[&str]) -> Result<(), Error> {
+    let abs_path = canonicalize_path(path.as_ref())?;
+    if db.untag_location(&abs_path, tags)? == 0 {
+        return Err(anyhow!("No such tags on {}", abs_path));
+    }
+    Ok(())
+}
+
+fn report_tags(db: &Database, path: Option<&str>) -> Result<(), Error> {
+    let location = path.map(canonicalize_path).transpose()?;
+    for (location, tag) in db.get_tags(location.as_deref())? {
+        println!("{}\t{}", location, tag);
+    }
+    Ok(())
+}
+
+fn remove_pattern<S: AsRef<str>>(db: &Database, pattern: S) -> Result<(), Error> {
+    let removed = db.remove_matching_locations(pattern)?;
+    eprintln!("Removed {} entries", removed);
+    Ok(())
+}
+
+/// Asks on stderr whether to go ahead, anything but `y` or `yes` declines.
+fn confirm(prompt: &str) -> Result<bool, Error> {
+    eprint!("{} [y/N]: ", prompt);
+    io::stderr().flush()?;
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input)?;
+    Ok(is_yes(&input))
+}
+
+fn is_yes(input: &str) -> bool {
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn forget_matching(db: &Database, query: &Query, yes: bool) -> Result<(), Error> {
+    let locations = db
+        .query(query)?
+        .into_iter()
+        .map(|e| e.location)
+        .collect::<Vec<_>>();
+    if locations.is_empty() {
+        process::exit(EXIT_NO_MATCH);
+    }
+    for l in locations.iter() {
+        eprintln!("{}", l);
+    }
+    if !yes && !confirm(&format!("Forget {} entries?", locations.len()))? {
+        return Ok(());
+    }
+    let removed = db.remove_locations(&locations)?;
+    eprintln!("Removed {} entries", removed);
+    Ok(())
+}
+
+fn missing_locations(db: &Database) -> Result<Vec<String>, Error> {
+    let missing = db
+        .get_entries()?
+        .into_iter()
+        .filter(|e| !e.pinned && !e.path().exists())
+        .map(|e| e.location)
+        .collect();
+    Ok(missing)
+}
+
+fn clean_missing_locations(db: &Database, dry_run: bool) -> Result<(), Error> {
+    let missing = missing_locations(db)?;
+    // A dry run reports the deletions itself.
+    if !dry_run {
+        for l in missing.iter() {
+            println!("{}", l);
+        }
+    }
+    let removed = db.remove_locations(&missing)?;
+    eprintln!("Removed {} entries", removed);
+    Ok(())
+}
+
+/// Entries below this score are offered by `clean -i` alongside missing ones.
+const DEFAULT_CLEAN_BELOW: &str = "1";
+
+/// Parses a `clean -i` selection of 1-based numbers and ranges such as `1-5 8`,
+/// where `all` selects every entry and `^N` or `^N-M` keeps entries selected
+/// otherwise. Returns the 0-based indexes in order, or `None` when invalid.
+fn parse_selection(input: &str, count: usize) -> Option<Vec<usize>> {
+    let mut selected = vec![false; count];
+    for word in input.split(|c: char| c.is_whitespace() || c == ',') {
+        if word.is_empty() {
+            continue;
+        }
+        if word == "all" {
+            selected.iter_mut().for_each(|s| *s = true);
+            continue;
+        }
+        let (keep, range) = match word.strip_prefix('^') {
+            Some(range) => (true, range),
+            None => (false, word),
+        };
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) => (first.parse::<usize>().ok()?, last.parse().ok()?),
+            None => {
+                let n = range.parse().ok()?;
+                (n, n)
             }
         };
+        if first < 1 || last < first || last > count {
+            return None;
+        }
+        selected[first - 1..last]
+            .iter_mut()
+            .for_each(|s| *s = !keep);
+    }
+    Some((0..count).filter(|&i| selected[i]).collect())
+}
+
+/// Lists missing entries and those scoring below `below`, then removes the ones
+/// picked on stdin.
+fn clean_interactively(db: &Database, below: f64) -> Result<(), Error> {
+    let candidates = db
+        .get_entries()?
+        .into_iter()
+        .filter(|e| !e.pinned)
+        .map(|e| (!e.path().exists(), e))
+        .filter(|(missing, e)| *missing || e.score < below)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        eprintln!("Nothing to clean");
+        return Ok(());
+    }
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    for (i, (missing, e)) in candidates.iter().enumerate() {
+        let why = if *missing {
+            String::from("missing")
+        } else {
+            format!("{:.2}", e.score)
+        };
+        writeln!(stderr, "{:>3}) {:>8}  {}", i + 1, why, e.location)?;
+    }
+    write!(
+        stderr,
+        "Remove which? [1-{}, ranges like 2-5, all, ^N to keep]: ",
+        candidates.len()
+    )?;
+    stderr.flush()?;
+
+    let mut input = String::new();
+    io::stdin().lock().read_line(&mut input)?;
+    let selected = parse_selection(&input, candidates.len())
+        .ok_or_else(|| anyhow!("Invalid selection {}", input.trim()))?;
+    let locations = selected
+        .into_iter()
+        .map(|i| candidates[i].1.location.as_str())
+        .collect::<Vec<_>>();
+    let removed = db.remove_locations(&locations)?;
+    eprintln!("Removed {} entries", removed);
+    Ok(())
+}
+
+/// Prints the changes a dry run would have made, one per line.
+fn report_changes(db: &Database) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for change in db.take_changes() {
+        match change {
+            Change::Insert { location, rank } => {
+                writeln!(stdout, "insert\t{}\t{:.2}", location, rank)?
+            }
+            Change::Update { location, from, to } => {
+                writeln!(stdout, "update\t{}\t{:.2}\t{:.2}", location, from, to)?
+            }
+            Change::Delete { location, rank } => {
+                writeln!(stdout, "delete\t{}\t{:.2}", location, rank)?
+            }
+        }
+    }
+    eprintln!("Dry run, nothing was changed");
+    Ok(())
+}
+
+fn doctor(db: &Database, fix: bool) -> Result<(), Error> {
+    let diagnosis = db.diagnose()?;
+    let stale = missing_locations(db)?;
+
+    if diagnosis.integrity.is_empty() {
+        println!("integrity\tok");
+    }
+    for message in diagnosis.integrity.iter() {
+        println!("integrity\t{}", message);
+    }
+    println!(
+        "schema\tversion {} of {}",
+        diagnosis.version, diagnosis.expected_version
+    );
+    for (normalized, locations) in diagnosis.duplicates.iter() {
+        println!("duplicate\t{}\t{}", normalized, locations.join("\t"));
+    }
+    for l in stale.iter() {
+        println!("stale\t{}", l);
+    }
+
+    if fix {
+        let merged = db.merge_duplicates(&diagnosis.duplicates)?;
+        let removed = db.remove_locations(&stale)?;
+        eprintln!("Merged {} duplicates, removed {} stale", merged, removed);
+    } else if !diagnosis.duplicates.is_empty() || !stale.is_empty() {
+        eprintln!("Run doctor --fix to merge duplicates and remove stale entries");
+    }
+    if !diagnosis.integrity.is_empty() || diagnosis.version != diagnosis.expected_version {
+        return Err(anyhow!("The database is damaged, restore it from a backup"));
+    }
+    Ok(())
+}
+
+fn report_stats(db: &Database, db_path: &Path, format: Format) -> Result<(), Error> {
+    let stats = db.stats()?;
+    let size = std::fs::metadata(db_path).map(|m| m.len()).unwrap_or(0);
+    let stale = missing_locations(db)?.len();
+    let top = db.get_entries()?.into_iter().take(10).collect::<Vec<_>>();
+    let oldest = stats
+        .oldest_access
+        .map(output::utc_time)
+        .unwrap_or_default();
+    let newest = stats
+        .newest_access
+        .map(output::utc_time)
+        .unwrap_or_default();
+
+    let figures = [
+        ("entries", stats.entries.to_string()),
+        ("visits", format!("{:.2}", stats.total_rank)),
+        ("size", size.to_string()),
+        ("stale", stale.to_string()),
+        ("oldest", oldest),
+        ("newest", newest),
+    ];
+    match format {
+        Format::Json => {
+            let mut fields = figures
+                .iter()
+                .map(|(name, value)| format!("  {}: {}", json::escape(name), json::escape(value)))
+                .collect::<Vec<_>>();
+            let top = top
+                .iter()
+                .map(|e| json::escape(&e.location))
+                .collect::<Vec<_>>();
+            fields.push(format!("  \"top\": [{}]", top.join(", ")));
+            println!("{{\n{}\n}}", fields.join(",\n"));
+        }
+        Format::Tsv => {
+            for (name, value) in figures.iter() {
+                println!("{}\t{}", name, value);
+            }
+            for e in top.iter() {
+                println!("top\t{}", e.location);
+            }
+        }
+        Format::Plain => {
+            for (name, value) in figures.iter() {
+                println!("{:<8}{}", name, value);
+            }
+            println!("top");
+            for e in top.iter() {
+                println!("  {:>10.2}  {}", e.score, e.location);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The final component of the best entry, a query that a real database matches.
+fn best_name(db: &Database) -> Result<Option<String>, Error> {
+    let best = db.get_entries()?.into_iter().next();
+    let name = best.and_then(|e| Some(e.path().file_name()?.to_string_lossy().into_owned()));
+    Ok(name)
+}
 
-        let text = ctx.get::<String>(1)?;
-        Ok(regex.is_match(&text))
-    })?;
+fn report_timings(timings: &[bench::Timing], format: Format) {
+    let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+    match format {
+        Format::Json => {
+            let timings = timings
+                .iter()
+                .map(|t| {
+                    format!(
+                        "  {{\"name\": {}, \"runs\": {}, \"min_ms\": {:.3}, \"mean_ms\": {:.3}}}",
+                        json::escape(&t.name),
+                        t.runs,
+                        millis(t.min),
+                        millis(t.mean)
+                    )
+                })
+                .collect::<Vec<_>>();
+            println!("[\n{}\n]", timings.join(",\n"));
+        }
+        Format::Tsv => {
+            for t in timings {
+                println!(
+                    "{}\t{}\t{:.3}\t{:.3}",
+                    t.name,
+                    t.runs,
+                    millis(t.min),
+                    millis(t.mean)
+                );
+            }
+        }
+        Format::Plain => {
+            let width = timings.iter().map(|t| t.name.len()).max().unwrap_or(0);
+            println!(
+                "{:<width$}  {:>10}  {:>10}",
+                "",
+                "min ms",
+                "mean ms",
+                width = width
+            );
+            for t in timings {
+                println!(
+                    "{:<width$}  {:>10.3}  {:>10.3}",
+                    t.name,
+                    millis(t.min),
+                    millis(t.mean),
+                    width = width
+                );
+            }
+        }
+    }
+}
 
+/// Parses `rank<TAB>path` lines written by `edit`, skipping blanks and comments.
+fn parse_edits(text: &str) -> Result<Vec<(String, f64)>, Error> {
+    let mut ranks = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (rank, location) = line
+            .split_once(|c: char| c.is_whitespace())
+            .ok_or_else(|| anyhow!("Expected rank and path on line {}", n + 1))?;
+        let rank = rank
+            .parse::<f64>()
+            .ok()
+            .filter(|rank| *rank > 0.0)
+            .ok_or_else(|| anyhow!("Invalid rank {} on line {}", rank, n + 1))?;
+        ranks.push((location.trim().to_string(), rank));
+    }
+    Ok(ranks)
+}
+
+fn run_editor(path: &Path) -> Result<(), Error> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("EDITOR is empty"))?;
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|err| anyhow!("Failed to run {}: {}", editor, err))?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", editor, status));
+    }
     Ok(())
 }
 
-fn canonicalize_path<P: AsRef<Path>>(path: P) -> Result<String, Error> {
-    let canonical = PathAbs::new(path.as_ref())?;
-    Ok(canonical.as_path().to_string_lossy().to_string())
+/// Creates the new file `name` in a new directory under the temp dir, both only
+/// readable by the user, so that other users can neither read the file nor plant
+/// a symlink where it goes. Returns the directory, to remove when done, and the
+/// open file.
+fn private_temp_file(name: &str) -> Result<(PathBuf, PathBuf, std::fs::File), Error> {
+    let mut builder = std::fs::DirBuilder::new();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        builder.mode(0o700);
+        options.mode(0o600);
+    }
+    let dir = loop {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let dir = std::env::temp_dir().join(format!("jumpjump-{}-{}", process::id(), nanos));
+        match builder.create(&dir) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            result => break result.map(|_| dir)?,
+        }
+    };
+    let path = dir.join(name);
+    match options.open(&path) {
+        Ok(file) => Ok((dir, path, file)),
+        Err(err) => {
+            let _ = std::fs::remove_dir(&dir);
+            Err(err.into())
+        }
+    }
+}
+
+/// Stores edited lines under existing locations as they are and canonicalizes
+/// added ones, which may be relative or start with `~` or `$NAME`.
+fn resolve_edits(
+    entries: &[import::ImportedLocation],
+    ranks: Vec<(String, f64)>,
+) -> Result<Vec<(String, f64)>, Error> {
+    ranks
+        .into_iter()
+        .map(|(location, rank)| {
+            if entries.iter().any(|e| e.location == location) {
+                Ok((location, rank))
+            } else {
+                Ok((canonicalize_path(config::expand(&location))?, rank))
+            }
+        })
+        .collect()
+}
+
+fn edit_locations(db: &Database) -> Result<(), Error> {
+    let entries = db.export_locations()?;
+    let mut text = String::from(
+        "# Edit ranks, add lines or delete lines, then save and quit.\n\
+         # Each line is rank<TAB>path.\n",
+    );
+    for e in &entries {
+        text.push_str(&format!("{}\t{}\n", e.rank, e.location));
+    }
+    let (dir, path, mut file) = private_temp_file("jumpjump-edit.tsv")?;
+    let written = file.write_all(text.as_bytes()).map_err(Error::from);
+    drop(file);
+
+    let edited = written
+        .and_then(|_| run_editor(&path))
+        .and_then(|_| Ok(std::fs::read_to_string(&path)?));
+    std::fs::remove_dir_all(&dir)?;
+    let ranks = resolve_edits(&entries, parse_edits(&edited?)?)?;
+
+    let kept = ranks
+        .iter()
+        .map(|(location, _)| location.as_str())
+        .collect::<std::collections::HashSet<_>>();
+    let removed = entries
+        .iter()
+        .filter(|e| !kept.contains(e.location.as_str()))
+        .map(|e| e.location.as_str())
+        .collect::<Vec<_>>();
+    let changed = ranks
+        .into_iter()
+        .filter(|(location, rank)| {
+            !entries
+                .iter()
+                .any(|e| &e.location == location && e.rank == *rank)
+        })
+        .collect::<Vec<_>>();
+
+    db.remove_locations(&removed)?;
+    db.set_ranks(&changed)?;
+    eprintln!(
+        "Removed {} entries, added or changed {}",
+        removed.len(),
+        changed.len()
+    );
+    Ok(())
+}
+
+fn default_import_path(format: &str) -> Result<PathBuf, Error> {
+    match format {
+        "z" => {
+            if let Some(path) = std::env::var_os("_Z_DATA") {
+                return Ok(PathBuf::from(path));
+            }
+            let mut home = dirs::home_dir()
+                .ok_or_else(|| anyhow!("Could not find home_dir to find z data"))?;
+            home.push(".z");
+            Ok(home)
+        }
+        "fasd" => {
+            if let Some(path) = std::env::var_os("_FASD_DATA") {
+                return Ok(PathBuf::from(path));
+            }
+            let mut home = dirs::home_dir()
+                .ok_or_else(|| anyhow!("Could not find home_dir to find fasd data"))?;
+            home.push(".fasd");
+            Ok(home)
+        }
+        "autojump" => {
+            let mut data = dirs::data_dir()
+                .ok_or_else(|| anyhow!("Could not find data_dir to find autojump data"))?;
+            data.push("autojump");
+            data.push("autojump.txt");
+            Ok(data)
+        }
+        _ => Err(anyhow!("No default file for {} import", format)),
+    }
+}
+
+fn import_zoxide(db: &Database, file: Option<&str>, conflict: Conflict) -> Result<(), Error> {
+    let locations = match file {
+        Some(file) => import::parse_zoxide(io::BufReader::new(std::fs::File::open(file)?))?,
+        None => {
+            let output = process::Command::new("zoxide")
+                .args(["query", "--list", "--score"])
+                .output()?;
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "zoxide query failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            import::parse_zoxide(&output.stdout[..])?
+        }
+    };
+    let imported = db.import_locations(&locations, conflict)?;
+    eprintln!("Imported {} entries from zoxide", imported);
+    Ok(())
 }
 
-impl Database {
-    pub fn new(connection: Connection) -> Result<Database, Error> {
-        ensure_tables(&connection)?;
-        add_regexp_function(&connection)?;
-        Ok(Database { connection })
+fn import_file(
+    db: &Database,
+    format: &str,
+    file: Option<&str>,
+    conflict: Conflict,
+) -> Result<(), Error> {
+    if format == "zoxide" {
+        return import_zoxide(db, file, conflict);
     }
+    let path = match file {
+        Some(file) => PathBuf::from(file),
+        None => default_import_path(format)?,
+    };
+    let reader: Box<dyn BufRead> = if path.as_os_str() == "-" {
+        Box::new(io::BufReader::new(io::stdin()))
+    } else {
+        Box::new(io::BufReader::new(std::fs::File::open(&path)?))
+    };
+    let locations = match format {
+        "z" => import::parse_z(reader)?,
+        "autojump" => import::parse_autojump(reader)?,
+        "fasd" => import::parse_fasd(reader)?,
+        "json" => import::parse_json(reader)?,
+        _ => return Err(anyhow!("Unknown import format {}", format)),
+    };
+    let imported = db.import_locations(&locations, conflict)?;
+    eprintln!("Imported {} entries from {}", imported, path.display());
+    Ok(())
+}
 
-    pub fn add_location<S: AsRef<str>>(&self, location: S) -> Result<(), Error> {
-        self.connection.execute(
-            "insert into jump_location(location, rank, lastAccess) values(?, 1, strftime('%Y-%m-%d %H:%M:%f', 'now', 'localtime')) \
-             on conflict(location) do update set rank=rank+1, lastAccess=strftime('%Y-%m-%d %H:%M:%f', 'now', 'localtime')",
-            &[&location.as_ref()]
-        )?;
-        Ok(())
-    }
-
-    pub fn get_locations(&self) -> Result<Vec<String>, Error> {
-        let mut stmt = self
-            .connection
-            .prepare("select location from jump_location order by rank desc, lastAccess desc")?;
-        let locations = stmt
-            .query_map(NO_PARAMS, |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(locations)
-    }
+/// Seeds the database with the `cd` targets in a shell's history, one visit each.
+fn import_history(
+    db: &Database,
+    shell: &str,
+    file: Option<&str>,
+    conflict: Conflict,
+) -> Result<(), Error> {
+    let home = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home_dir"))?;
+    let path = match (file, shell) {
+        (Some(file), _) => PathBuf::from(file),
+        (None, "bash") => home.join(".bash_history"),
+        (None, "zsh") => home.join(".zsh_history"),
+        (None, _) => dirs::data_dir()
+            .ok_or_else(|| anyhow!("Could not find data_dir to find fish history"))?
+            .join("fish")
+            .join("fish_history"),
+    };
+    let reader = io::BufReader::new(std::fs::File::open(&path)?);
+    let locations = import::parse_history(reader, shell, &home)?;
+    let imported = db.import_locations(&locations, conflict)?;
+    eprintln!("Imported {} entries from {}", imported, path.display());
+    Ok(())
+}
 
-    pub fn get_matching_locations<I>(&self, patterns: I) -> Result<Vec<String>, Error>
-    where
-        I: IntoIterator,
-        I::Item: std::fmt::Display,
-    {
-        let pattern = format!("(?i).*{}.*", join(patterns, ".*"));
-        let mut stmt = self.connection.prepare_cached(
-            "select location from jump_location where regexp(?, location) order by rank desc, lastAccess desc",
-        )?;
-        let locations = stmt
-            .query_map(&[&pattern], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(locations)
-    }
-
-    pub fn get_all_locations(&self) -> Result<Vec<String>, Error> {
-        let mut stmt = self
-            .connection
-            .prepare("select location, rank, lastAccess from jump_location order by rank desc, lastAccess desc")?;
-        let locations = stmt
-            .query_map(NO_PARAMS, |row| {
-                Ok(format!(
-                    "{} {} {}",
-                    row.get::<_, String>(0)?,
-                    row.get::<_, u32>(1)?,
-                    row.get::<_, String>(2)?
-                ))
-            })?
-            .collect::<Result<Vec<String>, _>>()?;
-
-        Ok(locations)
-    }
-}
-
-fn report_locations(db: &Database) -> Result<(), Error> {
-    let locations = db.get_locations()?;
-    for l in locations.iter() {
-        println!("{}", l);
+fn merge_database(db: &Database, path: &str) -> Result<(), Error> {
+    if !Path::new(path).is_file() {
+        return Err(anyhow!("No database at {}", path));
     }
+    let merged = db.merge_database(path)?;
+    eprintln!("Merged {} entries from {}", merged, path);
     Ok(())
 }
 
-fn report_all_locations(db: &Database) -> Result<(), Error> {
-    let locations = db.get_all_locations()?;
-    for l in locations.iter() {
-        println!("{}", l);
+fn export_locations(db: &Database, format: &str) -> Result<(), Error> {
+    let locations = db.export_locations()?;
+    let stdout = io::stdout();
+    match format {
+        "json" => import::write_json(stdout.lock(), &locations)?,
+        "z" => import::write_z(stdout.lock(), &locations)?,
+        _ => return Err(anyhow!("Unknown export format {}", format)),
     }
     Ok(())
 }
 
-fn report_best_location<I>(db: &Database, patterns: I) -> Result<(), Error>
-where
-    I: IntoIterator,
-    I::Item: std::fmt::Display,
-{
-    let locations = db.get_matching_locations(patterns)?;
-    if let Some(location) = locations.first() {
-        println!("{}", location);
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("Error: {:?}", err);
+        process::exit(exit_code(&err));
     }
-    Ok(())
 }
 
-fn add_path<P: AsRef<Path>>(db: &Database, path: P) -> Result<(), Error> {
-    let abs_path = canonicalize_path(path.as_ref())?;
-    db.add_location(abs_path)?;
-    Ok(())
+/// Database errors get their own exit status so that wrappers can report them.
+fn exit_code(err: &Error) -> i32 {
+    let db_error = err.chain().any(|e| {
+        e.is::<rusqlite::Error>()
+            || matches!(
+                e.downcast_ref::<JumpError>(),
+                Some(JumpError::Database(_)) | Some(JumpError::Migration(_))
+            )
+    });
+    if db_error {
+        EXIT_DB_ERROR
+    } else {
+        EXIT_ERROR
+    }
 }
 
-fn main() -> Result<(), Error> {
-    let matches = clap::App::new("jumpjump")
+fn app() -> clap::App<'static, 'static> {
+    clap::App::new("jumpjump")
         .version("0.1")
         .author("Leaf Garland")
         .about("Jump around! Jump around! Jump up, jump up and get down!")
         .arg(
-            clap::Arg::with_name("file")
+            clap::Arg::with_name("db")
+                .long("db")
                 .short("f")
+                .alias("file")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .env("JUMPJUMP_DB")
                 .help("Use given db file instead of default"),
         )
+        .arg(
+            clap::Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .help("Never add or return locations matching the glob, repeatable"),
+        )
+        .arg(
+            clap::Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .multiple(true)
+                .global(true)
+                .help("Log queries and scores to stderr, -vv also logs SQL"),
+        )
+        .arg(
+            clap::Arg::with_name("dry-run")
+                .long("dry-run")
+                .global(true)
+                .help("Print what a command would change without changing it"),
+        )
+        .arg(
+            clap::Arg::with_name("passphrase-file")
+                .long("passphrase-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .env("JUMPJUMP_PASSPHRASE_FILE")
+                .help("Open the db with SQLCipher using the passphrase in the given file"),
+        )
+        .arg(
+            clap::Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .takes_value(true)
+                .global(true)
+                .env("JUMPJUMP_PROFILE")
+                .help("Use the separate db of the named profile, such as work or personal"),
+        )
+        .arg(
+            clap::Arg::with_name("per-host")
+                .long("per-host")
+                .global(true)
+                .help("Track locations separately for this host, for a database shared between machines"),
+        )
+        .arg(
+            clap::Arg::with_name("all-hosts")
+                .long("all-hosts")
+                .global(true)
+                .help("Match the locations of every host, merged by location"),
+        )
+        .arg(
+            clap::Arg::with_name("socket")
+                .long("socket")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .env("JUMPJUMP_SOCKET")
+                .help("Send plain add and get to the daemon listening on this socket"),
+        )
+        .arg(
+            clap::Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .global(true)
+                .possible_values(&output::FORMATS)
+                .help("Output format for get and show, defaults to plain"),
+        )
+        .arg(
+            clap::Arg::with_name("abbrev-home")
+                .long("abbrev-home")
+                .global(true)
+                .help("Print paths under the home directory as ~/..., for display only"),
+        )
+        .arg(
+            clap::Arg::with_name("print0")
+                .long("print0")
+                .short("0")
+                .global(true)
+                .help("End plain output lines with NUL instead of newline, for xargs -0"),
+        )
+        .arg(
+            clap::Arg::with_name("quote")
+                .long("quote")
+                .global(true)
+                .help("Shell-quote plain output lines, for eval"),
+        )
         .subcommand(
             clap::SubCommand::with_name("add")
+                .alias("a")
                 .about("add location to db")
+                .arg(clap::Arg::with_name("location").required(true).index(1))
+                .arg(
+                    // `--file` is taken by the old name of `--db`.
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Record a visit to a file, such as one opened in an editor"),
+                )
+                .arg(
+                    clap::Arg::with_name("resolve-symlinks")
+                        .long("resolve-symlinks")
+                        .short("P")
+                        .overrides_with("logical")
+                        .help("Store the physical path with symlinks resolved"),
+                )
+                .arg(
+                    clap::Arg::with_name("logical")
+                        .long("logical")
+                        .short("L")
+                        .overrides_with("resolve-symlinks")
+                        .help("Store the path as given, keeping symlinks [default]"),
+                )
+                .arg(
+                    clap::Arg::with_name("vcs-root")
+                        .long("vcs-root")
+                        .overrides_with("no-vcs-root")
+                        .help("Also record the root of the repository containing the location"),
+                )
+                .arg(
+                    clap::Arg::with_name("no-vcs-root")
+                        .long("no-vcs-root")
+                        .overrides_with("vcs-root")
+                        .help("Record only the location itself, ignoring vcs_roots"),
+                )
+                .arg(
+                    clap::Arg::with_name("no-aging")
+                        .long("no-aging")
+                        .help("Do not age existing ranks"),
+                )
+                .arg(
+                    clap::Arg::with_name("aging-factor")
+                        .long("aging-factor")
+                        .takes_value(true)
+                        .help("Multiply all ranks by this factor when aging"),
+                )
+                .arg(
+                    clap::Arg::with_name("aging-interval")
+                        .long("aging-interval")
+                        .takes_value(true)
+                        .help("Age ranks once every this many adds"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("leave")
+                .about("record leaving location, so time spent there counts towards its score")
                 .arg(clap::Arg::with_name("location").required(true).index(1)),
         )
+        .subcommand(
+            clap::SubCommand::with_name("back")
+                .about("print the location this shell was in before the current one, for j -")
+                .after_help("Needs JUMPJUMP_SESSION, set by the scripts from jumpjump init.")
+                .arg(
+                    clap::Arg::with_name("n")
+                        .index(1)
+                        .default_value("1")
+                        .help("Go back N distinct locations"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("push")
+                .about("push location, the current directory by default, onto this shell's stack")
+                .after_help(STACK_HELP)
+                .arg(clap::Arg::with_name("location").index(1)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pop")
+                .about("remove the top of this shell's stack and print it")
+                .after_help(STACK_HELP),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("stack")
+                .about("list this shell's stack, top first")
+                .after_help(STACK_HELP),
+        )
         .subcommand(
             clap::SubCommand::with_name("get")
+                .alias("g")
                 .about("get recent location from db")
+                .after_help(EXIT_STATUS)
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Match files added with add --file instead of directories"),
+                )
+                .arg(
+                    clap::Arg::with_name("interactive")
+                        .long("interactive")
+                        .short("i")
+                        .help("Choose between multiple matches on stderr"),
+                )
+                .arg(
+                    clap::Arg::with_name("exec")
+                        .long("exec")
+                        .short("x")
+                        .value_name("COMMAND")
+                        .takes_value(true)
+                        .conflicts_with_all(&["list", "limit"])
+                        .help("Run COMMAND with {} replaced by the best match instead of printing it"),
+                )
+                .arg(
+                    clap::Arg::with_name("explain")
+                        .long("explain")
+                        .conflicts_with_all(&["interactive", "exec", "list", "limit"])
+                        .help("Print every match with what it matched, its score, rank and lastAccess"),
+                )
+                .arg(
+                    clap::Arg::with_name("recent-index")
+                        .long("recent-index")
+                        .takes_value(true)
+                        .value_name("N")
+                        .conflicts_with_all(&["pattern", "interactive", "list", "limit", "explain"])
+                        .help("Print the Nth most recently visited location, 1 being the latest, ignoring rank"),
+                )
+                .arg(
+                    clap::Arg::with_name("list")
+                        .long("list")
+                        .short("l")
+                        .conflicts_with("interactive")
+                        .help("Print all matches, best first, e.g. for piping into fzf"),
+                )
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .long("limit")
+                        .short("n")
+                        .takes_value(true)
+                        .value_name("N")
+                        .conflicts_with("interactive")
+                        .help("Print the best N matches, best first"),
+                )
+                .arg(
+                    clap::Arg::with_name("tag")
+                        .long("tag")
+                        .short("t")
+                        .takes_value(true)
+                        .help("Only match locations with the given tag"),
+                )
+                .arg(
+                    clap::Arg::with_name("match")
+                        .long("match")
+                        .short("m")
+                        .takes_value(true)
+                        .possible_values(&Strategy::NAMES)
+                        .help("How patterns match locations [default: substr]")
+                        .long_help(
+                            "How patterns match locations. `substr` treats patterns as \
+                             regexes that appear in order, `exact` needs each pattern to \
+                             be a whole path component and `fuzzy` finds the characters \
+                             of the patterns in order, scoring closer matches higher. \
+                             `regex` uses the patterns as regexes exactly as given and \
+                             `glob` matches whole locations against the patterns, joined \
+                             by spaces, as a shell glob such as `~/src/*/backend`.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("any")
+                        .long("any")
+                        .help("Match locations matching any one pattern rather than all in order"),
+                )
+                .arg(
+                    clap::Arg::with_name("regex")
+                        .long("regex")
+                        .short("e")
+                        .value_name("REGEX")
+                        .takes_value(true)
+                        .conflicts_with("match")
+                        .help("Match locations with REGEX as given, like --match regex"),
+                )
+                .arg(
+                    clap::Arg::with_name("glob")
+                        .long("glob")
+                        .short("g")
+                        .conflicts_with_all(&["match", "regex"])
+                        .help("Match whole locations against the patterns as a glob, like --match glob"),
+                )
+                .arg(
+                    clap::Arg::with_name("cwd")
+                        .long("cwd")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .help("Prefer locations below DIR [default: current directory]"),
+                )
+                .arg(
+                    clap::Arg::with_name("skip-cwd")
+                        .long("skip-cwd")
+                        .help("Leave out the --cwd directory itself, also set by JUMPJUMP_SKIP_CWD"),
+                )
+                .arg(
+                    clap::Arg::with_name("require-exists")
+                        .long("require-exists")
+                        .overrides_with("include-missing")
+                        .help("Skip matches that no longer exist on disk, the default"),
+                )
+                .args(&age_args())
+                .arg(
+                    clap::Arg::with_name("include-missing")
+                        .long("include-missing")
+                        .overrides_with("require-exists")
+                        .help("Include matches that no longer exist on disk"),
+                )
+                .arg(
+                    clap::Arg::with_name("case-sensitive")
+                        .long("case-sensitive")
+                        .short("s")
+                        .help("Match letter case exactly"),
+                )
+                .arg(
+                    clap::Arg::with_name("ignore-case")
+                        .long("ignore-case")
+                        .conflicts_with("case-sensitive")
+                        .help("Ignore letter case")
+                        .long_help(
+                            "Ignore letter case. By default matching ignores case unless \
+                             a pattern contains an uppercase letter, see the `case` \
+                             config option.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("score")
+                        .long("score")
+                        .help("Print tab separated path, score and lastAccess columns"),
+                )
+                .arg(
+                    clap::Arg::with_name("select-1")
+                        .long("select-1")
+                        .requires("list")
+                        .help("Print only the path when there is exactly one match")
+                        .long_help(
+                            "Print only the path when there is exactly one match. \
+                             When there is more than one match the list is printed and \
+                             the exit status is 2, so a shell function can hand the list \
+                             to fzf only when a choice is needed.",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("exit-0")
+                        .long("exit-0")
+                        .requires("list")
+                        .help("Exit with status 1 when there are no matches"),
+                )
+                .arg(
+                    clap::Arg::with_name("pattern")
+                        .multiple(true)
+                        .index(1)
+                        .help("Fragments to match in order, !fragment excludes locations matching it")
+                        .long_help(
+                            "Fragments to match in order. A fragment starting with ! \
+                             excludes the locations it would match, as in \
+                             `j api '!node_modules'`, quoted in shells that expand !.",
+                        ),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("complete")
+                .about("print names completing the last pattern, for shell completion")
                 .arg(clap::Arg::with_name("pattern").multiple(true).index(1)),
         )
         .subcommand(
             clap::SubCommand::with_name("show")
-                .about("show all db entries"),
+                .aliases(&["ls", "list"])
+                .about("show all db entries")
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Show files added with add --file instead of directories"),
+                )
+                .arg(
+                    clap::Arg::with_name("score")
+                        .long("score")
+                        .help("Print tab separated path, score and lastAccess columns"),
+                )
+                .args(&age_args())
+                .arg(
+                    clap::Arg::with_name("sort")
+                        .long("sort")
+                        .takes_value(true)
+                        .possible_values(&SORTS)
+                        .default_value("score")
+                        .help("Order by frecency score, rank, last access or path"),
+                )
+                .arg(
+                    clap::Arg::with_name("reverse")
+                        .long("reverse")
+                        .short("r")
+                        .help("Reverse the order"),
+                )
+                .arg(
+                    clap::Arg::with_name("porcelain")
+                        .long("porcelain")
+                        .help("Print the stable path, rank and lastAccess format for scripts"),
+                )
+                .arg(
+                    clap::Arg::with_name("tree")
+                        .long("tree")
+                        .conflicts_with_all(&["score", "porcelain"])
+                        .help("Group entries under their shared ancestors with summed scores"),
+                )
+                .arg(
+                    clap::Arg::with_name("no-color")
+                        .long("no-color")
+                        .help("Do not highlight the highest ranked entries, also set by NO_COLOR"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("recent")
+                .about("list the most recently visited locations, ignoring rank")
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .long("limit")
+                        .short("n")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .help("Print the N most recent locations"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("projects")
+                .about("list the repository roots of known locations, best first")
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .long("limit")
+                        .short("n")
+                        .takes_value(true)
+                        .value_name("N")
+                        .help("Print the best N projects"),
+                )
+                .arg(
+                    clap::Arg::with_name("score")
+                        .long("score")
+                        .help("Print tab separated path, score and lastAccess columns"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("history")
+                .about("list the locations chosen by get, newest first")
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .long("limit")
+                        .short("n")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("20")
+                        .help("Print the N most recent jumps"),
+                )
+                .arg(
+                    clap::Arg::with_name("clear")
+                        .long("clear")
+                        .conflicts_with("limit")
+                        .help("Forget every recorded jump"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("frequent")
+                .about("list the most visited locations, ignoring recency")
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .long("limit")
+                        .short("n")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .help("Print the N most visited locations"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("remove")
+                .alias("rm")
+                .about("remove location from db")
+                .arg(
+                    clap::Arg::with_name("pattern")
+                        .long("pattern")
+                        .short("p")
+                        .takes_value(true)
+                        .help("Remove all locations matching the given regex"),
+                )
+                .arg(
+                    clap::Arg::with_name("location")
+                        .index(1)
+                        .required_unless("pattern")
+                        .conflicts_with("pattern"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("undo")
+                .about("revert the most recent add or removal"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("forget")
+                .about("remove every location matching the patterns, as get matches them")
+                .arg(
+                    clap::Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Do not ask before removing"),
+                )
+                .arg(
+                    clap::Arg::with_name("pattern")
+                        .required(true)
+                        .multiple(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("clean")
+                .about("remove locations that no longer exist")
+                .arg(
+                    clap::Arg::with_name("n")
+                        .short("n")
+                        .hidden(true)
+                        .help("Same as --dry-run"),
+                )
+                .arg(
+                    clap::Arg::with_name("interactive")
+                        .long("interactive")
+                        .short("i")
+                        .help("Pick which missing or low scoring locations to remove"),
+                )
+                .arg(
+                    clap::Arg::with_name("below")
+                        .long("below")
+                        .takes_value(true)
+                        .value_name("SCORE")
+                        .default_value(DEFAULT_CLEAN_BELOW)
+                        .requires("interactive")
+                        .help("With --interactive, also offer locations scoring below SCORE"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("init")
+                .about("print shell integration script")
+                .arg(
+                    clap::Arg::with_name("shell")
+                        .required(true)
+                        .index(1)
+                        .possible_values(&init::SHELLS),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("completions")
+                .about("print shell completion script")
+                .arg(
+                    clap::Arg::with_name("shell")
+                        .required(true)
+                        .index(1)
+                        .possible_values(&clap::Shell::variants()),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("import")
+                .about("import locations from another tool")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .global(true)
+                        .help("Import the locations as files instead of directories"),
+                )
+                .arg(
+                    clap::Arg::with_name("sum")
+                        .long("sum")
+                        .global(true)
+                        .help("Add imported ranks to existing entries (default)"),
+                )
+                .arg(
+                    clap::Arg::with_name("max")
+                        .long("max")
+                        .global(true)
+                        .conflicts_with_all(&["sum", "keep-existing"])
+                        .help("Keep the larger of the imported and existing ranks"),
+                )
+                .arg(
+                    clap::Arg::with_name("keep-existing")
+                        .long("keep-existing")
+                        .global(true)
+                        .conflicts_with("sum")
+                        .help("Leave existing entries untouched"),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("z")
+                        .about("import z datafile, defaults to $_Z_DATA or ~/.z")
+                        .arg(clap::Arg::with_name("file").index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("autojump")
+                        .about("import autojump database, defaults to autojump.txt in the user data dir")
+                        .arg(clap::Arg::with_name("file").index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("fasd")
+                        .about("import directories from fasd datafile, defaults to $_FASD_DATA or ~/.fasd")
+                        .arg(clap::Arg::with_name("file").index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("json")
+                        .about("import a json export, use - for stdin")
+                        .arg(clap::Arg::with_name("file").required(true).index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("history")
+                        .about("import the directories cd'd to in a shell history file")
+                        .arg(
+                            clap::Arg::with_name("shell")
+                                .long("shell")
+                                .takes_value(true)
+                                .required(true)
+                                .possible_values(&import::HISTORY_SHELLS),
+                        )
+                        .arg(clap::Arg::with_name("file").index(1)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("zoxide")
+                        .about("import from `zoxide query --list --score`, or a file of its output")
+                        .arg(clap::Arg::with_name("file").index(1)),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("export")
+                .about("export all db entries to stdout")
+                .arg(
+                    clap::Arg::with_name("to")
+                        .long("to")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["json", "z"])
+                        .default_value("json"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("merge")
+                .about("merge entries from another jumpjump db")
+                .arg(clap::Arg::with_name("database").required(true).index(1)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pin")
+                .about("always rank location above unpinned matches")
+                .arg(clap::Arg::with_name("location").required(true).index(1))
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Pin a file, adding it as one if needed"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("boost")
+                .about("raise the rank of location, by one visit by default")
+                .arg(clap::Arg::with_name("location").required(true).index(1))
+                .arg(clap::Arg::with_name("amount").index(2)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("demote")
+                .about("lower the rank of location, by one visit by default")
+                .arg(clap::Arg::with_name("location").required(true).index(1))
+                .arg(clap::Arg::with_name("amount").index(2)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("set-rank")
+                .about("set the rank of location, adding it if needed")
+                .arg(clap::Arg::with_name("location").required(true).index(1))
+                .arg(clap::Arg::with_name("rank").required(true).index(2)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("unpin")
+                .about("rank location by frecency again")
+                .arg(clap::Arg::with_name("location").required(true).index(1)),
         )
-        .get_matches();
+        .subcommand(
+            clap::SubCommand::with_name("alias")
+                .about("name a location, or list aliases when no name is given")
+                .after_help(
+                    "`get NAME` with a single pattern that is an alias name returns the \
+                     aliased location first, before any pattern matches.",
+                )
+                .arg(
+                    clap::Arg::with_name("remove")
+                        .long("remove")
+                        .short("d")
+                        .requires("name")
+                        .conflicts_with("location")
+                        .help("Remove the named alias"),
+                )
+                .arg(clap::Arg::with_name("name").index(1))
+                .arg(clap::Arg::with_name("location").index(2)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tag")
+                .about("tag a location, or list tags when no tag is given")
+                .arg(
+                    clap::Arg::with_name("remove")
+                        .long("remove")
+                        .short("d")
+                        .requires("tag")
+                        .help("Remove the given tags"),
+                )
+                .arg(clap::Arg::with_name("location").index(1))
+                .arg(clap::Arg::with_name("tag").multiple(true).index(2))
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Tag a file, adding it as one if needed"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("edit")
+                .about("edit ranks and locations in $VISUAL or $EDITOR")
+                .arg(
+                    clap::Arg::with_name("files")
+                        .long("files")
+                        .help("Edit files added with add --file instead of directories"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("serve")
+                .about("answer HTTP requests for /query, /list and /add with JSON")
+                .arg(
+                    clap::Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("ADDR")
+                        .takes_value(true)
+                        .default_value(server::DEFAULT_LISTEN)
+                        .help("Listen on the given address and port"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tui")
+                .about("browse the db full screen, searching as you type, and print the chosen location"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("profile")
+                .about("manage profiles, which keep separate dbs")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("list profiles with a db, marking the current one"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("daemon")
+                .about("keep the db open and answer add and get on --socket, next to the db by default")
+                .arg(
+                    clap::Arg::with_name("watch")
+                        .long("watch")
+                        .value_name("SECONDS")
+                        .takes_value(true)
+                        .help("Every SECONDS, check every location on disk and drop those whose directories were deleted"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("doctor")
+                .about("check the db for damage, duplicate and stale entries")
+                .arg(
+                    clap::Arg::with_name("fix")
+                        .long("fix")
+                        .help("Merge duplicate entries and remove stale ones"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("dedupe")
+                .about("merge entries that normalize to the same path, summing their ranks"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("bench")
+                .about("time queries against the db, for reporting performance issues")
+                .arg(
+                    clap::Arg::with_name("runs")
+                        .long("runs")
+                        .short("r")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("10")
+                        .help("Time N runs of each query"),
+                )
+                .arg(
+                    clap::Arg::with_name("synthetic")
+                        .long("synthetic")
+                        .help("Time adds, matches and migrations on generated dbs of 1k, 10k and 100k entries instead"),
+                )
+                .arg(
+                    clap::Arg::with_name("patterns")
+                        .multiple(true)
+                        .value_name("QUERY")
+                        .help("Queries to time, quoted when they have several patterns, defaults to the best entry's name"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("optimize")
+                .alias("vacuum")
+                .about("compact the db file and refresh its query statistics"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("stats")
+                .about("show a summary of the db"),
+        )
+}
 
-    let default_path = get_database_path()?;
-    let default_path_str = default_path.to_string_lossy();
-    let db_path = matches.value_of("file").unwrap_or(&default_path_str);
-    let connection = Connection::open(db_path)?;
-    let db = Database::new(connection)?;
+/// `-v` wins over `$JUMPJUMP_LOG`.
+fn log_level(matches: &clap::ArgMatches) -> Result<Level, Error> {
+    let mut verbosity = matches.occurrences_of("verbose");
+    if let (_, Some(sub)) = matches.subcommand() {
+        verbosity = verbosity.max(sub.occurrences_of("verbose"));
+    }
+    match std::env::var("JUMPJUMP_LOG") {
+        Ok(name) if verbosity == 0 => Level::from_name(&name).ok_or_else(|| {
+            anyhow!(
+                "Unknown JUMPJUMP_LOG level {}, expected one of {}",
+                name,
+                LEVELS.join(", ")
+            )
+        }),
+        _ => Ok(Level::from_verbosity(verbosity)),
+    }
+}
+
+/// The db to open: `--db` given before or after the subcommand, else the profile's,
+/// else `db` from the config file, else the default.
+fn database_path(matches: &clap::ArgMatches, config: &Config) -> Result<PathBuf, Error> {
+    let db = matches
+        .value_of_os("db")
+        .or_else(|| global_value_os(matches, "db"));
+    let path = match (db, global_value(matches, "profile"), config.db.clone()) {
+        (Some(path), _, _) => PathBuf::from(path),
+        (None, Some(profile), _) => get_profile_database_path(profile)?,
+        (None, None, Some(path)) => path,
+        (None, None, None) => get_database_path()?,
+    };
+    Ok(path)
+}
+
+fn run() -> Result<(), Error> {
+    let matches = app().get_matches_safe().unwrap_or_else(|err| {
+        if err.use_stderr() {
+            eprintln!("{}", err.message);
+            process::exit(EXIT_ERROR);
+        }
+        err.exit()
+    });
+
+    log::set_level(log_level(&matches)?);
+
+    if let ("init", Some(matches)) = matches.subcommand() {
+        let shell = matches.value_of("shell").unwrap();
+        print!("{}", init::script(shell).unwrap());
+        return Ok(());
+    }
+    if let ("completions", Some(matches)) = matches.subcommand() {
+        let shell = matches.value_of("shell").unwrap().parse().unwrap();
+        app().gen_completions_to("jumpjump", shell, &mut io::stdout());
+        return Ok(());
+    }
+
+    let config = match config::get_config_path() {
+        Some(path) => config::load(&path)?,
+        None => Config::default(),
+    };
+
+    let format_name = global_value(&matches, "format")
+        .or(config.format.as_deref())
+        .unwrap_or("plain");
+    let format = Format::from_name(format_name)
+        .ok_or_else(|| anyhow!("Unknown output format {}", format_name))?;
+    output::set_print0(global_flag(&matches, "print0"));
+    output::set_quote(global_flag(&matches, "quote"));
+    if global_flag(&matches, "abbrev-home") {
+        if let Some(home) = dirs::home_dir() {
+            output::abbreviate_home(&home);
+        }
+    }
+
+    let profile = global_value(&matches, "profile");
+    if let ("profile", Some(_)) = matches.subcommand() {
+        for name in get_profiles()? {
+            let current = name == profile.unwrap_or(DEFAULT_PROFILE);
+            println!("{} {}", if current { "*" } else { " " }, name);
+        }
+        return Ok(());
+    }
+
+    let db_path = database_path(&matches, &config)?;
+    if let Some(socket) = global_value_os(&matches, "socket") {
+        if let Some(request) = daemon_request(&matches, &config, format)? {
+            let fields = request.iter().map(OsString::as_os_str).collect::<Vec<_>>();
+            // Without a daemon listening, fall back to opening the database.
+            if let Some(reply) = daemon::request(Path::new(socket), &fields)? {
+                log::debug(format_args!("Answered by daemon on {:?}", socket));
+                match reply {
+                    daemon::Reply::Ok(path) if request[0] != "add" => {
+                        output::print_path(&path)?;
+                        if let Some(hook) = &config.get_hook {
+                            run_get_hook(hook, Path::new(&path));
+                        }
+                    }
+                    daemon::Reply::NoMatch => process::exit(EXIT_NO_MATCH),
+                    _ => (),
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    let mut db = open_database(&matches, &config, &db_path)?;
+    let dry_run = match matches.subcommand() {
+        ("clean", Some(sub)) => sub.is_present("dry-run") || sub.is_present("n"),
+        (_, Some(sub)) => sub.is_present("dry-run"),
+        _ => matches.is_present("dry-run"),
+    };
+    db.set_dry_run(dry_run);
+    if let (_, Some(sub)) = matches.subcommand() {
+        // Import also takes it after the source.
+        let (_, source) = sub.subcommand();
+        if sub.is_present("files") || source.is_some_and(|s| s.is_present("files")) {
+            db.set_kind(Kind::File)?;
+        }
+    }
 
     match matches.subcommand() {
         ("add", Some(matches)) => {
-            let location = matches.value_of("location").unwrap();
-            add_path(&db, location)?;
+            db.set_aging(aging_for(matches, &config)?);
+            let location = expand_arg(matches.value_of_os("location").unwrap());
+            // A repository root is a directory, never recorded as a file.
+            let vcs_roots = if matches.is_present("files") {
+                false
+            } else if matches.is_present("vcs-root") {
+                true
+            } else if matches.is_present("no-vcs-root") {
+                false
+            } else {
+                config.vcs_roots
+            };
+            let physical = resolves_symlinks(matches, &config);
+            add_path(&db, location, physical, vcs_roots)?;
+        },
+        ("daemon", Some(matches)) => {
+            db.set_aging(aging_for(matches, &config)?);
+            let socket = match matches.value_of_os("socket") {
+                Some(socket) => PathBuf::from(socket),
+                None => daemon::default_socket_path(&db_path),
+            };
+            if let Some(seconds) = matches.value_of("watch") {
+                let interval = Duration::from_secs(seconds.parse()?);
+                let (matches, config) = (matches.clone(), config.clone());
+                daemon::watch(interval, move || open_database(&matches, &config, &db_path));
+            }
+            daemon::serve(&db, &socket, config.case)?;
+        },
+        ("serve", Some(matches)) => {
+            server::serve(&db, matches.value_of("listen").unwrap(), config.case)?;
+        },
+        ("tui", _) => {
+            if let Some(path) = browse(&db, config.case)? {
+                output::print_path(path.as_os_str())?;
+            }
+        },
+        ("get", Some(matches)) if matches.is_present("explain") => {
+            let query = get_query(matches, config.case)?;
+            let entries = matching_entries(&db, &query)?;
+            if entries.is_empty() {
+                process::exit(EXIT_NO_MATCH);
+            }
+            output::print_explanations(&entries, &query)?;
+        },
+        ("get", Some(matches)) if matches.is_present("recent-index") => {
+            let n = matches.value_of("recent-index").unwrap().parse::<usize>()?;
+            let mut entries = db.query(&get_query(matches, config.case)?)?;
+            sort_entries(&mut entries, "recent", false);
+            match n.checked_sub(1).and_then(|i| entries.get(i)) {
+                Some(entry) => {
+                    let line = line_for(matches, location_line);
+                    output::print_entries(std::slice::from_ref(entry), format, line)?
+                }
+                None => process::exit(EXIT_NO_MATCH),
+            }
+        },
+        ("get", Some(matches)) if matches.is_present("list") => {
+            let query = get_query(matches, config.case)?;
+            let limit = matches.value_of("limit").map(str::parse).transpose()?;
+            report_matching_locations(
+                &db,
+                &query,
+                line_for(matches, location_line),
+                matches.is_present("select-1"),
+                matches.is_present("exit-0"),
+                limit,
+                format,
+            )?;
         },
         ("get", Some(matches)) => {
-            if let Some(patterns) = matches.values_of_lossy("pattern") {
-                report_best_location(&db, patterns)?;
+            let limit = matches.value_of("limit").map(str::parse).transpose()?;
+            let line = line_for(matches, location_line);
+            let filters = [
+                "pattern",
+                "regex",
+                "tag",
+                "accessed-within",
+                "accessed-before",
+            ];
+            if filters.iter().any(|f| matches.is_present(f)) {
+                let query = get_query(matches, config.case)?;
+                let interactive = matches.is_present("interactive");
+                let exec = matches.value_of("exec");
+                let path = report_best_location(
+                    &db,
+                    &query,
+                    interactive,
+                    limit,
+                    line,
+                    format,
+                    exec.is_none(),
+                )?;
+                if let Some(path) = path {
+                    if let Some(hook) = &config.get_hook {
+                        run_get_hook(hook, &path);
+                    }
+                    if let Some(command) = exec {
+                        exec_on(command, &path)?;
+                    }
+                }
+            } else {
+                report_locations(&db, limit, line, format)?;
+            }
+        },
+        ("complete", Some(matches)) => {
+            let query = get_query(matches, config.case)?;
+            for name in db.complete(&query)? {
+                println!("{}", name);
+            }
+        },
+        ("edit", _) => {
+            edit_locations(&db)?;
+        },
+        ("doctor", Some(matches)) => {
+            doctor(&db, matches.is_present("fix"))?;
+        },
+        ("dedupe", _) => {
+            let duplicates = db.get_duplicates()?;
+            for (normalized, locations) in duplicates.iter() {
+                println!("{}\t{}", normalized, locations.join("\t"));
+            }
+            let merged = db.merge_duplicates(&duplicates)?;
+            eprintln!("Merged {} duplicates", merged);
+        },
+        ("bench", Some(matches)) => {
+            let runs = matches.value_of("runs").unwrap().parse()?;
+            let mut timings = Vec::new();
+            if matches.is_present("synthetic") {
+                for &size in bench::SIZES.iter() {
+                    timings.extend(bench::synthetic(size, runs)?);
+                }
+            } else {
+                let patterns = match matches.values_of("patterns") {
+                    Some(patterns) => patterns.map(str::to_string).collect(),
+                    None => best_name(&db)?.into_iter().collect::<Vec<_>>(),
+                };
+                timings = bench::queries(&db, &patterns, runs)?;
+            }
+            report_timings(&timings, format);
+        },
+        ("optimize", _) => {
+            let size = || std::fs::metadata(&db_path).map(|m| m.len()).unwrap_or(0);
+            let before = size();
+            db.optimize()?;
+            let after = size();
+            eprintln!(
+                "Optimized {}, {} bytes to {} ({} saved)",
+                db_path.display(),
+                before,
+                after,
+                before.saturating_sub(after)
+            );
+        },
+        ("stats", _) => {
+            report_stats(&db, &db_path, format)?;
+        },
+        ("show", Some(matches)) => {
+            let query = Query::new(Vec::<String>::new())
+                .with_accessed_within(age_arg(matches, "accessed-within")?)
+                .with_accessed_before(age_arg(matches, "accessed-before")?);
+            let mut entries = db.query(&query)?;
+            let sort = matches.value_of("sort").unwrap();
+            sort_entries(&mut entries, sort, matches.is_present("reverse"));
+            // Lines for xargs and eval hold just the path.
+            let paths = matches.is_present("print0") || matches.is_present("quote");
+            let raw = paths || matches.is_present("porcelain") || matches.is_present("score");
+            if matches.is_present("tree") {
+                output::print_tree(&entries)?;
+            } else if format == Format::Plain && !raw {
+                show_table(&db, &entries, !matches.is_present("no-color"))?;
+            } else {
+                let line = if paths { location_line } else { show_line };
+                output::print_entries(&entries, format, line_for(matches, line))?;
+            }
+        },
+        ("push", Some(matches)) => {
+            let location = match matches.value_of_os("location") {
+                Some(location) => canonicalize_path(expand_arg(location))?,
+                None => canonicalize_path(std::env::current_dir()?)?,
+            };
+            db.push_location(session()?, location)?;
+        },
+        ("pop", _) => match db.pop_location(session()?)? {
+            Some(location) => output::print_path(location.as_ref())?,
+            None => process::exit(EXIT_NO_MATCH),
+        },
+        ("stack", _) => {
+            for location in db.get_stack(session()?)? {
+                output::print_path(location.as_ref())?;
+            }
+        },
+        ("back", Some(matches)) => {
+            report_previous_location(&db, matches.value_of("n").unwrap().parse()?)?;
+        },
+        ("leave", Some(matches)) => {
+            leave_path(&db, matches.value_of_os("location").unwrap())?;
+        },
+        ("recent", Some(matches)) => {
+            let limit = matches.value_of("limit").unwrap().parse()?;
+            report_sorted(&db, "recent", limit, format)?;
+        },
+        ("projects", Some(matches)) => {
+            let mut projects = db.get_projects()?;
+            if let Some(limit) = matches.value_of("limit") {
+                projects.truncate(limit.parse()?);
+            }
+            output::print_entries(&projects, format, line_for(matches, location_line))?;
+        },
+        ("history", Some(matches)) => {
+            if matches.is_present("clear") {
+                let cleared = db.clear_history()?;
+                eprintln!("Forgot {} jumps", cleared);
+            } else {
+                let limit = matches.value_of("limit").unwrap().parse()?;
+                output::print_history(&db.get_history(Some(limit))?, format)?;
+            }
+        },
+        ("frequent", Some(matches)) => {
+            let limit = matches.value_of("limit").unwrap().parse()?;
+            report_sorted(&db, "rank", limit, format)?;
+        },
+        ("remove", Some(matches)) => {
+            if let Some(pattern) = matches.value_of("pattern") {
+                remove_pattern(&db, pattern)?;
             } else {
-                report_locations(&db)?;
+                let location = matches.value_of_os("location").unwrap();
+                remove_path(&db, location)?;
             }
         },
-        ("show", _) => {
-            report_all_locations(&db)?;
+        ("undo", _) => match db.undo()? {
+            Some(description) => eprintln!("Undid {}", description),
+            None => return Err(anyhow!("Nothing to undo")),
+        },
+        ("pin", Some(matches)) => {
+            pin_path(&db, matches.value_of_os("location").unwrap())?;
+        },
+        ("unpin", Some(matches)) => {
+            unpin_path(&db, matches.value_of_os("location").unwrap())?;
+        },
+        ("boost", Some(matches)) => {
+            let amount = matches.value_of("amount").unwrap_or("1").parse::<f64>()?;
+            adjust_rank(&db, matches.value_of_os("location").unwrap(), amount)?;
+        },
+        ("demote", Some(matches)) => {
+            let amount = matches.value_of("amount").unwrap_or("1").parse::<f64>()?;
+            adjust_rank(&db, matches.value_of_os("location").unwrap(), -amount)?;
+        },
+        ("set-rank", Some(matches)) => {
+            let rank = matches.value_of("rank").unwrap().parse()?;
+            set_rank(&db, matches.value_of_os("location").unwrap(), rank)?;
+        },
+        ("alias", Some(matches)) => {
+            match (matches.value_of("name"), matches.value_of("location")) {
+                (Some(name), _) if matches.is_present("remove") => remove_alias(&db, name)?,
+                (Some(name), Some(location)) => set_alias(&db, name, location)?,
+                (Some(name), None) => match db.get_alias_entry(name)? {
+                    Some(alias) => println!("{}", alias.location),
+                    None => return Err(anyhow!("No alias {}", name)),
+                },
+                (None, _) => report_aliases(&db)?,
+            }
+        },
+        ("tag", Some(matches)) => {
+            let location = matches.value_of("location");
+            match (location, matches.values_of("tag")) {
+                (Some(location), Some(tags)) if matches.is_present("remove") => {
+                    untag_path(&db, location, &tags.collect::<Vec<_>>())?
+                }
+                (Some(location), Some(tags)) => tag_path(&db, location, &tags.collect::<Vec<_>>())?,
+                (location, _) => report_tags(&db, location)?,
+            }
+        },
+        ("import", Some(matches)) => {
+            if let (format, Some(matches)) = matches.subcommand() {
+                let conflict = if matches.is_present("max") {
+                    Conflict::Max
+                } else if matches.is_present("keep-existing") {
+                    Conflict::KeepExisting
+                } else {
+                    Conflict::Sum
+                };
+                if format == "history" {
+                    let shell = matches.value_of("shell").unwrap();
+                    import_history(&db, shell, matches.value_of("file"), conflict)?;
+                } else {
+                    import_file(&db, format, matches.value_of("file"), conflict)?;
+                }
+            }
+        },
+        ("export", Some(matches)) => {
+            export_locations(&db, matches.value_of("to").unwrap())?;
+        },
+        ("merge", Some(matches)) => {
+            merge_database(&db, matches.value_of("database").unwrap())?;
+        },
+        ("forget", Some(matches)) => {
+            let query = get_query(matches, config.case)?.with_require_exists(false);
+            forget_matching(&db, &query, matches.is_present("yes") || dry_run)?;
+        },
+        ("clean", Some(matches)) if matches.is_present("interactive") => {
+            let below = matches.value_of("below").unwrap().parse()?;
+            clean_interactively(&db, below)?;
+        },
+        ("clean", _) => {
+            clean_missing_locations(&db, dry_run)?;
         },
         _ => (),
     }
+    if dry_run {
+        report_changes(&db)?;
+    }
 
     Ok(())
 }
@@ -286,55 +2592,246 @@ mod tests {
     use super::*;
 
     #[test]
-    fn most_visited_location_is_first() {
-        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+    fn parses_choice() {
+        assert_eq!(parse_choice("\n", 3), Some(0));
+        assert_eq!(parse_choice("2\n", 3), Some(1));
+        assert_eq!(parse_choice("3", 3), Some(2));
+        assert_eq!(parse_choice("4", 3), None);
+        assert_eq!(parse_choice("0", 3), None);
+        assert_eq!(parse_choice("x", 3), None);
+    }
 
-        db.add_location("foo").unwrap();
-        db.add_location("foo").unwrap();
-        db.add_location("bar").unwrap();
-        db.add_location("bar").unwrap();
-        db.add_location("bar").unwrap();
+    #[test]
+    fn finds_previous_locations() {
+        let visits = ["/c", "/b", "/c", "/gone", "/a"].map(String::from);
+        let exists = |l: &str| l != "/gone";
 
-        let locations: Vec<String> = db.get_locations().unwrap();
+        assert_eq!(previous_location(&visits, "/c", 1, exists), Some("/b"));
+        assert_eq!(previous_location(&visits, "/c", 2, exists), Some("/a"));
+        assert_eq!(previous_location(&visits, "/b", 1, exists), Some("/c"));
+        assert_eq!(previous_location(&visits, "/c", 3, exists), None);
+    }
 
-        assert_eq!(locations[..], ["bar", "foo"]);
+    #[test]
+    fn parses_selections() {
+        assert_eq!(parse_selection("\n", 5), Some(vec![]));
+        assert_eq!(parse_selection("1 3-4\n", 5), Some(vec![0, 2, 3]));
+        assert_eq!(parse_selection("all ^2 ^4-5", 5), Some(vec![0, 2]));
+        assert_eq!(parse_selection("1,2", 5), Some(vec![0, 1]));
+        assert_eq!(parse_selection("6", 5), None);
+        assert_eq!(parse_selection("0", 5), None);
+        assert_eq!(parse_selection("4-2", 5), None);
+        assert_eq!(parse_selection("x", 5), None);
     }
 
     #[test]
-    fn finds_by_multiple_substr() {
-        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+    fn exits_with_db_error_status() {
+        let db_error = Error::from(JumpError::Database(rusqlite::Error::InvalidQuery));
+        let open_error = Error::from(rusqlite::Error::InvalidQuery).context("Opening db");
 
-        db.add_location("/foo/bar").unwrap();
-        db.add_location("/foo/doo").unwrap();
-        db.add_location("/foo/bar/doo").unwrap();
+        assert_eq!(exit_code(&db_error), EXIT_DB_ERROR);
+        assert_eq!(exit_code(&open_error), EXIT_DB_ERROR);
+        assert_eq!(exit_code(&anyhow!("Invalid selection")), EXIT_ERROR);
+    }
+
+    #[test]
+    fn generates_completions() {
+        for shell in &clap::Shell::variants() {
+            let mut script = Vec::new();
+            app().gen_completions_to("jumpjump", shell.parse().unwrap(), &mut script);
+            assert!(String::from_utf8(script).unwrap().contains("import"));
+        }
+    }
 
-        let locations: Vec<String> = db.get_matching_locations(&["bar", "doo"]).unwrap();
+    #[test]
+    fn reads_confirmation() {
+        assert!(is_yes("y\n"));
+        assert!(is_yes(" Yes "));
+        assert!(!is_yes("\n"));
+        assert!(!is_yes("no"));
+    }
 
-        assert_eq!(locations[..], ["/foo/bar/doo"]);
+    #[test]
+    fn takes_db_path_anywhere() {
+        for args in &[
+            ["jumpjump", "--db", "x.db", "get"],
+            ["jumpjump", "-f", "x.db", "get"],
+            ["jumpjump", "get", "--file", "x.db"],
+        ] {
+            let matches = app().get_matches_from_safe(args).unwrap();
+            let db = database_path(&matches, &Config::default()).unwrap();
+            assert_eq!(db, Path::new("x.db"));
+        }
+        assert!(app().get_matches_from_safe(["jumpjump", "--db"]).is_err());
     }
 
     #[test]
-    fn finds_by_single_substr() {
-        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+    fn skips_the_daemon_when_it_cannot_answer() {
+        let config = Config::default();
+        let request = |args: &[&str]| {
+            let matches = app().get_matches_from_safe(args).unwrap();
+            daemon_request(&matches, &config, Format::Plain).unwrap()
+        };
 
-        db.add_location("/foo/bar").unwrap();
-        db.add_location("/foo/bar/doo").unwrap();
+        assert!(request(&["jumpjump", "add", "/"]).is_some());
+        assert!(request(&["jumpjump", "--dry-run", "add", "/"]).is_none());
+        assert!(request(&["jumpjump", "add", "--dry-run", "/"]).is_none());
+        assert!(request(&["jumpjump", "get", "--dry-run", "src"]).is_none());
+        assert!(request(&["jumpjump", "--db", "x.db", "add", "/"]).is_none());
+        assert!(request(&["jumpjump", "get", "--profile", "work", "src"]).is_none());
+        assert!(request(&["jumpjump", "get", "--passphrase-file", "key", "src"]).is_none());
+    }
 
-        let locations: Vec<String> = db.get_matching_locations(&["doo"]).unwrap();
+    #[test]
+    fn takes_format_anywhere() {
+        for args in &[
+            ["jumpjump", "--format", "json", "get"],
+            ["jumpjump", "get", "--format", "json"],
+        ] {
+            let matches = app().get_matches_from_safe(args).unwrap();
+            assert_eq!(global_value(&matches, "format"), Some("json"));
+        }
+        let matches = app()
+            .get_matches_from_safe(["jumpjump", "export", "--to", "z"])
+            .unwrap();
+        let (_, export) = matches.subcommand();
+        assert_eq!(export.unwrap().value_of("to"), Some("z"));
+    }
 
-        assert_eq!(locations[..], ["/foo/bar/doo"]);
+    #[test]
+    fn accepts_subcommand_aliases() {
+        let cases: [(&[&str], &str); 4] = [
+            (&["jumpjump", "a", "/tmp"], "add"),
+            (&["jumpjump", "g", "src"], "get"),
+            (&["jumpjump", "ls"], "show"),
+            (&["jumpjump", "rm", "/tmp"], "remove"),
+        ];
+        for (args, name) in cases.iter() {
+            let matches = app().get_matches_from_safe(args.iter()).unwrap();
+            assert_eq!(matches.subcommand_name(), Some(*name));
+        }
     }
 
     #[test]
-    fn finds_in_many() {
-        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+    fn describes_options_in_help() {
+        let mut help = Vec::new();
+        app().write_long_help(&mut help).unwrap();
+        let help = String::from_utf8(help).unwrap();
 
-        for x in 0..10000 {
-            db.add_location(format!("/foo/bar/{}", x)).unwrap();
+        assert!(help.contains("--db <PATH>"));
+        assert!(help.contains("--exclude <GLOB>"));
+        for name in &["add", "get", "show", "remove", "forget", "doctor"] {
+            assert!(help.contains(name), "help lists {}", name);
         }
 
-        let locations: Vec<String> = db.get_matching_locations(&["bar", "9999"]).unwrap();
+        let err = app()
+            .get_matches_from_safe(["jumpjump", "get", "--help"])
+            .unwrap_err();
+        assert_eq!(err.kind, clap::ErrorKind::HelpDisplayed);
+        assert!(err.message.contains("EXIT STATUS"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn quotes_hook_paths() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(OsStr::from_bytes(b"/home/me/it's \xff"));
+
+        let command = shell_command("cd {} && ls {}", path);
+
+        let args = command.get_args().collect::<Vec<_>>();
+        assert_eq!(
+            args[1].as_bytes(),
+            &b"cd '/home/me/it'\\''s \xff' && ls '/home/me/it'\\''s \xff'"[..]
+        );
+    }
+
+    #[test]
+    fn sorts_entries() {
+        let entry = |location: &str, rank, last_access| Entry {
+            location: location.to_string(),
+            rank,
+            last_access,
+            score: 0.0,
+            pinned: false,
+            raw: None,
+        };
+        let mut entries = vec![
+            entry("/b", 1.0, 1578009600),
+            entry("/a", 3.0, 1577836800),
+            entry("/c", 2.0, 1577923200),
+        ];
+        let locations = |entries: &[Entry]| {
+            entries
+                .iter()
+                .map(|e| e.location.clone())
+                .collect::<Vec<_>>()
+        };
+
+        sort_entries(&mut entries, "rank", false);
+        assert_eq!(locations(&entries), vec!["/a", "/c", "/b"]);
+        sort_entries(&mut entries, "recent", false);
+        assert_eq!(locations(&entries), vec!["/b", "/c", "/a"]);
+        sort_entries(&mut entries, "alpha", true);
+        assert_eq!(locations(&entries), vec!["/c", "/b", "/a"]);
+    }
+
+    #[test]
+    fn parses_edits() {
+        let ranks = parse_edits("# comment\n\n2.5\t/foo bar\n1 /baz\n").unwrap();
+
+        assert_eq!(
+            ranks,
+            vec![("/foo bar".to_string(), 2.5), ("/baz".to_string(), 1.0)]
+        );
+        assert!(parse_edits("/foo").is_err());
+        assert!(parse_edits("0\t/foo").is_err());
+        assert!(parse_edits("x\t/foo").is_err());
+    }
+
+    #[test]
+    fn canonicalizes_added_edits() {
+        let entries = [import::ImportedLocation {
+            location: "/kept//as/is".to_string(),
+            rank: 1.0,
+            last_access: None,
+        }];
+        let ranks = vec![
+            ("/kept//as/is".to_string(), 2.0),
+            ("~/src".to_string(), 1.0),
+            ("relative/../dir".to_string(), 1.0),
+        ];
+
+        let resolved = resolve_edits(&entries, ranks).unwrap();
+
+        let home = dirs::home_dir().unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            resolved,
+            vec![
+                ("/kept//as/is".to_string(), 2.0),
+                (canonicalize_path(home.join("src")).unwrap(), 1.0),
+                (canonicalize_path(cwd.join("dir")).unwrap(), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_private_temp_files() {
+        let (dir, path, _) = private_temp_file("test.tsv").unwrap();
+        let again = private_temp_file("test.tsv").unwrap();
+        std::fs::remove_dir_all(&again.0).unwrap();
+        #[cfg(unix)]
+        let modes = {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+            (mode(&dir), mode(&path))
+        };
+        std::fs::remove_dir_all(&dir).unwrap();
 
-        assert_eq!(locations[..], ["/foo/bar/9999"]);
+        assert_ne!(dir, again.0);
+        assert_eq!(path, dir.join("test.tsv"));
+        #[cfg(unix)]
+        assert_eq!(modes, (0o700, 0o600));
     }
 }
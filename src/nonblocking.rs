@@ -0,0 +1,112 @@
+//! An async facade over [`Database`] for editor plugins and language servers that
+//! run on tokio, enabled by the `async` feature.
+//!
+//! SQLite calls block, so each one runs on tokio's blocking thread pool while the
+//! caller's task awaits it. Calls are serialized on the one connection.
+//!
+//! ```no_run
+//! use jumpjump::nonblocking::AsyncDatabase;
+//! use jumpjump::{get_database_path, Query};
+//!
+//! # async fn run() -> Result<(), jumpjump::JumpError> {
+//! let db = AsyncDatabase::open(get_database_path()?).await?;
+//! if let Some(best) = db.query(Query::new(&["src"])).await?.first() {
+//!     println!("{}", best.location);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Database, Entry, JumpError, Query};
+use rusqlite::Connection;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A [`Database`] whose calls run without blocking the async runtime.
+///
+/// Cloning it is cheap and the clones share the connection.
+#[derive(Clone)]
+pub struct AsyncDatabase {
+    db: Arc<Mutex<Database>>,
+}
+
+impl AsyncDatabase {
+    /// Wraps an open database.
+    pub fn new(db: Database) -> AsyncDatabase {
+        AsyncDatabase {
+            db: Arc::new(Mutex::new(db)),
+        }
+    }
+
+    /// Opens the database at `path`, creating it if needed.
+    pub async fn open<P: Into<PathBuf>>(path: P) -> Result<AsyncDatabase, JumpError> {
+        let path = path.into();
+        let db = spawn(move || Database::new(Connection::open(path)?)).await?;
+        Ok(AsyncDatabase::new(db))
+    }
+
+    /// Runs `f` with the database on the blocking thread pool.
+    pub async fn run<F, T>(&self, f: F) -> Result<T, JumpError>
+    where
+        F: FnOnce(&mut Database) -> Result<T, JumpError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        spawn(move || f(&mut db.lock().unwrap_or_else(PoisonError::into_inner))).await
+    }
+
+    /// See [`Database::query`].
+    pub async fn query(&self, query: Query) -> Result<Vec<Entry>, JumpError> {
+        self.run(move |db| db.query(&query)).await
+    }
+
+    /// See [`Database::get_entries`].
+    pub async fn get_entries(&self) -> Result<Vec<Entry>, JumpError> {
+        self.run(|db| db.get_entries()).await
+    }
+
+    /// See [`Database::add_location`].
+    pub async fn add_location<S: Into<String>>(&self, location: S) -> Result<(), JumpError> {
+        let location = location.into();
+        self.run(move |db| db.add_location(location)).await
+    }
+
+    /// See [`Database::remove_location`].
+    pub async fn remove_location<S: Into<String>>(&self, location: S) -> Result<usize, JumpError> {
+        let location = location.into();
+        self.run(move |db| db.remove_location(location)).await
+    }
+}
+
+async fn spawn<F, T>(f: F) -> Result<T, JumpError>
+where
+    F: FnOnce() -> Result<T, JumpError> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(err) => Err(JumpError::Io(io::Error::other(err))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn queries_without_blocking() {
+        let db = AsyncDatabase::new(Database::new(Connection::open_in_memory().unwrap()).unwrap());
+        db.add_location("/foo/bar").await.unwrap();
+        db.add_location("/foo/doo").await.unwrap();
+        db.clone().add_location("/foo/bar").await.unwrap();
+
+        let entries = db.query(Query::new(&["foo"])).await.unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "/foo/bar");
+        assert_eq!(db.remove_location("/foo/doo").await.unwrap(), 1);
+        assert_eq!(db.run(|db| db.get_locations()).await.unwrap(), ["/foo/bar"]);
+    }
+}
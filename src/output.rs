@@ -0,0 +1,594 @@
+use jumpjump::{json, Entry, Jump, Query};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Plain,
+    Json,
+    Tsv,
+}
+
+pub const FORMATS: [&str; 3] = ["plain", "json", "tsv"];
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "plain" => Some(Format::Plain),
+            "json" => Some(Format::Json),
+            "tsv" => Some(Format::Tsv),
+            _ => None,
+        }
+    }
+}
+
+/// Home directory printed as `~` when set, see [`abbreviate_home`].
+static HOME: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Prints paths under `home` as `~/...` for the rest of the process. The paths
+/// are for display only, shells do not expand a quoted `~`.
+pub fn abbreviate_home(home: &Path) {
+    let _ = HOME.set(os_bytes(home.as_os_str()).into_owned());
+}
+
+static QUOTE: AtomicBool = AtomicBool::new(false);
+static PRINT0: AtomicBool = AtomicBool::new(false);
+
+/// Shell-quotes every plain output line for the rest of the process, so that it
+/// can be passed to `eval`.
+pub fn set_quote(quote: bool) {
+    QUOTE.store(quote, Ordering::Relaxed);
+}
+
+/// Ends plain output lines with NUL rather than newline for the rest of the
+/// process, for `xargs -0`.
+pub fn set_print0(print0: bool) {
+    PRINT0.store(print0, Ordering::Relaxed);
+}
+
+pub fn write_entries<W, F>(mut w: W, entries: &[Entry], format: Format, plain: F) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(&Entry) -> OsString,
+{
+    match format {
+        Format::Plain => {
+            for e in entries {
+                write_line(&mut w, &plain(e))?;
+            }
+        }
+        Format::Tsv => {
+            for e in entries {
+                writeln!(
+                    w,
+                    "{}\t{:.2}\t{}\t{:.2}\t{}",
+                    abbreviated(&e.location),
+                    e.rank,
+                    utc_time(e.last_access),
+                    e.score,
+                    e.pinned as u8
+                )?;
+            }
+        }
+        Format::Json => {
+            writeln!(w, "[")?;
+            for (i, e) in entries.iter().enumerate() {
+                writeln!(
+                    w,
+                    "  {{\"location\": {}, \"rank\": {}, \"lastAccess\": {}, \"score\": {}, \"pinned\": {}}}{}",
+                    json::escape(&abbreviated(&e.location)),
+                    e.rank,
+                    json::escape(&utc_time(e.last_access)),
+                    e.score,
+                    e.pinned,
+                    if i + 1 < entries.len() { "," } else { "" }
+                )?;
+            }
+            writeln!(w, "]")?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes `s` as a plain output line, otherwise as is so that paths which are not
+/// valid UTF-8 are printed intact.
+fn write_line<W: Write>(w: &mut W, s: &OsStr) -> io::Result<()> {
+    let bytes = os_bytes(s);
+    let line = match HOME.get() {
+        Some(home) => abbreviate(&bytes, home),
+        None => Cow::Borrowed(&*bytes),
+    };
+    if QUOTE.load(Ordering::Relaxed) {
+        w.write_all(&shell_quote(&line))?;
+    } else {
+        w.write_all(&line)?;
+    }
+    w.write_all(if PRINT0.load(Ordering::Relaxed) {
+        b"\0"
+    } else {
+        b"\n"
+    })
+}
+
+/// Quotes `s` as a single word for POSIX shells, or for cmd on Windows where a
+/// path cannot contain `"`.
+pub(crate) fn shell_quote(s: &[u8]) -> Vec<u8> {
+    if cfg!(windows) {
+        return [b"\"", s, b"\""].concat();
+    }
+    let mut quoted = vec![b'\''];
+    for &b in s {
+        if b == b'\'' {
+            quoted.extend_from_slice(br"'\''");
+        } else {
+            quoted.push(b);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(unix)]
+pub(crate) fn os_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(s.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    match s.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+/// The inverse of [`os_bytes`].
+#[cfg(unix)]
+pub(crate) fn os_string(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_string(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+fn abbreviated(location: &str) -> Cow<'_, str> {
+    match HOME.get().map(|home| abbreviate(location.as_bytes(), home)) {
+        Some(Cow::Owned(bytes)) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+        _ => Cow::Borrowed(location),
+    }
+}
+
+/// Replaces a leading `home` in `s` with `~`, where `s` is a path, possibly
+/// followed by tab separated columns.
+fn abbreviate<'a>(s: &'a [u8], home: &[u8]) -> Cow<'a, [u8]> {
+    match s.strip_prefix(home) {
+        Some(rest)
+            if !home.is_empty()
+                && (rest.is_empty()
+                    || rest[0] == b'\t'
+                    || std::path::is_separator(rest[0] as char)) =>
+        {
+            Cow::Owned([b"~", rest].concat())
+        }
+        _ => Cow::Borrowed(s),
+    }
+}
+
+pub fn print_entries<F>(entries: &[Entry], format: Format, plain: F) -> io::Result<()>
+where
+    F: Fn(&Entry) -> OsString,
+{
+    let stdout = io::stdout();
+    write_entries(stdout.lock(), entries, format, plain)
+}
+
+/// Writes jumps as time, query and location, newest first as given.
+pub fn write_history<W: Write>(mut w: W, jumps: &[Jump], format: Format) -> io::Result<()> {
+    match format {
+        Format::Plain | Format::Tsv => {
+            for j in jumps {
+                let time = utc_time(j.time);
+                writeln!(w, "{}\t{}\t{}", time, j.query, abbreviated(&j.location))?;
+            }
+        }
+        Format::Json => {
+            writeln!(w, "[")?;
+            for (i, j) in jumps.iter().enumerate() {
+                writeln!(
+                    w,
+                    "  {{\"time\": {}, \"query\": {}, \"location\": {}}}{}",
+                    json::escape(&utc_time(j.time)),
+                    json::escape(&j.query),
+                    json::escape(&abbreviated(&j.location)),
+                    if i + 1 < jumps.len() { "," } else { "" }
+                )?;
+            }
+            writeln!(w, "]")?;
+        }
+    }
+    Ok(())
+}
+
+pub fn print_history(jumps: &[Jump], format: Format) -> io::Result<()> {
+    let stdout = io::stdout();
+    write_history(stdout.lock(), jumps, format)
+}
+
+/// Prints a single path on its own line.
+pub fn print_path(path: &OsStr) -> io::Result<()> {
+    let stdout = io::stdout();
+    write_line(&mut stdout.lock(), path)
+}
+
+const GREEN: &str = "\x1b[1;32m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes entries as aligned path, rank and age columns, highlighting the highest
+/// ranked entries when `color` is set. `now` is the current Unix time.
+pub fn write_table<W: Write>(mut w: W, entries: &[Entry], now: i64, color: bool) -> io::Result<()> {
+    let width = entries
+        .iter()
+        .map(|e| abbreviated(&e.location).chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_rank = entries.iter().map(|e| e.rank).fold(0.0, f64::max);
+    for e in entries {
+        let ago = ago((now - e.last_access) as f64);
+        let pinned = if e.pinned { "  pinned" } else { "" };
+        // Entries with at least half the top rank stand out.
+        let (start, dim, reset) = if !color {
+            ("", "", "")
+        } else if e.rank * 2.0 >= max_rank {
+            (GREEN, DIM, RESET)
+        } else {
+            ("", DIM, RESET)
+        };
+        writeln!(
+            w,
+            "{}{:<width$}{}  {:>8.2}  {}{}{}{}",
+            start,
+            abbreviated(&e.location),
+            reset,
+            e.rank,
+            dim,
+            ago,
+            reset,
+            pinned,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_table(entries: &[Entry], now: i64, color: bool) -> io::Result<()> {
+    let stdout = io::stdout();
+    write_table(stdout.lock(), entries, now, color)
+}
+
+/// Writes each entry matching `query` with the parts its patterns matched in
+/// brackets, followed by the figures its score was computed from.
+pub fn write_explanations<W: Write>(mut w: W, entries: &[Entry], query: &Query) -> io::Result<()> {
+    for (i, e) in entries.iter().enumerate() {
+        let mut marked = String::new();
+        let mut last = 0;
+        for range in query.match_ranges(&e.location) {
+            marked.push_str(&e.location[last..range.start]);
+            marked.push('[');
+            marked.push_str(&e.location[range.clone()]);
+            marked.push(']');
+            last = range.end;
+        }
+        marked.push_str(&e.location[last..]);
+        writeln!(w, "{:>3}) {}", i + 1, marked)?;
+        writeln!(
+            w,
+            "     score {:.2}  rank {:.2}  lastAccess {}{}",
+            e.score,
+            e.rank,
+            utc_time(e.last_access),
+            if e.pinned { "  pinned" } else { "" }
+        )?;
+    }
+    Ok(())
+}
+
+pub fn print_explanations(entries: &[Entry], query: &Query) -> io::Result<()> {
+    let stdout = io::stdout();
+    write_explanations(stdout.lock(), entries, query)
+}
+
+/// A directory in the tree written by [`write_tree`].
+#[derive(Default)]
+struct Node {
+    /// Summed score of the entry here, if any, and every entry below it.
+    score: f64,
+    entry: bool,
+    children: BTreeMap<OsString, Node>,
+}
+
+impl Node {
+    fn write<W: Write>(&self, w: &mut W, name: PathBuf, depth: usize) -> io::Result<()> {
+        // Directories holding a single child and no entry of their own add nothing.
+        let (mut name, mut node) = (name, self);
+        while let (false, Some((child, only))) = (node.entry, single(&node.children)) {
+            name.push(child);
+            node = only;
+        }
+        let name = name.to_string_lossy();
+        let name = if depth == 0 { abbreviated(&name) } else { name };
+        writeln!(
+            w,
+            "{:indent$}{}  {:.2}",
+            "",
+            name,
+            node.score,
+            indent = depth * 2
+        )?;
+        let mut children = node.children.iter().collect::<Vec<_>>();
+        children.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+        for (child, node) in children {
+            node.write(w, PathBuf::from(child), depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn single(children: &BTreeMap<OsString, Node>) -> Option<(&OsString, &Node)> {
+    match children.len() {
+        1 => children.iter().next(),
+        _ => None,
+    }
+}
+
+/// Writes entries grouped under their shared ancestors, each directory with the
+/// summed score of the entries within it, highest first.
+pub fn write_tree<W: Write>(mut w: W, entries: &[Entry]) -> io::Result<()> {
+    let mut root = Node::default();
+    for e in entries {
+        let mut node = &mut root;
+        node.score += e.score;
+        for component in e.path().components() {
+            node = node
+                .children
+                .entry(component.as_os_str().to_os_string())
+                .or_default();
+            node.score += e.score;
+        }
+        node.entry = true;
+    }
+    let mut tops = root.children.iter().collect::<Vec<_>>();
+    tops.sort_by(|a, b| b.1.score.total_cmp(&a.1.score));
+    for (name, node) in tops {
+        node.write(&mut w, PathBuf::from(name), 0)?;
+    }
+    Ok(())
+}
+
+pub fn print_tree(entries: &[Entry]) -> io::Result<()> {
+    let stdout = io::stdout();
+    write_tree(stdout.lock(), entries)
+}
+
+/// Formats Unix time `secs` in UTC as RFC 3339, `2020-01-01T10:00:00Z`, for
+/// output read by programs.
+pub fn utc_time(secs: i64) -> String {
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The proleptic Gregorian date `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Describes a duration in seconds the way people say it, `3 days ago`.
+fn ago(seconds: f64) -> String {
+    let units = [
+        ("year", 365.0 * 86400.0),
+        ("month", 30.0 * 86400.0),
+        ("day", 86400.0),
+        ("hour", 3600.0),
+        ("minute", 60.0),
+    ];
+    for (unit, length) in units.iter() {
+        let count = (seconds / length).floor() as i64;
+        if count >= 1 {
+            let plural = if count == 1 { "" } else { "s" };
+            return format!("{} {}{} ago", count, unit, plural);
+        }
+    }
+    "just now".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn quotes_for_the_shell() {
+        if cfg!(windows) {
+            assert_eq!(shell_quote(br"C:\My Documents"), br#""C:\My Documents""#);
+            return;
+        }
+        assert_eq!(shell_quote(b"/tmp/a b"), b"'/tmp/a b'");
+        assert_eq!(shell_quote(b"/tmp/it's"), br"'/tmp/it'\''s'");
+        assert_eq!(shell_quote(b"/tmp/a\nb"), b"'/tmp/a\nb'");
+    }
+
+    #[test]
+    fn abbreviates_home() {
+        let home = b"/home/me";
+
+        assert_eq!(abbreviate(b"/home/me/src", home), &b"~/src"[..]);
+        assert_eq!(abbreviate(b"/home/me", home), &b"~"[..]);
+        assert_eq!(abbreviate(b"/home/me\t1.00", home), &b"~\t1.00"[..]);
+        assert_eq!(abbreviate(b"/home/meg/src", home), &b"/home/meg/src"[..]);
+        assert_eq!(abbreviate(b"/tmp", home), &b"/tmp"[..]);
+    }
+
+    #[test]
+    fn writes_json_entries() {
+        let entries = [Entry {
+            location: "/foo/\"bar\"".to_string(),
+            rank: 2.5,
+            last_access: 1577872800,
+            score: 10.0,
+            pinned: true,
+            raw: None,
+        }];
+        let mut output = Vec::new();
+
+        write_entries(&mut output, &entries, Format::Json, |e| e.path().into()).unwrap();
+
+        let value = json::parse(std::str::from_utf8(&output).unwrap()).unwrap();
+        let entry = match value {
+            json::Value::Array(values) => values[0].clone(),
+            _ => panic!("expected array"),
+        };
+        assert_eq!(
+            entry.get("location").and_then(json::Value::as_str),
+            Some("/foo/\"bar\"")
+        );
+        assert_eq!(entry.get("rank").and_then(json::Value::as_f64), Some(2.5));
+        assert_eq!(
+            entry.get("lastAccess").and_then(json::Value::as_str),
+            Some("2020-01-01T10:00:00Z")
+        );
+        assert_eq!(entry.get("pinned"), Some(&json::Value::Bool(true)));
+    }
+
+    #[test]
+    fn writes_history() {
+        let jumps = [Jump {
+            query: "src b".to_string(),
+            location: "/b/src".to_string(),
+            time: 1577872800,
+        }];
+        let mut output = Vec::new();
+
+        write_history(&mut output, &jumps, Format::Tsv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "2020-01-01T10:00:00Z\tsrc b\t/b/src\n"
+        );
+    }
+
+    #[test]
+    fn writes_aligned_table() {
+        let entry = |location: &str, rank, last_access| Entry {
+            location: location.to_string(),
+            rank,
+            last_access,
+            score: rank,
+            pinned: false,
+            raw: None,
+        };
+        let entries = [
+            entry("/foo", 12.0, 1583053200),
+            entry("/foo/bar", 1.5, 1582797600),
+        ];
+        let mut output = Vec::new();
+
+        write_table(&mut output, &entries, 1583058600, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "/foo         12.00  1 hour ago\n/foo/bar      1.50  3 days ago\n"
+        );
+    }
+
+    #[test]
+    fn explains_matches() {
+        let entries = [Entry {
+            location: "/home/me/src".to_string(),
+            rank: 3.0,
+            last_access: 1583053200,
+            score: 24.0,
+            pinned: true,
+            raw: None,
+        }];
+        let mut output = Vec::new();
+
+        write_explanations(&mut output, &entries, &Query::new(&["me", "src"])).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "  1) /home/[me]/[src]\n     score 24.00  rank 3.00  lastAccess 2020-03-01T09:00:00Z  pinned\n"
+        );
+    }
+
+    #[test]
+    fn writes_tree_of_shared_ancestors() {
+        let entry = |location: &str, score| Entry {
+            location: location.to_string(),
+            rank: score,
+            last_access: 1583053200,
+            score,
+            pinned: false,
+            raw: None,
+        };
+        let entries = [
+            entry("/home/me/src/projA", 8.0),
+            entry("/home/me/src/projB", 4.0),
+            entry("/home/me/src/projB/docs", 2.0),
+            entry("/tmp", 1.0),
+        ];
+        let mut output = Vec::new();
+
+        write_tree(&mut output, &entries).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "/  15.00\n  home/me/src  14.00\n    projA  8.00\n    projB  6.00\n      docs  2.00\n  tmp  1.00\n"
+        );
+    }
+
+    #[test]
+    fn describes_ages() {
+        assert_eq!(ago(5.0), "just now");
+        assert_eq!(ago(120.0), "2 minutes ago");
+        assert_eq!(ago(86400.0 * 45.0), "1 month ago");
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(utc_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_time(86401), "1970-01-02T00:00:01Z");
+        assert_eq!(utc_time(1582977600), "2020-02-29T12:00:00Z");
+        assert_eq!(utc_time(-1), "1969-12-31T23:59:59Z");
+    }
+}
@@ -0,0 +1,829 @@
+//! Options for finding locations, used by [`Database::query`](crate::Database::query).
+
+use crate::JumpError;
+use itertools::join;
+use regex::Regex;
+use std::ops::Range;
+use std::time::Duration;
+
+/// How letter case is treated when matching.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Case {
+    /// Case sensitive only when a pattern contains an uppercase letter.
+    #[default]
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl Case {
+    /// Names accepted by [`Case::from_name`].
+    pub const NAMES: [&'static str; 3] = ["smart", "sensitive", "insensitive"];
+
+    pub fn from_name(name: &str) -> Option<Case> {
+        match name {
+            "smart" => Some(Case::Smart),
+            "sensitive" => Some(Case::Sensitive),
+            "insensitive" => Some(Case::Insensitive),
+            _ => None,
+        }
+    }
+}
+
+/// How patterns are matched against locations.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Strategy {
+    /// Patterns are regexes that must appear in order, with z style anchors.
+    #[default]
+    Substring,
+    /// Patterns are literals that must each be a whole path component, in order.
+    Exact,
+    /// The characters of the patterns must appear in order, fzf style, and the
+    /// closeness of the match adjusts the score.
+    Fuzzy,
+    /// Patterns are regexes used as they are, without anchors or separator
+    /// handling, that must match in order.
+    Regex,
+    /// Patterns joined by spaces are a shell style glob that the whole location
+    /// must match, where `*` and `?` stop at separators and `**` does not.
+    Glob,
+}
+
+impl Strategy {
+    /// Names accepted by [`Strategy::from_name`].
+    pub const NAMES: [&'static str; 5] = ["substr", "exact", "fuzzy", "regex", "glob"];
+
+    pub fn from_name(name: &str) -> Option<Strategy> {
+        match name {
+            "substr" => Some(Strategy::Substring),
+            "exact" => Some(Strategy::Exact),
+            "fuzzy" => Some(Strategy::Fuzzy),
+            "regex" => Some(Strategy::Regex),
+            "glob" => Some(Strategy::Glob),
+            _ => None,
+        }
+    }
+}
+
+/// What to match against stored locations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    /// Fragments that must appear in the location in order.
+    pub patterns: Vec<String>,
+    /// Fragments that must not appear in the location, given to [`Query::new`] as
+    /// `!fragment`.
+    pub excluded: Vec<String>,
+    /// Match locations matching any one of the patterns rather than all in order.
+    pub any: bool,
+    /// Only match locations with this tag.
+    pub tag: Option<String>,
+    pub case: Case,
+    pub strategy: Strategy,
+    /// Boost locations below this directory, usually the working directory.
+    pub cwd: Option<String>,
+    /// Skip [`Query::cwd`] itself, as jumping there would go nowhere.
+    pub skip_cwd: bool,
+    /// Skip locations that no longer exist on disk or cannot be entered.
+    pub require_exists: bool,
+    /// Only match locations accessed within this long.
+    pub accessed_within: Option<Duration>,
+    /// Only match locations not accessed for this long.
+    pub accessed_before: Option<Duration>,
+}
+
+impl Query {
+    /// A query for `patterns` with default options, the patterns in Unicode NFC
+    /// like stored locations. Patterns starting with `!` exclude the locations that
+    /// the rest of them would match.
+    pub fn new<I>(patterns: I) -> Query
+    where
+        I: IntoIterator,
+        I::Item: std::fmt::Display,
+    {
+        let mut query = Query::default();
+        for pattern in patterns {
+            let pattern = crate::location::nfc(&pattern.to_string());
+            match pattern.strip_prefix('!') {
+                Some(excluded) if !excluded.is_empty() => query.excluded.push(excluded.to_string()),
+                _ => query.patterns.push(pattern),
+            }
+        }
+        query
+    }
+
+    /// Restricts the query to locations tagged with `tag`.
+    pub fn with_tag<S: Into<String>>(mut self, tag: Option<S>) -> Query {
+        self.tag = tag.map(Into::into);
+        self
+    }
+
+    /// Sets how letter case is treated.
+    pub fn with_case(mut self, case: Case) -> Query {
+        self.case = case;
+        self
+    }
+
+    /// Sets how patterns are matched.
+    pub fn with_strategy(mut self, strategy: Strategy) -> Query {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets whether a location matching any one pattern matches the query.
+    pub fn with_any(mut self, any: bool) -> Query {
+        self.any = any;
+        self
+    }
+
+    /// Sets the directory whose descendants are preferred.
+    pub fn with_cwd<S: Into<String>>(mut self, cwd: Option<S>) -> Query {
+        self.cwd = cwd.map(Into::into);
+        self
+    }
+
+    /// Sets whether [`Query::cwd`] itself is left out of the matches.
+    pub fn with_skip_cwd(mut self, skip_cwd: bool) -> Query {
+        self.skip_cwd = skip_cwd;
+        self
+    }
+
+    /// Sets whether locations missing from disk, or that cannot be entered, are skipped.
+    pub fn with_require_exists(mut self, require_exists: bool) -> Query {
+        self.require_exists = require_exists;
+        self
+    }
+
+    /// Only matches locations last accessed within `age` of now.
+    pub fn with_accessed_within(mut self, age: Option<Duration>) -> Query {
+        self.accessed_within = age;
+        self
+    }
+
+    /// Only matches locations last accessed longer than `age` ago.
+    pub fn with_accessed_before(mut self, age: Option<Duration>) -> Query {
+        self.accessed_before = age;
+        self
+    }
+
+    /// Returns [`Query::accessed_within`] and [`Query::accessed_before`] in days.
+    pub(crate) fn age_window(&self) -> (Option<f64>, Option<f64>) {
+        let days = |age: &Duration| age.as_secs_f64() / 86400.0;
+        (
+            self.accessed_within.as_ref().map(days),
+            self.accessed_before.as_ref().map(days),
+        )
+    }
+
+    /// Returns the prefix shared by descendants of [`Query::cwd`].
+    pub(crate) fn cwd_prefix(&self) -> Option<String> {
+        let cwd = self.cwd.as_ref()?.trim_end_matches(['/', '\\']);
+        Some(format!("{}{}", cwd, std::path::MAIN_SEPARATOR))
+    }
+
+    /// Returns the location to leave out, see [`Query::skip_cwd`].
+    pub(crate) fn skipped_location(&self) -> Option<&str> {
+        self.cwd.as_deref().filter(|_| self.skip_cwd)
+    }
+
+    pub(crate) fn ignores_case(&self) -> bool {
+        match self.case {
+            Case::Smart => !self
+                .patterns
+                .iter()
+                .any(|p| p.chars().any(char::is_uppercase)),
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+        }
+    }
+
+    /// Splits z style anchors from the patterns, a leading `^` on the first pattern
+    /// anchors the start of the location and a trailing `$` on the last its end.
+    /// Separators in patterns match either `/` or `\`, see [`separators`].
+    fn anchored(&self) -> (bool, Vec<String>, bool) {
+        let mut patterns = self.patterns.iter().map(String::as_str).collect::<Vec<_>>();
+        let start = match patterns.first_mut() {
+            Some(first) if first.starts_with('^') => {
+                *first = &first[1..];
+                true
+            }
+            _ => false,
+        };
+        let end = match patterns.last_mut() {
+            Some(last) if last.ends_with('$') && !last.ends_with("\\$") => {
+                *last = &last[..last.len() - 1];
+                true
+            }
+            _ => false,
+        };
+        patterns.retain(|p| !p.is_empty());
+        let patterns = patterns.into_iter().map(separators).collect();
+        (start, patterns, end)
+    }
+
+    /// Returns the regex that a matching location must satisfy.
+    pub(crate) fn regex(&self) -> String {
+        if self.is_any() {
+            return join(
+                self.alternatives().map(|q| format!("(?:{})", q.regex())),
+                "|",
+            );
+        }
+        let flags = if self.ignores_case() { "(?i)" } else { "" };
+        match self.strategy {
+            Strategy::Substring => {
+                let (start, patterns, end) = self.anchored();
+                format!(
+                    "{}{}{}{}",
+                    flags,
+                    if start { "^" } else { ".*" },
+                    join(patterns.iter().map(|p| format!("(?:{})", p)), ".*"),
+                    if end { "$" } else { ".*" }
+                )
+            }
+            Strategy::Exact if self.patterns.is_empty() => flags.to_string(),
+            Strategy::Exact => format!(
+                "{}(^|.*[/\\\\]){}([/\\\\].*)?$",
+                flags,
+                join(
+                    self.patterns.iter().map(|p| regex::escape(p)),
+                    "[/\\\\](.*[/\\\\])?"
+                )
+            ),
+            Strategy::Fuzzy => format!(
+                "{}.*{}.*",
+                flags,
+                join(
+                    self.fuzzy_pattern().chars().map(|c| match c {
+                        '/' | '\\' => SEPARATOR.to_string(),
+                        c => regex::escape(&c.to_string()),
+                    }),
+                    ".*"
+                )
+            ),
+            Strategy::Regex => format!(
+                "{}{}",
+                flags,
+                join(self.patterns.iter().map(|p| format!("(?:{})", p)), ".*")
+            ),
+            Strategy::Glob if self.patterns.is_empty() => flags.to_string(),
+            Strategy::Glob => {
+                let glob = self.patterns.join(" ");
+                // An invalid glob is reported by Query::check before any query runs.
+                let regex =
+                    crate::glob::to_location_regex(&glob).unwrap_or_else(|_| regex::escape(&glob));
+                format!("{}{}", flags, regex)
+            }
+        }
+    }
+
+    /// Returns an error for patterns that cannot be matched with the strategy, a
+    /// glob with an unterminated `[` for one, which [`Query::regex`] cannot report.
+    pub(crate) fn check(&self) -> Result<(), JumpError> {
+        if self.strategy != Strategy::Glob {
+            return Ok(());
+        }
+        let mut globs = self.excluded.clone();
+        if self.is_any() {
+            globs.extend(self.patterns.iter().cloned());
+        } else {
+            globs.push(self.patterns.join(" "));
+        }
+        for glob in &globs {
+            crate::glob::to_location_regex(glob)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a regex matching locations that any excluded fragment would match as
+    /// a pattern, or `None` when nothing is excluded.
+    pub(crate) fn excluded_regex(&self) -> Option<String> {
+        if self.excluded.is_empty() {
+            return None;
+        }
+        let regexes = self
+            .excluded
+            .iter()
+            .map(|excluded| format!("(?:{})", self.single(excluded).regex()));
+        Some(join(regexes, "|"))
+    }
+
+    /// Whether patterns are alternatives, see [`Query::any`].
+    fn is_any(&self) -> bool {
+        self.any && self.patterns.len() > 1
+    }
+
+    /// This query for `pattern` alone, to match it as one of several alternatives.
+    fn single(&self, pattern: &str) -> Query {
+        Query {
+            patterns: vec![pattern.to_string()],
+            excluded: Vec::new(),
+            any: false,
+            ..self.clone()
+        }
+    }
+
+    /// The queries for each pattern alone when they are alternatives.
+    fn alternatives(&self) -> impl Iterator<Item = Query> + '_ {
+        self.patterns.iter().map(move |p| self.single(p))
+    }
+
+    /// Returns a regex matching locations whose final component matches the last
+    /// pattern, or `None` when there is no such boost.
+    pub(crate) fn last_component_regex(&self) -> Option<String> {
+        if self.is_any() {
+            let regexes = self
+                .alternatives()
+                .map(|q| Some(format!("(?:{})", q.last_component_regex()?)))
+                .collect::<Option<Vec<_>>>()?;
+            return Some(regexes.join("|"));
+        }
+        let flags = if self.ignores_case() { "(?i)" } else { "" };
+        match self.strategy {
+            Strategy::Substring => {
+                let (_, patterns, end) = self.anchored();
+                let last = patterns.last()?;
+                let rest = if end { "$" } else { "[^/\\\\]*$" };
+                Some(format!("{}(?:{}){}", flags, last, rest))
+            }
+            Strategy::Exact => {
+                let last = regex::escape(self.patterns.last()?);
+                Some(format!("{}(^|[/\\\\]){}$", flags, last))
+            }
+            Strategy::Fuzzy | Strategy::Regex | Strategy::Glob => None,
+        }
+    }
+
+    /// Returns a `LIKE` pattern, escaped with `\`, that every match satisfies so
+    /// that SQLite can discard most rows before running the regex, or `None`
+    /// when it would not discard anything.
+    pub(crate) fn like_pattern(&self) -> Option<String> {
+        if self.is_any() {
+            return None;
+        }
+        let fragments = match self.strategy {
+            Strategy::Substring => self
+                .anchored_literals()
+                .into_iter()
+                .map(|p| p.unwrap_or_default())
+                .collect(),
+            Strategy::Exact => self.patterns.clone(),
+            Strategy::Fuzzy => self.fuzzy_pattern().chars().map(String::from).collect(),
+            Strategy::Regex | Strategy::Glob => return None,
+        };
+        let mut like = String::from("%");
+        for fragment in fragments {
+            for c in fragment.chars() {
+                match c {
+                    '%' | '_' => {
+                        like.push('\\');
+                        like.push(c);
+                    }
+                    // LIKE only folds ASCII case, and a separator may be either.
+                    c if !c.is_ascii() || c == '/' || c == '\\' => like.push('_'),
+                    c => like.push(c),
+                }
+            }
+            if !like.ends_with('%') || like.ends_with("\\%") {
+                like.push('%');
+            }
+        }
+        if like == "%" {
+            None
+        } else {
+            Some(like)
+        }
+    }
+
+    /// Returns each substring pattern with anchors removed, or `None` for those
+    /// using regex syntax which cannot be expressed with `LIKE`.
+    fn anchored_literals(&self) -> Vec<Option<String>> {
+        let last = self.patterns.len().saturating_sub(1);
+        self.patterns
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let p = if i == 0 { p.trim_start_matches('^') } else { p };
+                let p = if i == last {
+                    p.trim_end_matches('$')
+                } else {
+                    p
+                };
+                if p.contains(|c| "\\.+*?()|[]{}^$".contains(c)) {
+                    None
+                } else {
+                    Some(p.to_string())
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the byte ranges of `location` matched by each pattern in turn, to
+    /// show why it matched. Fuzzy patterns give a range per character and a glob
+    /// the whole location.
+    pub fn match_ranges(&self, location: &str) -> Vec<Range<usize>> {
+        if self.is_any() {
+            return self
+                .alternatives()
+                .map(|q| q.match_ranges(location))
+                .find(|ranges| !ranges.is_empty())
+                .unwrap_or_default();
+        }
+        let flags = if self.ignores_case() { "(?i)" } else { "" };
+        let patterns = match self.strategy {
+            Strategy::Fuzzy => {
+                let pattern = self.fuzzy_pattern();
+                return fuzzy_positions(&pattern, location, self.ignores_case())
+                    .unwrap_or_default();
+            }
+            Strategy::Glob if self.patterns.is_empty() => return Vec::new(),
+            Strategy::Glob => return std::iter::once(0..location.len()).collect(),
+            Strategy::Substring => self.anchored().1,
+            Strategy::Exact => self.patterns.iter().map(|p| regex::escape(p)).collect(),
+            Strategy::Regex => self.patterns.clone(),
+        };
+        // Like fuzzy matching, prefer the latest place each pattern can match.
+        let mut ranges = Vec::new();
+        let mut end = location.len();
+        for pattern in patterns.iter().rev() {
+            let found = Regex::new(&format!("{}{}", flags, pattern))
+                .ok()
+                .and_then(|regex| regex.find_iter(&location[..end]).last());
+            if let Some(found) = found {
+                ranges.push(found.start()..found.end());
+                end = found.start();
+            }
+        }
+        ranges.reverse();
+        ranges
+    }
+
+    /// Returns the characters whose [`fuzzy_score`] measures how well a location
+    /// matches, or `None` when the patterns are not plain text.
+    pub(crate) fn match_pattern(&self) -> Option<String> {
+        if self.is_any() {
+            return None;
+        }
+        match self.strategy {
+            Strategy::Fuzzy => Some(self.fuzzy_pattern()),
+            Strategy::Substring | Strategy::Exact => self
+                .anchored_literals()
+                .into_iter()
+                .collect::<Option<Vec<_>>>()
+                .map(|literals| literals.concat()),
+            Strategy::Regex | Strategy::Glob => None,
+        }
+    }
+
+    /// Returns the characters to find for [`Strategy::Fuzzy`].
+    pub(crate) fn fuzzy_pattern(&self) -> String {
+        self.patterns
+            .concat()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    }
+}
+
+/// Parses an age such as `90s`, `30m`, `12h`, `7d` or `2w`.
+pub fn parse_age(age: &str) -> Result<Duration, JumpError> {
+    let age = age.trim();
+    let split = age
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(age.len());
+    let (count, unit) = age.split_at(split);
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 7.0 * 86400.0,
+        _ => {
+            return Err(parse_error!(
+                "Invalid age {}, expected a number and one of s, m, h, d or w",
+                age
+            ))
+        }
+    };
+    let count = count.parse::<f64>()?;
+    Ok(Duration::from_secs_f64(count * seconds))
+}
+
+/// Regex class matching either path separator.
+const SEPARATOR: &str = "[/\\\\]";
+
+/// Makes `/` in a pattern match either separator. On Windows a `\` before a
+/// letter or digit, or an escaped `\\`, is also a separator rather than a regex
+/// escape, so `src\app` matches as typed.
+fn separators(pattern: &str) -> String {
+    let mut converted = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', _) => converted.push_str(SEPARATOR),
+            ('\\', Some('\\')) if cfg!(windows) => {
+                chars.next();
+                converted.push_str(SEPARATOR);
+            }
+            ('\\', Some(next)) if cfg!(windows) && next.is_alphanumeric() => {
+                converted.push_str(SEPARATOR)
+            }
+            ('\\', None) if cfg!(windows) => converted.push_str(SEPARATOR),
+            ('\\', Some(&next)) => {
+                chars.next();
+                converted.push(c);
+                converted.push(next);
+            }
+            (c, _) => converted.push(c),
+        }
+    }
+    converted
+}
+
+fn is_separator(c: char) -> bool {
+    "/\\-_. ".contains(c)
+}
+
+/// Scores `pattern` as a subsequence of `location`, or `None` when it does not
+/// appear. Matching runs from the end so that later components are preferred, and
+/// the score ranges from 1 for scattered characters towards 3 as more characters
+/// follow a separator or the previous match.
+pub(crate) fn fuzzy_score(pattern: &str, location: &str, ignore_case: bool) -> Option<f64> {
+    let fold = |c: char| match c {
+        '\\' => '/',
+        c if ignore_case => c.to_lowercase().next().unwrap_or(c),
+        c => c,
+    };
+    let pattern = pattern.chars().map(fold).collect::<Vec<_>>();
+    let location = location.chars().map(fold).collect::<Vec<_>>();
+    if pattern.is_empty() {
+        return Some(1.0);
+    }
+
+    let mut remaining = pattern.len();
+    let mut previous = None;
+    let mut bonus = 0.0;
+    for i in (0..location.len()).rev() {
+        if location[i] != pattern[remaining - 1] {
+            continue;
+        }
+        if previous == Some(i + 1) {
+            bonus += 1.0;
+        }
+        if i == 0 || is_separator(location[i - 1]) {
+            bonus += 1.0;
+        }
+        previous = Some(i);
+        remaining -= 1;
+        if remaining == 0 {
+            return Some(1.0 + bonus / pattern.len() as f64);
+        }
+    }
+    None
+}
+
+/// Returns the byte ranges of the characters [`fuzzy_score`] matches, in order.
+fn fuzzy_positions(pattern: &str, location: &str, ignore_case: bool) -> Option<Vec<Range<usize>>> {
+    let fold = |c: char| match c {
+        '\\' => '/',
+        c if ignore_case => c.to_lowercase().next().unwrap_or(c),
+        c => c,
+    };
+    let mut pattern = pattern.chars().map(fold).rev().peekable();
+    let mut ranges = Vec::new();
+    for (i, c) in location.char_indices().rev() {
+        if pattern.peek() == Some(&fold(c)) {
+            pattern.next();
+            ranges.push(i..i + c.len_utf8());
+        }
+    }
+    if pattern.peek().is_some() {
+        return None;
+    }
+    ranges.reverse();
+    Some(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86400));
+        assert_eq!(parse_age("1.5h").unwrap(), Duration::from_secs(5400));
+        assert!(parse_age("7").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3 days").is_err());
+    }
+
+    #[test]
+    fn excludes_negated_patterns() {
+        let query = Query::new(&["api", "!node_modules", "!", "!Test"]);
+        let excluded = Regex::new(&query.excluded_regex().unwrap()).unwrap();
+
+        assert_eq!(query.patterns, ["api", "!"]);
+        assert_eq!(query.excluded, ["node_modules", "Test"]);
+        assert!(excluded.is_match("/src/api/node_modules/x"));
+        assert!(excluded.is_match("/src/Test/api"));
+        assert!(!excluded.is_match("/src/test/api"));
+        assert!(!excluded.is_match("/src/api"));
+        assert_eq!(Query::new(&["api"]).excluded_regex(), None);
+    }
+
+    #[test]
+    fn matches_any_pattern() {
+        let query = Query::new(&["frontend", "web"]);
+        let any = query.clone().with_any(true);
+        let regex = Regex::new(&any.regex()).unwrap();
+        let last = Regex::new(&any.last_component_regex().unwrap()).unwrap();
+
+        assert!(regex.is_match("/src/frontend"));
+        assert!(regex.is_match("/src/web/app"));
+        assert!(!regex.is_match("/src/api"));
+        assert!(last.is_match("/src/web"));
+        assert!(!last.is_match("/src/web/app"));
+        assert_eq!(any.like_pattern(), None);
+        assert_eq!(
+            any.match_ranges("/src/web/app"),
+            [Range { start: 5, end: 8 }]
+        );
+        assert!(!Regex::new(&query.regex()).unwrap().is_match("/src/web"));
+
+        let piped = Regex::new(&Query::new(&["src", "frontend|web"]).regex()).unwrap();
+        assert!(piped.is_match("/src/web"));
+        assert!(!piped.is_match("/web/src"));
+        let last = Query::new(&["frontend|web"])
+            .last_component_regex()
+            .unwrap();
+        assert!(!Regex::new(&last).unwrap().is_match("/frontend/app"));
+    }
+
+    #[test]
+    fn matches_last_component() {
+        let regex = Regex::new(&Query::new(&["src"]).last_component_regex().unwrap()).unwrap();
+
+        assert!(regex.is_match("/home/me/project/src"));
+        assert!(regex.is_match("C:\\project\\src-old"));
+        assert!(!regex.is_match("/home/me/srcball/old"));
+        assert_eq!(Query::default().last_component_regex(), None);
+    }
+
+    #[test]
+    fn matches_exact_components() {
+        let query = Query::new(&["me", "src"]).with_strategy(Strategy::Exact);
+        let regex = Regex::new(&query.regex()).unwrap();
+
+        assert!(regex.is_match("/home/me/project/src"));
+        assert!(regex.is_match("/home/me/src/old"));
+        assert!(!regex.is_match("/home/me/srcball"));
+        assert!(!regex.is_match("/home/meh/src"));
+        assert!(
+            Regex::new(&Query::new(&["a.b"]).with_strategy(Strategy::Exact).regex())
+                .unwrap()
+                .is_match("/x/a.b")
+        );
+    }
+
+    #[test]
+    fn uses_raw_regexes() {
+        let query = Query::new(&[r"^/home/(me|you)/src$"]).with_strategy(Strategy::Regex);
+        let regex = Regex::new(&query.regex()).unwrap();
+
+        assert_eq!(query.regex(), r"(?i)(?:^/home/(me|you)/src$)");
+        assert!(regex.is_match("/home/you/src"));
+        assert!(!regex.is_match("/home/me/src/old"));
+        assert_eq!(query.like_pattern(), None);
+        assert_eq!(query.last_component_regex(), None);
+        let query = Query::new(&["a|b", "c"]).with_strategy(Strategy::Regex);
+        assert!(!Regex::new(&query.regex()).unwrap().is_match("/a"));
+    }
+
+    #[test]
+    fn matches_globs() {
+        let query = Query::new(&["/home/*/src/*/my", "app"]).with_strategy(Strategy::Glob);
+        let regex = Regex::new(&query.regex()).unwrap();
+
+        assert!(regex.is_match("/home/me/src/shop/my app"));
+        assert!(!regex.is_match("/home/me/src/shop/my app/old"));
+        assert!(!regex.is_match("/home/me/src/my app"));
+        assert!(query.check().is_ok());
+
+        let unterminated = Query::new(&["/tmp/[ab"]).with_strategy(Strategy::Glob);
+        assert!(matches!(unterminated.check(), Err(JumpError::Parse(_))));
+        let excluded = Query::new(&["/tmp/*", "![ab"]).with_strategy(Strategy::Glob);
+        assert!(matches!(excluded.check(), Err(JumpError::Parse(_))));
+        assert!(Query::new(&["[ab"]).check().is_ok());
+    }
+
+    #[test]
+    fn finds_match_ranges() {
+        let query = Query::new(&["me", "src"]);
+        assert_eq!(query.match_ranges("/home/me/src"), [6..8, 9..12]);
+        let fuzzy = query.clone().with_strategy(Strategy::Fuzzy);
+        assert_eq!(
+            fuzzy.match_ranges("/home/me/src"),
+            [6..7, 7..8, 9..10, 10..11, 11..12]
+        );
+        let glob = Query::new(&["/home/*"]).with_strategy(Strategy::Glob);
+        assert_eq!(glob.match_ranges("/home/me"), [Range { start: 0, end: 8 }]);
+        assert!(Query::default().match_ranges("/home").is_empty());
+    }
+
+    #[test]
+    fn scores_fuzzy_matches() {
+        let close = fuzzy_score("src", "/home/me/project/src", true).unwrap();
+        let scattered = fuzzy_score("src", "/home/sarah/recipes", true).unwrap();
+
+        assert_eq!(close, 2.0);
+        assert!(scattered < close);
+        assert_eq!(fuzzy_score("crs", "/home/me/src", true), None);
+        assert_eq!(fuzzy_score("SRC", "/home/me/src", false), None);
+        let query = Query::new(&["pr", "src"]).with_strategy(Strategy::Fuzzy);
+        assert!(Regex::new(&query.regex())
+            .unwrap()
+            .is_match("/home/project/src"));
+    }
+
+    #[test]
+    fn matches_either_separator() {
+        let matches = |patterns: &[&str], location: &str| {
+            Regex::new(&Query::new(patterns).regex())
+                .unwrap()
+                .is_match(location)
+        };
+
+        assert!(matches(&["src/app"], r"C:\src\app"));
+        assert!(matches(&["src/app"], "/src/app"));
+        assert!(matches(&[r"src\.app"], "/src.app"));
+        assert!(!matches(&[r"src\.app"], "/srcxapp"));
+        assert_eq!(
+            fuzzy_score("s/a", r"C:\s\a", true),
+            fuzzy_score("s/a", "/s/a", true)
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn matches_windows_separators() {
+        let regex = Regex::new(&Query::new(&[r"src\app"]).regex()).unwrap();
+
+        assert!(regex.is_match(r"C:\src\app"));
+        assert!(regex.is_match("C:/src/app"));
+        assert!(Regex::new(&Query::new(&[r"src\\app"]).regex())
+            .unwrap()
+            .is_match(r"C:\src\app"));
+    }
+
+    #[test]
+    fn builds_like_prefilters() {
+        let like = |patterns: &[&str], strategy| {
+            Query::new(patterns).with_strategy(strategy).like_pattern()
+        };
+
+        assert_eq!(
+            like(&["^/home", "src$"], Strategy::Substring),
+            Some("%_home%src%".to_string())
+        );
+        assert_eq!(
+            like(&["a.*b", "100%_done"], Strategy::Substring),
+            Some("%100\\%\\_done%".to_string())
+        );
+        assert_eq!(like(&["x+"], Strategy::Substring), None);
+        assert_eq!(like(&[], Strategy::Substring), None);
+        assert_eq!(like(&["café"], Strategy::Exact), Some("%caf_%".to_string()));
+        assert_eq!(
+            like(&["ab", "c"], Strategy::Fuzzy),
+            Some("%a%b%c%".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_anchors() {
+        let matches = |patterns: &[&str], location: &str| {
+            Regex::new(&Query::new(patterns).regex())
+                .unwrap()
+                .is_match(location)
+        };
+
+        assert!(matches(&["^/home", "src$"], "/home/me/src"));
+        assert!(!matches(&["^/home", "src$"], "/mnt/home/me/src"));
+        assert!(!matches(&["^/home", "src$"], "/home/me/src/old"));
+        assert!(matches(&["^/home/me/src$"], "/home/me/src"));
+        assert!(matches(&["^/h", "me"], "/home/me"));
+        assert!(matches(&["cost\\$"], "/tmp/cost$/x"));
+    }
+
+    #[test]
+    fn smart_case_follows_patterns() {
+        assert!(Query::new(&["src"]).ignores_case());
+        assert!(!Query::new(&["src", "Docs"]).ignores_case());
+        assert!(Query::new(&["Docs"])
+            .with_case(Case::Insensitive)
+            .ignores_case());
+        assert!(!Query::new(&["src"])
+            .with_case(Case::Sensitive)
+            .ignores_case());
+    }
+}
@@ -0,0 +1,307 @@
+//! `jumpjump serve` answers HTTP requests with JSON, so that launchers and editor
+//! extensions can query the database without starting jumpjump on every keystroke.
+//!
+//! `GET /query?pattern=src&pattern=app` returns the matching entries best first and
+//! `GET /list` every entry, both in the form of `--format json` and both taking an
+//! optional `limit`. `POST /add?path=/abs/path` records a visit. Requests are
+//! answered one at a time and every connection is closed after its response.
+//!
+//! Only requests whose `Host` is localhost, 127.0.0.1 or the listen address and
+//! that carry no `Origin` are answered, so web pages cannot reach the server from
+//! a browser, not even through a name of their own resolving to this machine.
+
+use crate::output::{self, Format};
+use anyhow::{anyhow, Error};
+use jumpjump::{json, Case, Database, Entry, Query};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+/// The address used when `--listen` is not given, only reachable from this machine.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7321";
+
+/// How long a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Headers read before a request is refused as too large.
+const MAX_HEADERS: usize = 100;
+
+/// Bytes of the request line or of a header read before the request is refused.
+const MAX_LINE: u64 = 8192;
+
+pub fn serve(db: &Database, listen: &str, case: Case) -> Result<(), Error> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    serve_on(db, listener, case)
+}
+
+fn serve_on(db: &Database, listener: TcpListener, case: Case) -> Result<(), Error> {
+    let listen = listener.local_addr()?;
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(Error::from)
+            .and_then(|mut stream| handle(db, case, listen, &mut stream));
+        if let Err(err) = result {
+            eprintln!("Request failed: {}", err);
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    db: &Database,
+    case: Case,
+    listen: SocketAddr,
+    stream: &mut TcpStream,
+) -> Result<(), Error> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&*stream);
+    let request_line = match read_line(&mut reader)? {
+        Some(line) => line,
+        None => {
+            return reply(
+                stream,
+                "414 URI Too Long",
+                &error_body("Request line too long"),
+            )
+        }
+    };
+    let mut host = None;
+    let mut origin = false;
+    let mut headers = 0;
+    loop {
+        let header = match read_line(&mut reader)? {
+            Some(header) => header,
+            None => {
+                let body = error_body("Header too long");
+                return reply(stream, "431 Request Header Fields Too Large", &body);
+            }
+        };
+        if header.trim().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Err(anyhow!("Too many headers"));
+        }
+        let mut fields = header.splitn(2, ':');
+        match (fields.next(), fields.next()) {
+            (Some(name), Some(value)) if name.eq_ignore_ascii_case("host") => {
+                host = Some(value.trim().to_string())
+            }
+            (Some(name), Some(_)) if name.eq_ignore_ascii_case("origin") => origin = true,
+            _ => {}
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = if origin {
+        (
+            "403 Forbidden",
+            error_body("Requests from web pages are not allowed"),
+        )
+    } else if !host.is_some_and(|host| allowed_host(&host, listen)) {
+        ("403 Forbidden", error_body("Unexpected host"))
+    } else {
+        match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => respond(db, case, method, target),
+            _ => ("400 Bad Request", error_body("Invalid request line")),
+        }
+    };
+    reply(stream, status, &body)
+}
+
+/// Reads a line of at most `MAX_LINE` bytes, or `None` when it is longer.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE).read_line(&mut line)?;
+    if line.len() as u64 == MAX_LINE && !line.ends_with('\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// Whether `host`, the value of a `Host` header, names localhost, 127.0.0.1 or the
+/// address the server listens on, with or without a port.
+fn allowed_host(host: &str, listen: SocketAddr) -> bool {
+    let name = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    let listen = match listen.ip() {
+        IpAddr::V6(ip) => format!("[{}]", ip),
+        ip => ip.to_string(),
+    };
+    name.eq_ignore_ascii_case("localhost") || name == "127.0.0.1" || name == listen
+}
+
+fn reply(stream: &mut TcpStream, status: &str, body: &str) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// Answers a request for `target` with a status line and a JSON body.
+fn respond(db: &Database, case: Case, method: &str, target: &str) -> (&'static str, String) {
+    let url = match Url::parse("http://localhost").and_then(|base| base.join(target)) {
+        Ok(url) => url,
+        Err(err) => return ("400 Bad Request", error_body(&err.to_string())),
+    };
+    let params = url.query_pairs().into_owned().collect::<Vec<_>>();
+    let param = |name: &str| params.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+    let limit = match param("limit")
+        .map(|limit| limit.parse::<usize>())
+        .transpose()
+    {
+        Ok(limit) => limit,
+        Err(err) => {
+            return (
+                "400 Bad Request",
+                error_body(&format!("Invalid limit: {}", err)),
+            )
+        }
+    };
+
+    let result = match (method, url.path()) {
+        ("GET", "/query") => {
+            let patterns = params
+                .iter()
+                .filter(|(k, _)| k == "pattern")
+                .map(|(_, v)| v);
+            let query = Query::new(patterns)
+                .with_case(case)
+                .with_require_exists(true);
+            crate::matching_entries(db, &query).and_then(|entries| entries_body(entries, limit))
+        }
+        ("GET", "/list") => db
+            .get_entries()
+            .map_err(Error::from)
+            .and_then(|entries| entries_body(entries, limit)),
+        ("POST", "/add") => match param("path") {
+            Some(path) if Path::new(path).is_absolute() => db
+                .add_path(path)
+                .map(|_| format!("{{\"added\": {}}}\n", json::escape(path)))
+                .map_err(Error::from),
+            _ => return ("400 Bad Request", error_body("Expected an absolute path")),
+        },
+        (_, "/query") | (_, "/list") | (_, "/add") => {
+            return ("405 Method Not Allowed", error_body("Method not allowed"))
+        }
+        _ => return ("404 Not Found", error_body("Not found")),
+    };
+    match result {
+        Ok(body) => ("200 OK", body),
+        Err(err) => ("500 Internal Server Error", error_body(&err.to_string())),
+    }
+}
+
+fn entries_body(mut entries: Vec<Entry>, limit: Option<usize>) -> Result<String, Error> {
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    let mut body = Vec::new();
+    output::write_entries(&mut body, &entries, Format::Json, |e| e.path().into())?;
+    Ok(String::from_utf8(body)?)
+}
+
+fn error_body(message: &str) -> String {
+    format!("{{\"error\": {}}}\n", json::escape(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use std::io::Read;
+
+    fn get(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn answers_http_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+            serve_on(&db, listener, Case::Smart).unwrap();
+        });
+        let tmp = std::env::temp_dir();
+        let tmp = tmp.to_str().unwrap();
+
+        let added = get(
+            addr,
+            &format!("POST /add?path={} HTTP/1.1\r\nHost: localhost\r\n\r\n", tmp),
+        );
+        let found = get(
+            addr,
+            &format!(
+                "GET /query?pattern={} HTTP/1.1\r\nHost: {}\r\n\r\n",
+                tmp, addr
+            ),
+        );
+        let listed = get(
+            addr,
+            "GET /list?limit=0 HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+        );
+        let relative = get(
+            addr,
+            "POST /add?path=tmp HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        );
+        let missing = get(addr, "GET /remove HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        assert!(added.starts_with("HTTP/1.1 200 OK\r\n"));
+        let body = found.split("\r\n\r\n").nth(1).unwrap();
+        let entries = match json::parse(body).unwrap() {
+            json::Value::Array(entries) => entries,
+            _ => panic!("expected array"),
+        };
+        assert_eq!(
+            entries[0].get("location").and_then(json::Value::as_str),
+            Some(tmp)
+        );
+        assert!(listed.ends_with("\r\n\r\n[\n]\n"));
+        assert!(relative.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn refuses_requests_from_elsewhere() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+            serve_on(&db, listener, Case::Smart).unwrap();
+        });
+
+        let hostless = get(addr, "GET /list HTTP/1.1\r\n\r\n");
+        let rebound = get(
+            addr,
+            "GET /list HTTP/1.1\r\nHost: evil.example:7321\r\n\r\n",
+        );
+        let origin = get(
+            addr,
+            "POST /add?path=/ HTTP/1.1\r\nHost: localhost\r\nOrigin: http://evil.example\r\n\r\n",
+        );
+        let long = get(addr, &format!("GET /{}", "a".repeat(MAX_LINE as usize - 5)));
+
+        assert!(hostless.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(rebound.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(origin.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(long.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+        assert!(allowed_host("[::1]:7321", "[::1]:7321".parse().unwrap()));
+        assert!(allowed_host("LocalHost", addr));
+        assert!(!allowed_host("127.0.0.1.evil.example", addr));
+    }
+}
@@ -0,0 +1,346 @@
+//! `jumpjump tui` is a full screen browser of the database. Typing searches as
+//! `get` does, the selected directory's contents are previewed beside the matches
+//! and keys pin, tag or forget the selected entry.
+//!
+//! The screen is drawn on stderr so that the chosen location, printed on stdout,
+//! can be captured by a shell function like the output of `get`.
+
+use anyhow::Error;
+use jumpjump::{Case, Database, Entry, Query};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::io::{self, Stderr};
+use std::path::{Path, PathBuf};
+
+const HELP: &str = "enter jump  ^p pin  ^t tag  ^d forget  esc quit";
+
+/// Directory entries read for the preview.
+const PREVIEW_LIMIT: usize = 200;
+
+enum Mode {
+    Search,
+    /// Typing the tag to add to the selected entry.
+    Tag(String),
+}
+
+struct App<'a> {
+    db: &'a Database,
+    case: Case,
+    input: String,
+    entries: Vec<Entry>,
+    list: ListState,
+    mode: Mode,
+    /// The outcome of the last action, shown until the next key.
+    status: Option<String>,
+}
+
+/// Runs the browser until a location is chosen, returned to be printed, or it is
+/// left without one.
+pub fn browse(db: &Database, case: Case) -> Result<Option<PathBuf>, Error> {
+    terminal::enable_raw_mode()?;
+    execute!(io::stderr(), EnterAlternateScreen)?;
+    let result = Terminal::new(CrosstermBackend::new(io::stderr()))
+        .map_err(Error::from)
+        .and_then(|mut terminal| run(&mut terminal, db, case));
+    execute!(io::stderr(), LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+    db: &Database,
+    case: Case,
+) -> Result<Option<PathBuf>, Error> {
+    let mut app = App {
+        db,
+        case,
+        input: String::new(),
+        entries: Vec::new(),
+        list: ListState::default(),
+        mode: Mode::Search,
+        status: None,
+    };
+    app.refresh();
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Release {
+                if let Some(done) = app.handle(key) {
+                    return Ok(done);
+                }
+            }
+        }
+    }
+}
+
+impl App<'_> {
+    fn selected(&self) -> Option<&Entry> {
+        self.list.selected().and_then(|i| self.entries.get(i))
+    }
+
+    /// Queries the database again for the current input, keeping the selection
+    /// on the same location when it still matches.
+    fn refresh(&mut self) {
+        let selected = self.selected().map(|e| e.location.clone());
+        let query = Query::new(self.input.split_whitespace())
+            .with_case(self.case)
+            .with_require_exists(false);
+        match self.db.query(&query) {
+            Ok(entries) => self.entries = entries,
+            // A half typed regex is not worth more than a note.
+            Err(err) => {
+                self.entries.clear();
+                self.status = Some(err.to_string());
+            }
+        }
+        let index = selected
+            .and_then(|location| self.entries.iter().position(|e| e.location == location))
+            .unwrap_or(0);
+        self.list.select(if self.entries.is_empty() {
+            None
+        } else {
+            Some(index)
+        });
+    }
+
+    fn step(&mut self, by: isize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let last = self.entries.len() as isize - 1;
+        let i = self.list.selected().unwrap_or(0) as isize + by;
+        self.list.select(Some(i.clamp(0, last) as usize));
+    }
+
+    /// Handles a key, returning `Some` when the browser should close with the
+    /// chosen location, if any.
+    fn handle(&mut self, key: KeyEvent) -> Option<Option<PathBuf>> {
+        self.status = None;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if let Mode::Tag(tag) = &mut self.mode {
+            match key.code {
+                KeyCode::Enter => {
+                    let tag = std::mem::take(tag);
+                    self.mode = Mode::Search;
+                    self.tag(&tag);
+                }
+                KeyCode::Esc => self.mode = Mode::Search,
+                KeyCode::Backspace => {
+                    tag.pop();
+                }
+                KeyCode::Char(c) if !ctrl && !c.is_whitespace() => tag.push(c),
+                _ => (),
+            }
+            return None;
+        }
+        match key.code {
+            KeyCode::Esc => return Some(None),
+            KeyCode::Char('c') if ctrl => return Some(None),
+            KeyCode::Enter => return self.selected().map(|e| Some(e.path())),
+            KeyCode::Up => self.step(-1),
+            KeyCode::Down => self.step(1),
+            KeyCode::Char('k') if ctrl => self.step(-1),
+            KeyCode::Char('j') | KeyCode::Char('n') if ctrl => self.step(1),
+            KeyCode::PageUp => self.step(-10),
+            KeyCode::PageDown => self.step(10),
+            KeyCode::Char('p') if ctrl => self.toggle_pin(),
+            KeyCode::Char('t') if ctrl && self.selected().is_some() => {
+                self.mode = Mode::Tag(String::new())
+            }
+            KeyCode::Char('d') if ctrl => self.forget(),
+            KeyCode::Char('u') if ctrl => {
+                self.input.clear();
+                self.refresh();
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.input.push(c);
+                self.refresh();
+            }
+            _ => (),
+        }
+        None
+    }
+
+    fn toggle_pin(&mut self) {
+        let (location, pinned) = match self.selected() {
+            Some(e) => (e.location.clone(), e.pinned),
+            None => return,
+        };
+        let result = if pinned {
+            self.db.unpin_location(&location).map(|_| "Unpinned")
+        } else {
+            self.db.pin_location(&location).map(|_| "Pinned")
+        };
+        self.report(result.map(|done| format!("{} {}", done, location)));
+    }
+
+    fn tag(&mut self, tag: &str) {
+        let location = match self.selected() {
+            Some(e) if !tag.is_empty() => e.location.clone(),
+            _ => return,
+        };
+        let result = self.db.tag_location(&location, &[tag]);
+        self.report(result.map(|_| format!("Tagged {} with {}", location, tag)));
+    }
+
+    fn forget(&mut self) {
+        let location = match self.selected() {
+            Some(e) => e.location.clone(),
+            None => return,
+        };
+        let result = self.db.remove_location(&location);
+        self.report(result.map(|_| format!("Forgot {}", location)));
+    }
+
+    fn report(&mut self, result: Result<String, jumpjump::JumpError>) {
+        self.refresh();
+        self.status = Some(match result {
+            Ok(done) => done,
+            Err(err) => err.to_string(),
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(rows[1]);
+
+        let prompt = match &self.mode {
+            Mode::Search => format!("> {}", self.input),
+            Mode::Tag(tag) => format!("tag: {}", tag),
+        };
+        frame.render_widget(Paragraph::new(prompt), rows[0]);
+
+        let items = self
+            .entries
+            .iter()
+            .map(|e| {
+                let pinned = if e.pinned { "  pinned" } else { "" };
+                ListItem::new(format!("{:>8.2}  {}{}", e.score, e.location, pinned))
+            })
+            .collect::<Vec<_>>();
+        let title = format!(" {} matches ", self.entries.len());
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, columns[0], &mut self.list);
+
+        self.draw_preview(frame, columns[1]);
+
+        let footer = self.status.as_deref().unwrap_or(HELP);
+        frame.render_widget(
+            Paragraph::new(footer).style(Style::default().add_modifier(Modifier::DIM)),
+            rows[2],
+        );
+    }
+
+    fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+        let lines = match self.selected() {
+            Some(e) => preview(&e.path()),
+            None => Vec::new(),
+        };
+        let lines = lines.into_iter().map(Line::from).collect::<Vec<_>>();
+        let block = Block::default().borders(Borders::ALL).title(" preview ");
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// The names in the directory at `path`, subdirectories first and marked with a
+/// trailing separator, or why there are none.
+fn preview(path: &Path) -> Vec<String> {
+    let dir = match std::fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(err) => return vec![err.to_string()],
+    };
+    let mut names = dir
+        .filter_map(Result::ok)
+        .take(PREVIEW_LIMIT)
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if is_dir {
+                name.push(std::path::MAIN_SEPARATOR);
+            }
+            (!is_dir, name)
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn press(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> Option<Option<PathBuf>> {
+        app.handle(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn searches_pins_and_forgets() {
+        let db = Database::new(Connection::open_in_memory().unwrap()).unwrap();
+        db.add_location("/foo/bar").unwrap();
+        db.add_location("/foo/doo").unwrap();
+        db.add_location("/foo/doo").unwrap();
+        let mut app = App {
+            db: &db,
+            case: Case::default(),
+            input: String::new(),
+            entries: Vec::new(),
+            list: ListState::default(),
+            mode: Mode::Search,
+            status: None,
+        };
+        app.refresh();
+        assert_eq!(app.entries.len(), 2);
+
+        for c in "bar".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        assert_eq!(app.entries.len(), 1);
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        assert!(db.get_entries().unwrap()[0].pinned);
+
+        press(&mut app, KeyCode::Char('t'), KeyModifiers::CONTROL);
+        for c in "work".chars() {
+            press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(
+            db.get_tags(Some("/foo/bar")).unwrap(),
+            [("/foo/bar".to_string(), "work".to_string())]
+        );
+
+        let chosen = press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        assert_eq!(chosen, Some(Some(PathBuf::from("/foo/bar"))));
+        press(&mut app, KeyCode::Char('d'), KeyModifiers::CONTROL);
+        assert!(app.entries.is_empty());
+        assert_eq!(db.get_locations().unwrap(), ["/foo/doo"]);
+        assert_eq!(
+            press(&mut app, KeyCode::Esc, KeyModifiers::NONE),
+            Some(None)
+        );
+    }
+}